@@ -0,0 +1,52 @@
+// `encrypt_auto` against fixed chunk sizes, across input sizes.
+// Run with `cargo bench -p rc4 --features alloc --bench chunking`.
+
+use rc4::{encrypt_auto, encrypt_with_chunk_size};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const KEY: [u8; 16] = [0x4b, 0x8e, 0x29, 0x87, 0x80, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b];
+const INPUT_SIZES: &[usize] = &[64, 1024, 16 * 1024, 256 * 1024, 4 * 1024 * 1024, 32 * 1024 * 1024];
+const CHUNK_SIZES: &[usize] = &[1024, 4 * 1024, 16 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+// Best of several runs of at least ~50ms each, in ns per byte
+fn measure<F: FnMut() -> Vec<u8>>(len: usize, mut f: F) -> f64 {
+    let iters = (8 * 1024 * 1024 / len).max(1);
+    let mut best = f64::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        let mut elapsed = Duration::ZERO;
+        let mut runs = 0;
+        while elapsed < Duration::from_millis(50) {
+            for _ in 0..iters {
+                black_box(f());
+            }
+            runs += iters;
+            elapsed = start.elapsed();
+        }
+        best = best.min(elapsed.as_nanos() as f64 / (runs * len) as f64);
+    }
+    best
+}
+
+fn main() {
+    println!("{:>10} {:>10} {:>10} {:>10}", "input", "auto", "best fixed", "auto/best");
+    for &len in INPUT_SIZES {
+        let data = vec![0x5a; len];
+        let auto = measure(len, || encrypt_auto(&KEY, black_box(&data)).unwrap());
+
+        let (best_chunk, best) = std::iter::once(len)
+            .chain(CHUNK_SIZES.iter().copied().filter(|&c| c < len))
+            .map(|chunk| (chunk, measure(len, || encrypt_with_chunk_size(&KEY, black_box(&data), chunk).unwrap())))
+            .fold((0, f64::MAX), |best, c| if c.1 < best.1 { c } else { best });
+
+        println!(
+            "{:>10} {:>8.3}ns {:>8.3}ns {:>9.2}x   (best chunk {})",
+            len,
+            auto,
+            best,
+            auto / best,
+            best_chunk
+        );
+    }
+}
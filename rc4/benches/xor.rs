@@ -0,0 +1,115 @@
+// `apply_keystream`'s byte-at-a-time XOR against word-wise restructurings of it, on large buffers.
+// Run with `cargo bench -p rc4 --bench xor`.
+
+use rc4::Rc4;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const KEY: [u8; 16] = [0x4b, 0x8e, 0x29, 0x87, 0x80, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b];
+const INPUT_SIZES: &[usize] = &[4 * 1024, 256 * 1024, 4 * 1024 * 1024, 32 * 1024 * 1024];
+
+// A copy of the cipher, to try other shapes of the XOR step on (the library's state is private)
+struct Candidate {
+    s: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Candidate {
+    fn new(key: &[u8]) -> Self {
+        let mut s = [0u8; 256];
+        for (i, x) in s.iter_mut().enumerate() {
+            *x = i as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+            s.swap(i, j as usize);
+        }
+        Candidate { s, i: 0, j: 0 }
+    }
+
+    fn prga_next(&mut self) -> u8 {
+        self.i = self.i.wrapping_add(1);
+        self.j = self.j.wrapping_add(self.s[self.i as usize]);
+        self.s.swap(self.i as usize, self.j as usize);
+        self.s[(self.s[self.i as usize].wrapping_add(self.s[self.j as usize])) as usize]
+    }
+
+    // Keystream gathered into a u64, XORed over the data 8 bytes at a time
+    fn apply_words(&mut self, data: &mut [u8]) {
+        let mut words = data.chunks_exact_mut(8);
+        for word in &mut words {
+            let mut ks = [0u8; 8];
+            for k in &mut ks {
+                *k = self.prga_next();
+            }
+            let x = u64::from_le_bytes((&*word).try_into().unwrap()) ^ u64::from_le_bytes(ks);
+            word.copy_from_slice(&x.to_le_bytes());
+        }
+        for b in words.into_remainder() {
+            *b ^= self.prga_next();
+        }
+    }
+
+    // Keystream generated into a 64-byte block, then XORed over the data in a loop the autovectorizer can widen
+    fn apply_blocks(&mut self, data: &mut [u8]) {
+        for block in data.chunks_mut(64) {
+            let mut ks = [0u8; 64];
+            for k in &mut ks[..block.len()] {
+                *k = self.prga_next();
+            }
+            for (b, k) in block.iter_mut().zip(ks) {
+                *b ^= k;
+            }
+        }
+    }
+}
+
+// Best of several runs of at least ~50ms each, in MB/s
+fn measure<F: FnMut(&mut [u8])>(data: &mut [u8], mut f: F) -> f64 {
+    let iters = (8 * 1024 * 1024 / data.len()).max(1);
+    let mut best = f64::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        let mut elapsed = Duration::ZERO;
+        let mut runs = 0;
+        while elapsed < Duration::from_millis(50) {
+            for _ in 0..iters {
+                f(black_box(&mut *data));
+            }
+            runs += iters;
+            elapsed = start.elapsed();
+        }
+        best = best.min(elapsed.as_secs_f64() / runs as f64);
+    }
+    data.len() as f64 / best / 1e6
+}
+
+fn main() {
+    // Same output first, or the timings mean nothing. 4099 bytes leaves a tail after the words and blocks
+    let mut expected = vec![0x5a; 4099];
+    Rc4::apply_keystream_static(&KEY, &mut expected).unwrap();
+    for apply in [Candidate::apply_words, Candidate::apply_blocks] {
+        let mut data = vec![0x5a; 4099];
+        apply(&mut Candidate::new(&KEY), &mut data);
+        assert_eq!(data, expected);
+    }
+
+    println!("{:>10} {:>15} {:>19} {:>19}", "input", "byte-at-a-time", "u64 words", "64-byte blocks");
+    for &len in INPUT_SIZES {
+        let mut data = vec![0x5a; len];
+        let bytes = measure(&mut data, |data| Rc4::apply_keystream_static(&KEY, data).unwrap());
+        let words = measure(&mut data, |data| Candidate::new(&KEY).apply_words(data));
+        let blocks = measure(&mut data, |data| Candidate::new(&KEY).apply_blocks(data));
+        println!(
+            "{:>10} {:>10.1} MB/s {:>8.1} MB/s {:>5.2}x {:>8.1} MB/s {:>5.2}x",
+            len,
+            bytes,
+            words,
+            words / bytes,
+            blocks,
+            blocks / bytes
+        );
+    }
+}
@@ -0,0 +1,128 @@
+// ATTACK DEMO: the Fluhrer-Mantin-Shamir (FMS) key recovery attack on RC4 keyed with a public IV prepended to a secret,
+// the construction that broke WEP. On a toy scale: it recovers a short secret from a few thousand chosen weak-IV samples.
+// This exists to show why prepending an IV to the key is broken, never to protect anything.
+//
+// How it works: the KSA's first steps only touch key bytes the attacker knows (the IV, then secret bytes recovered so
+// far), so they can be simulated up to the step that mixes in the next unknown byte K[A]. For some IVs the simulated
+// state is "resolved": S[1] < A and S[1] + S[S[1]] == A. Then the first keystream byte is, with probability about 5%
+// (when the rest of the KSA leaves S[0], S[1] and S[A] alone), the value the KSA swapped into S[A] at step A, which
+// gives K[A] away. Each resolved sample votes for its candidate; the right byte collects ~5% of the votes, every wrong
+// one ~1/256. Recovering byte by byte, a mistake early on derails every later step.
+
+use crate::{Rc4, Rc4Error};
+
+// Length of the IV prepended to the secret, as in WEP
+pub const IV_LEN: usize = 3;
+
+// One observed packet: its public IV, and the first byte of the keystream under `IV || secret`. In WEP the first
+// plaintext byte is the fixed 0xAA of the SNAP header, so this is just the first ciphertext byte XOR 0xAA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub iv: [u8; IV_LEN],
+    pub first_byte: u8,
+}
+
+// Stand-in for sniffed traffic: the sample for IV `(index + 3, 255, x)`, the classic weak IV that most often leaves the
+// state resolved for secret byte `index`. 256 of them (every `x`) per secret byte are usually enough.
+pub fn weak_iv_sample(secret: &[u8], index: u8, x: u8) -> Result<Sample, Rc4Error> {
+    let iv = [index.wrapping_add(IV_LEN as u8), 0xff, x];
+    let mut key = [0u8; 256];
+    let key_len = IV_LEN + secret.len();
+    if key_len > key.len() {
+        return Err(Rc4Error::KeyTooLong(key.len() - IV_LEN));
+    }
+    key[..IV_LEN].copy_from_slice(&iv);
+    key[IV_LEN..key_len].copy_from_slice(secret);
+
+    let mut rc4 = Rc4::new(&key[..key_len])?;
+    Ok(Sample { iv, first_byte: rc4.prga_next() })
+}
+
+// Each resolved sample's vote for the secret byte following `known`, the secret bytes recovered so far
+pub fn vote_next_byte(samples: &[Sample], known: &[u8]) -> [u32; 256] {
+    let mut votes = [0u32; 256];
+    let a = IV_LEN + known.len();
+    if a > 255 {
+        return votes;
+    }
+
+    for sample in samples {
+        // The KSA's first `a` steps, over key bytes known by now
+        let mut s = [0u8; 256];
+        for (i, x) in s.iter_mut().enumerate() {
+            *x = i as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..a {
+            let k = if i < IV_LEN { sample.iv[i] } else { known[i - IV_LEN] };
+            j = j.wrapping_add(s[i]).wrapping_add(k);
+            s.swap(i, j as usize);
+        }
+
+        let s1 = s[1] as usize;
+        if s1 >= a || s[1].wrapping_add(s[s1]) as usize != a {
+            continue;
+        }
+
+        // Step `a` swaps S[j'] into S[a], with j' = j + S[a] + K[a]. Take the first byte as that value, invert S to find
+        // j', and solve for K[a]
+        let inverse = s.iter().position(|&x| x == sample.first_byte).unwrap_or(0) as u8;
+        votes[inverse.wrapping_sub(j).wrapping_sub(s[a]) as usize] += 1;
+    }
+    votes
+}
+
+// Recover a secret of `secret.len()` bytes into `secret`, one byte after the other, each the top vote given the ones
+// before it
+pub fn recover_secret(samples: &[Sample], secret: &mut [u8]) {
+    for index in 0..secret.len().min(256 - IV_LEN) {
+        let votes = vote_next_byte(samples, &secret[..index]);
+        let (best, _) = votes.iter().enumerate().max_by_key(|&(_, &count)| count).unwrap_or((0, &0));
+        secret[index] = best as u8;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every weak IV for each of the secret's bytes
+    fn samples(secret: &[u8]) -> Vec<Sample> {
+        (0..secret.len() as u8)
+            .flat_map(|index| (0..=255).map(move |x| (index, x)))
+            .map(|(index, x)| weak_iv_sample(secret, index, x).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn recovers_a_short_secret_from_weak_ivs() {
+        for secret in [[0x4b, 0x8e, 0x29, 0x87, 0x80], [0x01, 0x02, 0x03, 0x04, 0x05], *b"WEP!!"] {
+            let mut recovered = [0u8; 5];
+            recover_secret(&samples(&secret), &mut recovered);
+            assert_eq!(recovered, secret);
+        }
+    }
+
+    #[test]
+    fn samples_are_the_first_keystream_byte() {
+        let sample = weak_iv_sample(&[0x4b, 0x8e, 0x29, 0x87, 0x80], 0, 7).unwrap();
+        assert_eq!(sample.iv, [3, 0xff, 7]);
+
+        let mut first = [0u8];
+        Rc4::apply_keystream_static(&[3, 0xff, 7, 0x4b, 0x8e, 0x29, 0x87, 0x80], &mut first).unwrap();
+        assert_eq!(sample.first_byte, first[0]);
+    }
+
+    #[test]
+    fn right_byte_stands_out() {
+        let secret = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let samples = samples(&secret);
+        let votes = vote_next_byte(&samples, &secret[..2]);
+        let total: u32 = votes.iter().sum();
+
+        // Well above the 1/256 of any wrong byte
+        assert!(votes[0x29] as f64 > 0.03 * total as f64, "{} of {}", votes[0x29], total);
+        assert_eq!(votes.iter().max(), Some(&votes[0x29]));
+    }
+}
@@ -0,0 +1,96 @@
+// Length-prefixed framing, for sending encrypted messages over a byte stream (a socket, a pipe) whose
+// receiver needs to know where each message ends.
+//
+// Frame layout: ciphertext length (u32, big-endian) | ciphertext.
+// The length prefix is sent in the clear, so message lengths are visible to anyone watching the stream.
+
+use crate::{Rc4, Rc4Error};
+
+// Size of the length prefix in front of every frame
+pub const LEN_PREFIX: usize = 4;
+
+// Frames messages under one continuous keystream: every message picks up the keystream where the previous
+// one left off, so a sender and receiver started with the same key stay in step as long as frames are read
+// back in the order they were written. Use one `Framer` per direction of a connection.
+#[derive(Debug)]
+pub struct Framer {
+    rc4: Rc4,
+}
+
+impl Framer {
+    pub fn new(key: &[u8]) -> Result<Self, Rc4Error> {
+        Ok(Framer { rc4: Rc4::new(key)? })
+    }
+
+    // Encrypt `message` into `out` as one frame, returning the frame's length (`LEN_PREFIX + message.len()`).
+    // Errors if `out` is too small, or the message doesn't fit the length prefix.
+    pub fn frame_message(&mut self, message: &[u8], out: &mut [u8]) -> Result<usize, Rc4Error> {
+        let len = u32::try_from(message.len()).map_err(|_| Rc4Error::FrameTooLong(u32::MAX as usize))?;
+        let frame_len = LEN_PREFIX + message.len();
+        if out.len() < frame_len {
+            return Err(Rc4Error::LengthMismatch(frame_len, out.len()));
+        }
+
+        out[..LEN_PREFIX].copy_from_slice(&len.to_be_bytes());
+        out[LEN_PREFIX..frame_len].copy_from_slice(message);
+        self.rc4.apply_keystream(&mut out[LEN_PREFIX..frame_len]);
+        Ok(frame_len)
+    }
+
+    // Decrypt the frame at the start of `buf` in place, returning its plaintext and how many bytes of `buf`
+    // it took up, or `None` if `buf` doesn't hold a whole frame yet. Bytes arrive from a stream in arbitrary
+    // pieces: on `None`, read more onto the end of `buf` and try again. The keystream only advances once a frame
+    // is complete.
+    pub fn read_framed<'a>(&mut self, buf: &'a mut [u8]) -> Option<(&'a [u8], usize)> {
+        let prefix: [u8; LEN_PREFIX] = buf.get(..LEN_PREFIX)?.try_into().unwrap();
+        let frame_len = LEN_PREFIX.checked_add(u32::from_be_bytes(prefix) as usize)?;
+
+        let payload = buf.get_mut(LEN_PREFIX..frame_len)?;
+        self.rc4.apply_keystream(payload);
+        Some((payload, frame_len))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+
+    #[test]
+    fn two_messages_in_one_buffer() {
+        let mut sender = Framer::new(&KEY).unwrap();
+        let mut stream = [0u8; 64];
+        let first = sender.frame_message(b"hello", &mut stream).unwrap();
+        let second = sender.frame_message(b"framed world", &mut stream[first..]).unwrap();
+        assert_eq!((first, second), (LEN_PREFIX + 5, LEN_PREFIX + 12));
+        assert_eq!(stream[..LEN_PREFIX], [0, 0, 0, 5]);
+
+        let mut receiver = Framer::new(&KEY).unwrap();
+        let (message, used) = receiver.read_framed(&mut stream[..first + second]).unwrap();
+        assert_eq!((message, used), (&b"hello"[..], first));
+        let (message, used) = receiver.read_framed(&mut stream[first..first + second]).unwrap();
+        assert_eq!((message, used), (&b"framed world"[..], second));
+    }
+
+    #[test]
+    fn partial_frames_wait_for_more_data() {
+        let mut stream = [0u8; 32];
+        let len = Framer::new(&KEY).unwrap().frame_message(b"hello", &mut stream).unwrap();
+
+        let mut receiver = Framer::new(&KEY).unwrap();
+        for partial in [0, 2, LEN_PREFIX, len - 1] {
+            assert!(receiver.read_framed(&mut stream[..partial]).is_none());
+        }
+        // Nothing was consumed by the attempts on partial data
+        assert_eq!(receiver.read_framed(&mut stream[..len]).unwrap().0, b"hello");
+    }
+
+    #[test]
+    fn frame_must_fit_the_output() {
+        let mut out = [0u8; 8];
+        let err = Framer::new(&KEY).unwrap().frame_message(b"hello", &mut out).unwrap_err();
+        assert!(matches!(err, Rc4Error::LengthMismatch(9, 8)));
+    }
+}
@@ -0,0 +1,157 @@
+// `std::io` adapters, en/decrypting whatever passes through them under one continuous keystream, so a file or a
+// socket can be streamed a buffer at a time instead of being read into memory whole.
+
+use crate::{Rc4, Rc4Error};
+use std::io::{self, Read, Write};
+
+// Decrypts (or encrypts, it's the same operation) everything read from `inner`. Every read picks up the keystream
+// where the previous one left off, so wrapping it in a `BufReader`, or reading it in pieces of any size, gives the
+// same bytes as decrypting the whole stream in one go.
+#[derive(Debug)]
+pub struct Rc4Reader<R: Read> {
+    inner: R,
+    cipher: Rc4,
+}
+
+impl<R: Read> Rc4Reader<R> {
+    pub fn new(key: &[u8], inner: R) -> Result<Self, Rc4Error> {
+        Ok(Rc4Reader { inner, cipher: Rc4::new(key)? })
+    }
+
+    // Unwrap the inner reader, dropping the cipher and its place in the keystream
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Rc4Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Only the bytes actually read use up keystream, a failed or short read leaves the rest of it for next time
+        let n = self.inner.read(buf)?;
+        self.cipher.apply_keystream(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+// How much of a buffer `Rc4Writer::write` encrypts and hands on per call
+const WRITE_CHUNK: usize = 4096;
+
+// Encrypts (or decrypts) everything written through it into `inner`, under one continuous keystream. A write the
+// inner writer only partly accepts advances the keystream by just the bytes it took, so retrying the rest, as
+// `write_all` does, carries on where the written ciphertext ends.
+#[derive(Debug)]
+pub struct Rc4Writer<W: Write> {
+    inner: W,
+    cipher: Rc4,
+}
+
+impl<W: Write> Rc4Writer<W> {
+    pub fn new(key: &[u8], inner: W) -> Result<Self, Rc4Error> {
+        Ok(Rc4Writer { inner, cipher: Rc4::new(key)? })
+    }
+
+    // Unwrap the inner writer, without flushing it
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Rc4Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The caller's buffer isn't ours to encrypt in place, so it goes through a chunk-sized copy
+        let len = buf.len().min(WRITE_CHUNK);
+        let mut chunk = [0; WRITE_CHUNK];
+        chunk[..len].copy_from_slice(&buf[..len]);
+
+        // Encrypt with a copy of the cipher, and only keep its state if the whole chunk was taken
+        let mut ahead = self.cipher.clone();
+        ahead.apply_keystream(&mut chunk[..len]);
+        let n = self.inner.write(&chunk[..len])?;
+        match n == len {
+            true => self.cipher = ahead,
+            false => self.cipher.skip(n),
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, BufWriter, Cursor};
+
+    const KEY: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+
+    #[test]
+    fn reader_decrypts_across_reads() {
+        let plaintext: Vec<u8> = (0..1000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+        let mut ciphertext = plaintext.clone();
+        Rc4::apply_keystream_static(&KEY, &mut ciphertext).unwrap();
+
+        let mut whole = Vec::new();
+        Rc4Reader::new(&KEY, Cursor::new(&ciphertext)).unwrap().read_to_end(&mut whole).unwrap();
+        assert_eq!(whole, plaintext);
+
+        // Small, uneven reads through a buffer come out the same
+        let mut reader = BufReader::with_capacity(7, Rc4Reader::new(&KEY, Cursor::new(&ciphertext)).unwrap());
+        let (mut pieces, mut buf) = (Vec::new(), [0; 13]);
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => pieces.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(pieces, plaintext);
+
+        assert_eq!(Rc4Reader::new(&[0; 4], Cursor::new(&ciphertext)).unwrap_err(), Rc4Error::KeyTooShort(5));
+    }
+
+    // Takes at most 3 bytes per write, and fails every fourth call as if interrupted by a signal
+    struct Trickle {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(4) {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writer_encrypts_what_the_inner_writer_takes() {
+        let plaintext: Vec<u8> = (0..10_000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+
+        let mut writer = BufWriter::new(Rc4Writer::new(&KEY, Vec::new()).unwrap());
+        for piece in plaintext.chunks(1000) {
+            writer.write_all(piece).unwrap();
+        }
+        let mut ciphertext = writer.into_inner().unwrap().into_inner();
+        assert_ne!(ciphertext, plaintext);
+        Rc4::apply_keystream_static(&KEY, &mut ciphertext).unwrap();
+        assert_eq!(ciphertext, plaintext);
+
+        // Partial and interrupted writes leave the keystream in step with the bytes actually written
+        let mut writer = Rc4Writer::new(&KEY, Trickle { written: Vec::new(), calls: 0 }).unwrap();
+        writer.write_all(&plaintext[..100]).unwrap();
+        writer.flush().unwrap();
+        let mut trickled = writer.into_inner().written;
+        Rc4::apply_keystream_static(&KEY, &mut trickled).unwrap();
+        assert_eq!(trickled, &plaintext[..100]);
+    }
+}
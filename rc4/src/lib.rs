@@ -9,47 +9,433 @@
 //This allows our code to maximize Rust's memory safety guarantees, even if we refactor it or add new features later.
 #![forbid(unsafe_code)]
 
-// `derive` macro only applies to this structure, telling the compiler how to pretty print its contents to a console
-#[derive(Debug)]
+// Helpers returning owned buffers need an allocator, opt-in so the core cipher stays usable without one
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+// `std` only for `io::Error` conversions and the `io` adapters, the cipher itself never needs it (test builds link it anyway)
+#[cfg(all(feature = "std", not(test)))]
+extern crate std;
+
+// Statistical analysis helpers, opt-in since they're only useful for studying RC4's weaknesses
+#[cfg(feature = "research")]
+pub mod research;
+
+// Fluhrer-Mantin-Shamir key recovery, an attack demo (see the module), opt-in and off by default
+#[cfg(feature = "fms")]
+pub mod fms;
+
+// Length-prefixed framing of encrypted messages for stream protocols
+pub mod framing;
+
+// `Read` and `Write` adapters en/decrypting on the fly
+#[cfg(feature = "std")]
+pub mod io;
+
+// `Clone` snapshots the state, say to resume a stream from a checkpoint. Every copy is as good as the key, and is wiped
+// on its own drop with the `zeroize` feature
+#[derive(Clone)]
 pub struct Rc4 {
     s: [u8; 256],
     i: u8,
     j: u8, 
+    // Keystream bytes produced since the key was set, see `position`
+    pos: u64,
+}
+
+// Hand-written rather than derived: the permutation and counters are the whole cipher state, as good as the key, and
+// printing an `Rc4` while debugging must not leak them into a log
+impl core::fmt::Debug for Rc4 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Rc4").field("s", &format_args!("[redacted; 256]")).finish_non_exhaustive()
+    }
 }
 
+// The state is derived from the key and recovers it, so with the `zeroize` feature it's wiped when the cipher is dropped.
+// `zeroize`'s writes are volatile, so they aren't optimized away as dead stores right before the memory is freed.
+#[cfg(feature = "zeroize")]
+impl Drop for Rc4 {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+// RustCrypto's `KeyInit`, `StreamCipher` and `StreamCipherSeek` from the `cipher` crate, so generic code built on those
+// traits can construct and run an `Rc4`. `KeyInit` wants one fixed key size: it's 16 bytes, the common RC4-128, while
+// `new_from_slice` takes any key length `new` does.
+#[cfg(feature = "cipher")]
+impl cipher::KeySizeUser for Rc4 {
+    type KeySize = cipher::typenum::U16;
+}
+
+#[cfg(feature = "cipher")]
+impl cipher::KeyInit for Rc4 {
+    fn new(key: &cipher::Key<Self>) -> Self {
+        Rc4::new(key.as_slice()).expect("16 bytes is a valid key length")
+    }
+
+    fn new_from_slice(key: &[u8]) -> Result<Self, cipher::InvalidLength> {
+        Rc4::new(key).map_err(|_| cipher::InvalidLength)
+    }
+}
+
+// The keystream never runs out, so neither method fails
+#[cfg(feature = "cipher")]
+impl cipher::StreamCipher for Rc4 {
+    fn try_apply_keystream_inout(&mut self, mut buf: cipher::inout::InOutBuf<'_, '_, u8>) -> Result<(), cipher::StreamCipherError> {
+        for i in 0..buf.len() {
+            let mut byte = buf.get(i);
+            *byte.get_out() = byte.clone_in() ^ self.prga_next();
+        }
+        Ok(())
+    }
+
+    // In place is the inherent method's job
+    fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<(), cipher::StreamCipherError> {
+        Rc4::apply_keystream(self, buf);
+        Ok(())
+    }
+}
+
+// The position is `position`'s, and seeking is `skip`: RC4 can only move forward, so seeking to a position already
+// passed is a `StreamCipherError` (a fresh `Rc4`, or a `clone` kept from earlier, is the way back)
+#[cfg(feature = "cipher")]
+impl cipher::StreamCipherSeek for Rc4 {
+    fn try_current_pos<T: cipher::SeekNum>(&self) -> Result<T, cipher::OverflowError> {
+        T::from_block_byte(self.pos, 0, 1)
+    }
+
+    fn try_seek<T: cipher::SeekNum>(&mut self, pos: T) -> Result<(), cipher::StreamCipherError> {
+        let (target, _) = pos.into_block_byte::<u64>(1).map_err(|_| cipher::StreamCipherError)?;
+        let mut remaining = target.checked_sub(self.pos).ok_or(cipher::StreamCipherError)?;
+        while remaining > 0 {
+            let n = usize::try_from(remaining).unwrap_or(usize::MAX);
+            self.skip(n);
+            remaining -= n as u64;
+        }
+        Ok(())
+    }
+}
+
+// The keystream of a cipher as an endless iterator, from `Rc4::keystream`: `.take(n)` pulls n bytes, `.zip(data)` XORs.
+// Borrowing the cipher means the bytes it yields are used up, the next call carries on after them.
 #[derive(Debug)]
+pub struct Keystream<'a>(&'a mut Rc4);
+
+impl Iterator for Keystream<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        Some(self.0.prga_next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+// The keystream as a seeded, reproducible source of random numbers, for generating deterministic test data, through
+// `rand_core`'s `RngCore` and `SeedableRng` (seeded from 32 bytes, used as the key; words are little-endian runs of
+// the keystream). NOT a CSPRNG, so it doesn't implement `CryptoRng`: RC4's keystream is measurably biased (see
+// `research`), never use this for keys, nonces or anything else that has to be unpredictable.
+#[cfg(feature = "rand_core")]
+#[derive(Debug, Clone)]
+pub struct Rc4Rng(Rc4);
+
+#[cfg(feature = "rand_core")]
+impl rand_core::SeedableRng for Rc4Rng {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        Rc4Rng(Rc4::from_array(seed).expect("32 bytes is a valid key length"))
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for Rc4Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.0.fill_keystream(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.0.fill_keystream(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_keystream(dest);
+    }
+
+    // The keystream never runs out
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Any cipher as a generator, e.g. one keyed with fewer than 32 bytes, or from `with_drop` to skip the biased start
+#[cfg(feature = "rand_core")]
+impl From<Rc4> for Rc4Rng {
+    fn from(cipher: Rc4) -> Self {
+        Rc4Rng(cipher)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Rc4Error {
     KeyTooShort(usize),
     KeyTooLong(usize), 
+    // Slices that must be the same length weren't: (expected, found)
+    LengthMismatch(usize, usize),
+    // Message too long for a frame's length prefix: (max length)
+    FrameTooLong(usize),
+    // Hex key text held something other than hex digits, whitespace and `0x` prefixes: (byte offset)
+    InvalidHexDigit(usize),
+    // Hex key text had an odd number of digits, half a byte left over: (digits)
+    OddHexDigits(usize),
+    // `Rc4::self_test` got the wrong keystream, the cipher can't be trusted: (keystream offset of the failed vector)
+    SelfTestFailed(usize),
+    // A chunked API was asked for chunks of 0 bytes
+    ZeroChunkSize,
+}
+
+// `core::fmt`, so even `no_std` callers can print a readable message
+impl core::fmt::Display for Rc4Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Rc4Error::KeyTooShort(min) => write!(f, "RC4 key too short, keys are at least {} bytes", min),
+            Rc4Error::KeyTooLong(max) => write!(f, "RC4 key too long, keys are at most {} bytes", max),
+            Rc4Error::LengthMismatch(expected, found) => {
+                write!(f, "length mismatch, expected {} bytes but found {}", expected, found)
+            }
+            Rc4Error::FrameTooLong(max) => write!(f, "message too long for a frame, at most {} bytes", max),
+            Rc4Error::InvalidHexDigit(offset) => write!(f, "invalid hex digit at byte {} of the key", offset),
+            Rc4Error::OddHexDigits(digits) => write!(f, "odd number of hex digits ({}) in the key", digits),
+            Rc4Error::SelfTestFailed(offset) => write!(f, "RC4 self-test failed, wrong keystream at byte {}", offset),
+            Rc4Error::ZeroChunkSize => write!(f, "chunk size must be non-zero"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Rc4Error {}
+
+// Lets code returning `io::Result` use `?` on the cipher's errors. Every variant but a failed self-test is a bad
+// argument from the caller. The message goes in as a string, which keeps the error's `Debug` output readable too.
+#[cfg(feature = "std")]
+impl From<Rc4Error> for std::io::Error {
+    fn from(e: Rc4Error) -> Self {
+        let kind = match e {
+            Rc4Error::SelfTestFailed(_) => std::io::ErrorKind::Other,
+            _ => std::io::ErrorKind::InvalidInput,
+        };
+        std::io::Error::new(kind, std::string::ToString::to_string(&e))
+    }
+}
+
+
+// Valid key lengths, 40 to 2048 bits
+const MIN_KEY_LEN: usize = 5;
+const MAX_KEY_LEN: usize = 256;
+
+// RFC 6229's test vectors for the 40-bit key 0x0102030405: 16 keystream bytes at each offset, in order
+const IETF_40_BIT_KEY: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+#[rustfmt::skip]
+const IETF_40_BIT_VECTORS: &[(usize, [u8; 16])] = &[
+    (0, [0xb2, 0x39, 0x63, 0x05, 0xf0, 0x3d, 0xc0, 0x27, 0xcc, 0xc3, 0x52, 0x4a, 0x0a, 0x11, 0x18, 0xa8]),
+    (16, [0x69, 0x82, 0x94, 0x4f, 0x18, 0xfc, 0x82, 0xd5, 0x89, 0xc4, 0x03, 0xa4, 0x7a, 0x0d, 0x09, 0x19]),
+    (240, [0x28, 0xcb, 0x11, 0x32, 0xc9, 0x6c, 0xe2, 0x86, 0x42, 0x1d, 0xca, 0xad, 0xb8, 0xb6, 0x9e, 0xae]),
+    (256, [0x1c, 0xfc, 0xf6, 0x2b, 0x03, 0xed, 0xdb, 0x64, 0x1d, 0x77, 0xdf, 0xcf, 0x7f, 0x8d, 0x8c, 0x93]),
+    (496, [0x42, 0xb7, 0xd0, 0xcd, 0xd9, 0x18, 0xa8, 0xa3, 0x3d, 0xd5, 0x17, 0x81, 0xc8, 0x1f, 0x40, 0x41]),
+    (512, [0x64, 0x59, 0x84, 0x44, 0x32, 0xa7, 0xda, 0x92, 0x3c, 0xfb, 0x3e, 0xb4, 0x98, 0x06, 0x61, 0xf6]),
+    (752, [0xec, 0x10, 0x32, 0x7b, 0xde, 0x2b, 0xee, 0xfd, 0x18, 0xf9, 0x27, 0x76, 0x80, 0x45, 0x7e, 0x22]),
+    (768, [0xeb, 0x62, 0x63, 0x8d, 0x4f, 0x0b, 0xa1, 0xfe, 0x9f, 0xca, 0x20, 0xe0, 0x5b, 0xf8, 0xff, 0x2b]),
+    (1008, [0x45, 0x12, 0x90, 0x48, 0xe6, 0xa0, 0xed, 0x0b, 0x56, 0xb4, 0x90, 0x33, 0x8f, 0x07, 0x8d, 0xa5]),
+    (1024, [0x30, 0xab, 0xbc, 0xc7, 0xc2, 0x0b, 0x01, 0x60, 0x9f, 0x23, 0xee, 0x2d, 0x5f, 0x6b, 0xb7, 0xdf]),
+    (1520, [0x32, 0x94, 0xf7, 0x44, 0xd8, 0xf9, 0x79, 0x05, 0x07, 0xe7, 0x0f, 0x62, 0xe5, 0xbb, 0xce, 0xea]),
+    (1536, [0xd8, 0x72, 0x9d, 0xb4, 0x18, 0x82, 0x25, 0x9b, 0xee, 0x4f, 0x82, 0x53, 0x25, 0xf5, 0xa1, 0x30]),
+    (2032, [0x1e, 0xb1, 0x4a, 0x0c, 0x13, 0xb3, 0xbf, 0x47, 0xfa, 0x2a, 0x0b, 0xa9, 0x3a, 0xd4, 0x5b, 0x8b]),
+    (2048, [0xcc, 0x58, 0x2f, 0x8b, 0xa9, 0xf2, 0x65, 0xe2, 0xb1, 0xbe, 0x91, 0x12, 0xe9, 0x75, 0xd2, 0xd7]),
+    (3056, [0xf2, 0xe3, 0x0f, 0x9b, 0xd1, 0x02, 0xec, 0xbf, 0x75, 0xaa, 0xad, 0xe9, 0xbc, 0x35, 0xc4, 0x3c]),
+    (3072, [0xec, 0x0e, 0x11, 0xc4, 0x79, 0xdc, 0x32, 0x9d, 0xc8, 0xda, 0x79, 0x68, 0xfe, 0x96, 0x56, 0x81]),
+    (4080, [0x06, 0x83, 0x26, 0xa2, 0x11, 0x84, 0x16, 0xd2, 0x1f, 0x9d, 0x04, 0xb2, 0xcd, 0x1c, 0xa0, 0x50]),
+    (4096, [0xff, 0x25, 0xb5, 0x89, 0x95, 0x99, 0x67, 0x07, 0xe5, 0x1f, 0xbd, 0xf0, 0x8b, 0x34, 0xd8, 0x75]),
+];
+
+
+// Decode hex text such as "4b8e2987" or "0x4b 0x8e" into `out`, returning the number of bytes written. Whitespace and
+// `0x` prefixes are skipped, everything else has to be a hex digit, and the digits have to pair up into whole bytes.
+// More bytes than `out` holds is `KeyTooLong(out.len())`. Never allocates, so it works on a key read off a UART too.
+pub fn decode_hex(s: &str, out: &mut [u8]) -> Result<usize, Rc4Error> {
+    let text = s.as_bytes();
+    let (mut len, mut digits, mut high) = (0, 0, 0u8);
+    let mut pos = 0;
+    while pos < text.len() {
+        let c = text[pos];
+        let token_start = pos == 0 || text[pos - 1].is_ascii_whitespace();
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if token_start && c == b'0' && text.get(pos + 1) == Some(&b'x') {
+            pos += 2;
+            continue;
+        }
+
+        let value = (c as char).to_digit(16).ok_or(Rc4Error::InvalidHexDigit(pos))? as u8;
+        if digits % 2 == 0 {
+            high = value;
+        } else {
+            if len == out.len() {
+                return Err(Rc4Error::KeyTooLong(out.len()));
+            }
+            out[len] = high << 4 | value;
+            len += 1;
+        }
+        digits += 1;
+        pos += 1;
+    }
+
+    if digits % 2 == 1 {
+        return Err(Rc4Error::OddHexDigits(digits));
+    }
+    Ok(len)
+}
+
+
+// A key in hex text (see `decode_hex`), checked to be a valid RC4 key: the bytes and how many of them there are.
+// Fixed-size, so `no_std` code without an allocator can parse a key, e.g. `let (key, len) = parse_hex_key(s)?;`
+pub fn parse_hex_key(s: &str) -> Result<([u8; MAX_KEY_LEN], usize), Rc4Error> {
+    let mut key = [0u8; MAX_KEY_LEN];
+    let len = decode_hex(s, &mut key)?;
+    if len < MIN_KEY_LEN {
+        return Err(Rc4Error::KeyTooShort(MIN_KEY_LEN));
+    }
+    Ok((key, len))
+}
+
+
+// XOR `b` into `a` in place (a[i] ^= b[i]), the same combining step the cipher applies to its keystream.
+// Handy building block for one-time-pad and keystream-reuse analysis tools. Errors if the lengths differ.
+pub fn xor_slices(a: &mut [u8], b: &[u8]) -> Result<(), Rc4Error> {
+    if a.len() != b.len() {
+        return Err(Rc4Error::LengthMismatch(a.len(), b.len()));
+    }
+
+    for (x, y) in a.iter_mut().zip(b) {
+        *x ^= y;
+    }
+    Ok(())
+}
+
+// Inputs up to this size are encrypted in one go by `encrypt_auto`, larger ones in `AUTO_CHUNK_SIZE` chunks
+#[cfg(feature = "alloc")]
+pub const AUTO_ONE_SHOT_MAX: usize = 64 * 1024;
+#[cfg(feature = "alloc")]
+pub const AUTO_CHUNK_SIZE: usize = 16 * 1024;
+
+// One-shot en/decryption into a new buffer, with the processing chunk size picked from the input length.
+// Copying a large input and then XORing the copy reads it all back from memory a second time, so past
+// `AUTO_ONE_SHOT_MAX` bytes each chunk is XORed right after it's copied, while it's still in cache. `AUTO_CHUNK_SIZE`
+// chunks are small enough to stay in a typical L1/L2 cache and large enough that the per-chunk overhead doesn't show.
+// Smaller inputs fit in cache whole and are processed in one go.
+// Generating the keystream (a few ns per byte) dwarfs the memory traffic, so the choice only matters at the margin:
+// `benches/chunking.rs` has this within ~10% of the best fixed chunk size from 64 bytes to 32 MiB.
+#[cfg(feature = "alloc")]
+pub fn encrypt_auto(key: &[u8], data: &[u8]) -> Result<alloc::vec::Vec<u8>, Rc4Error> {
+    let chunk_size = if data.len() <= AUTO_ONE_SHOT_MAX { data.len().max(1) } else { AUTO_CHUNK_SIZE };
+    encrypt_with_chunk_size(key, data, chunk_size)
+}
+
+// En/decrypt `data` into a new buffer, leaving `data` as it is, for callers who want to keep the plaintext. The same
+// result as the in-place API on a copy, made the way `encrypt_auto` makes it.
+#[cfg(feature = "alloc")]
+pub fn encrypt_to_vec(key: &[u8], data: &[u8]) -> Result<alloc::vec::Vec<u8>, Rc4Error> {
+    encrypt_auto(key, data)
+}
+
+// One-shot en/decryption into a new buffer, copying and XORing `chunk_size` bytes at a time. A `chunk_size` of 0 is
+// refused with `ZeroChunkSize`.
+#[cfg(feature = "alloc")]
+pub fn encrypt_with_chunk_size(key: &[u8], data: &[u8], chunk_size: usize) -> Result<alloc::vec::Vec<u8>, Rc4Error> {
+    if chunk_size == 0 {
+        return Err(Rc4Error::ZeroChunkSize);
+    }
+    let mut rc4 = Rc4::new(key)?;
+    let mut out = alloc::vec::Vec::with_capacity(data.len());
+    for chunk in data.chunks(chunk_size) {
+        let start = out.len();
+        out.extend_from_slice(chunk);
+        rc4.apply_keystream(&mut out[start..]);
+    }
+    Ok(out)
+}
+
+// Lazily en/decrypt `data` in `chunk`-sized pieces (the last one possibly shorter), each produced as the iterator
+// is advanced. All chunks come from one cipher, so their keystream is continuous: concatenated, they equal a one-shot
+// en/decryption. Suits pull-based pipelines that hand results on without allocating the whole output upfront.
+// Panics if `chunk` is 0.
+#[cfg(feature = "alloc")]
+pub fn encrypt_chunks<'a>(
+    key: &[u8],
+    data: &'a [u8],
+    chunk: usize,
+) -> Result<impl Iterator<Item = alloc::vec::Vec<u8>> + 'a, Rc4Error> {
+    assert!(chunk > 0, "chunk size must be non-zero");
+    let mut rc4 = Rc4::new(key)?;
+    Ok(data.chunks(chunk).map(move |piece| {
+        let mut out = piece.to_vec();
+        rc4.apply_keystream(&mut out);
+        out
+    }))
 }
 
 impl Rc4 {
     
-    // Init a new Rc4 stream cipher instance: returns `Result<T, E>`
-    // Choosing the unit type ((), an empty value) instead of a custom error type is a "bare bones" approach. 
-    // One typically better-suited in private, internal APIs. 
-    fn new(key :&[u8]) -> Result<Self, Rc4Error> {
-         
-         // Verify valid key length (40 to 2048 bits)
-         // assert!(5 <= key.len() && key.len() <= 256);
+    // Init a new Rc4 stream cipher instance
+    // A key of the wrong length is an `Err` rather than a panic, which `no_std` firmware can't recover from
+    pub fn new(key :&[u8]) -> Result<Self, Rc4Error> {
          
-         const MIN_KEY_LEN:usize = 5;
-         const MAX_KEY_LEN: usize = 256; 
-         if key.len() < MIN_KEY_LEN {
-            return Err(Rc4Error::KeyTooShort(MIN_KEY_LEN));
-         } else if key.len() > MAX_KEY_LEN {
-            return Err(Rc4Error::KeyTooLong(MAX_KEY_LEN));
-         }  
-
          // Init our struct with default vals
          let mut rc4 = Rc4 {
             s: [0; 256],
             i: 0,
             j: 0, 
+            pos: 0,
          }; 
 
+         rc4.reset(key)?;
+            // Return our initialized Rc4  => Notice no semi-colon
+            Ok(rc4) 
+           }
+
+    // `new` for a key whose length is part of its type, e.g. `Rc4::from_array([0x01, 0x02, 0x03, 0x04, 0x05])?`.
+    // The length is still checked at runtime, the same way for every caller, rather than by a compile-time assertion
+    // that would turn a bad `N` into a build error only where the function happens to be instantiated.
+    pub fn from_array<const N: usize>(key: [u8; N]) -> Result<Self, Rc4Error> {
+        Rc4::new(&key)
+    }
+
+    // Re-key this instance in place, as if it were just created by `new(key)`: the KSA runs on the existing state
+    // array, so one buffer stays hot across many independent messages. A bad key is an `Err`, with the state untouched
+    pub fn reset(&mut self, key :&[u8]) -> Result<(), Rc4Error> {
+         
+         // Verify valid key length (40 to 2048 bits)
+         if key.len() < MIN_KEY_LEN {
+            return Err(Rc4Error::KeyTooShort(MIN_KEY_LEN));
+         } else if key.len() > MAX_KEY_LEN {
+            return Err(Rc4Error::KeyTooLong(MAX_KEY_LEN));
+         }  
+
          // Cipher state identity permutation
-         for (i,b) in rc4.s.iter_mut().enumerate() {
+         // This stays a separate pass: fusing it into the mixing loop below by setting s[i] = i just before use is wrong,
+         // since the swap of an earlier step may already have moved a value into s[i] (or s[j] may not be set yet).
+         // The correct single-pass form has to track which entries are still untouched, which measured ~3x slower
+         // than this pass of 256 plain stores (see `fused_ksa_needs_lazy_init` in the tests).
+         for (i,b) in self.s.iter_mut().enumerate() {
             // s[i] = i 
             *b = i as u8; 
          }   
@@ -61,14 +447,35 @@ impl Rc4 {
             // j = (j + s[i] + key[i % key_len]) % 256
             
             // Wrap around is used here rather than std `+` operator to emulate modular arithmetic accounting for integer overflow  
-            j = j.wrapping_add(rc4.s[i]).wrapping_add(key[i % key.len()]);
+            j = j.wrapping_add(self.s[i]).wrapping_add(key[i % key.len()]);
 
             // Swap values of s[i] and s[j]
-            rc4.s.swap(i, j as usize); 
+            self.s.swap(i, j as usize); 
          }
-            // Return our initialized Rc4  => Notice no semi-colon
-            Ok(rc4) 
-           }
+
+         // The keystream starts over
+         self.i = 0;
+         self.j = 0;
+         self.pos = 0;
+         Ok(())
+    }
+
+    // Zero the state, see `Drop`
+    #[cfg(feature = "zeroize")]
+    fn wipe(&mut self) {
+        use zeroize::Zeroize;
+        self.s.zeroize();
+        self.i.zeroize();
+        self.j.zeroize();
+        self.pos.zeroize();
+    }
+
+    // `new` followed by `skip(drop)`, RC4-drop[n] as it's used in the field, e.g. `Rc4::with_drop(&key, 768)?`
+    pub fn with_drop(key: &[u8], drop: usize) -> Result<Self, Rc4Error> {
+        let mut rc4 = Rc4::new(key)?;
+        rc4.skip(drop);
+        Ok(rc4)
+    }
 
       // `prga_next` is our keystream generation function, it outputs a single keystream byte each time it's called. 
       // Unlike the new associated function, prga_next is a method. Methods always take a reference to self.  
@@ -87,32 +494,159 @@ impl Rc4 {
         // Swap values of s[i] and s[j]
         self.s.swap(self.i as usize, self.j as usize); 
 
+        // Wrapping, since a panic is no way for `no_std` code to fail (not that 2^64 bytes will ever be produced)
+        self.pos = self.pos.wrapping_add(1);
+
         // k = s[(s[i] + s[j]) mod 256]
         self.s[ (self.s[self.i as usize].wrapping_add(self.s[self.j as usize])) as usize] 
       }
 
+    // The next keystream byte, the public face of `prga_next`, for XORing into data that isn't one contiguous slice.
+    // Shares the keystream with `apply_keystream` and friends, so the two can be interleaved freely.
+    pub fn next_byte(&mut self) -> u8 {
+        self.prga_next()
+    }
+
+    // How many keystream bytes have been produced since the key was set (by `new` or `reset`), however they were used:
+    // XORed into data, skipped or pulled raw. A cipher from `with_drop(key, n)` starts at `n`. Lets a caller resuming
+    // a stream check it's at the offset it expects before processing the next chunk.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    // Discard the next `n` keystream bytes, RC4-drop[n]: the start of the keystream is measurably biased, so deployments
+    // that can't avoid RC4 skip it (`skip(768)` or `skip(3072)`) right after `new`. Both sides have to skip the same amount.
+    pub fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.prga_next();
+        }
+    }
+
+    // The keystream as an iterator, see `Keystream`
+    pub fn keystream(&mut self) -> Keystream<'_> {
+        Keystream(self)
+    }
+
     // Stateful, in-place en/decryption (current keystream XORed with data).
-    // Use if plaintext/ciphertext is transmitted in chunks.  
-    fn apply_keystream(&mut self, data: &mut [u8]) {
+    // Use if plaintext/ciphertext is transmitted in chunks: the keystream continues from one call to the next.  
+    // The XOR stays byte by byte: each keystream byte takes a serial swap in `s` first, and that dominates. XORing
+    // word-wise (a u64 of keystream at a time, or a 64-byte keystream block the autovectorizer can widen) measured
+    // 0.77-0.92x and 0.79-1.09x of this loop on 4 KiB to 32 MiB buffers over two runs, see `benches/xor.rs` (which
+    // checks first that both produce byte-identical output). Re-run it before adding a fast path.
+    pub fn apply_keystream(&mut self, data: &mut [u8]) {
         for b_ptr in data {
             // c = k^ p where c => cipher_text, k => key, p => plain_text 
             *b_ptr ^= self.prga_next() 
         }
     }
 
+    // Raw keystream: `out` is overwritten with the next `out.len()` keystream bytes rather than XORed with them.
+    // For a deterministic byte source, or looking at test vectors directly
+    pub fn fill_keystream(&mut self, out: &mut [u8]) {
+        for b_ptr in out {
+            *b_ptr = self.prga_next()
+        }
+    }
+
+
+    // Partial, in-place en/decryption: processes at most the first `n` bytes of `data` and leaves the rest untouched.
+    // Returns how many bytes the keystream actually advanced by, i.e. `min(n, data.len())`.
+    // Use when the sink can only take part of a chunk (e.g. a full ring buffer or a non-blocking socket): 
+    // encrypt only what will be emitted, so the keystream never runs ahead of the bytes truly written.   
+    pub fn apply_keystream_partial(&mut self, data: &mut [u8], n: usize) -> usize {
+        let n = n.min(data.len());
+        self.apply_keystream(&mut data[..n]);
+        n
+    }
+
+
+    // Derive an independent sub-stream from the current cipher state and a domain-separation `tag`,
+    // e.g. `b"client->server"` and `b"server->client"` for the two directions of a connection.
+    // `self` is left untouched: forking doesn't consume any keystream.
+    //
+    // Construction:
+    //  1. Copy the current permutation s, and start j at the current j.
+    //  2. Run the KSA mixing loop on the copy with the tag as the key, also adding the tag length in at every step 
+    //     (so a tag and its repetition, e.g. "ab" and "abab", don't mix identically): 
+    //     j = (j + s[i] + tag[i % tag_len] + tag_len) % 256, swap s[i] and s[j]. An empty tag mixes in zeroes.
+    //  3. Reset i and j to 0 and discard the first 256 keystream bytes, as the weak post-KSA output of RC4-drop.
+    //
+    // Caveats: this is NOT a vetted KDF. The sub-stream is a deterministic function of the parent state, so anyone who 
+    // can recover the parent's state can derive all of its forks. Forks of the same parent at different keystream positions
+    // differ. Tag lengths are only mixed in modulo 256. And all of RC4's own weaknesses apply to every fork.
+    pub fn fork_at(&self, tag: &[u8]) -> Rc4 {
+        let mut fork = Rc4 {
+            s: self.s,
+            i: 0,
+            j: 0, 
+            pos: 0,
+        };
+
+        let tag_len = tag.len() as u8;
+        let mut j: u8 = self.j;
+        for i in 0..256 {
+            let t = if tag.is_empty() { 0 } else { tag[i % tag.len()] };
+            j = j.wrapping_add(fork.s[i]).wrapping_add(t).wrapping_add(tag_len);
+            fork.s.swap(i, j as usize);
+        }
+
+        for _ in 0..256 {
+            fork.prga_next();
+        }
+        // The discarded bytes are part of the construction, the fork's own keystream starts after them
+        fork.pos = 0;
+        fork
+    }
+
+
+    // Power-on self-test: checks the keystream against RFC 6229's vectors for the 40-bit key, 4 KiB of it, so firmware
+    // can refuse to use a cipher that's broken (a bad build, a hardware fault) with `Rc4::self_test().is_ok()`.
+    // Runs in `no_std` without allocating, the keystream is compared 16 bytes at a time as it's produced.
+    pub fn self_test() -> Result<(), Rc4Error> {
+        check_vectors(&IETF_40_BIT_KEY, IETF_40_BIT_VECTORS)
+    }
+
 
+    // One-shot en/decryption of `data` in place. A bad key is an `Err` from `new`, with `data` left untouched
     pub fn apply_keystream_static(key :&[u8], data: &mut[u8]) -> Result<(), Rc4Error> {
         let mut rc4 = Rc4::new(key)?; 
         rc4.apply_keystream(data); 
         Ok(())
     }       
+
+
+    // One-shot en/decryption continuing the keystream at byte `offset`, as if `offset` bytes had already been processed.
+    // Lets a caller pick up a stream where it left off (e.g. appending to an encrypted log) without keeping the cipher
+    // state around: the state is key-equivalent, and unlike the key it can't just be looked up again later. 
+    pub fn apply_keystream_static_at(key :&[u8], offset: u64, data: &mut[u8]) -> Result<(), Rc4Error> {
+        let mut rc4 = Rc4::new(key)?; 
+        for _ in 0..offset {
+            rc4.prga_next();
+        }
+        rc4.apply_keystream(data); 
+        Ok(())
+    }
 }
 
 
+// `self_test` on any key and vectors, which have to be in order of offset and not overlap
+fn check_vectors(key: &[u8], vectors: &[(usize, [u8; 16])]) -> Result<(), Rc4Error> {
+    let mut rc4 = Rc4::new(key)?;
+    let mut block = [0u8; 16];
+    for (offset, expected) in vectors {
+        rc4.skip(*offset - rc4.position() as usize);
+        rc4.fill_keystream(&mut block);
+        if &block != expected {
+            return Err(Rc4Error::SelfTestFailed(*offset));
+        }
+    }
+    Ok(())
+}
+
 
 #[cfg(test)]
 mod tests {
-    use super::Rc4;
+    use super::{decode_hex, parse_hex_key, xor_slices, Rc4, Rc4Error};
 
     #[test]
     fn sanity_check_static_api() {
@@ -130,7 +664,7 @@ mod tests {
             0x72, 0x6c, 0x64, 0x21,
         ]; // "Hello World!"
 
-        let mut msg: [u8; 12] = plaintext.clone(); 
+        let mut msg: [u8; 12] = plaintext; 
 
         println!(
             "Plaintext (initial): {}",
@@ -158,41 +692,556 @@ mod tests {
 
 
     #[test]
-    fn ietf_40_bit_key_official_test_vectors(){
-            let key: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 05]; 
+    fn new_returns_an_error_for_out_of_range_keys() {
+        assert_eq!(Rc4::new(&[0; 4]).unwrap_err(), Rc4Error::KeyTooShort(5));
+        assert_eq!(Rc4::new(&[0; 257]).unwrap_err(), Rc4Error::KeyTooLong(256));
+        assert!(Rc4::new(&[0; 5]).is_ok() && Rc4::new(&[0; 256]).is_ok());
+    }
+
+
+    #[test]
+    fn keystream_continues_across_uneven_chunks() {
+        let key = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let plaintext: [u8; 100] = core::array::from_fn(|n| n as u8);
+
+        let mut one_shot = plaintext;
+        Rc4::apply_keystream_static(&key, &mut one_shot).unwrap();
+
+        let mut chunked = plaintext;
+        let mut cipher = Rc4::new(&key).unwrap();
+        let (first, rest) = chunked.split_at_mut(7);
+        let (second, third) = rest.split_at_mut(61);
+        for chunk in [first, second, third] {
+            cipher.apply_keystream(chunk);
+        }
+        assert_eq!(chunked, one_shot);
+    }
+
+
+    #[test]
+    fn next_byte_yields_the_keystream_apply_keystream_uses() {
+        let key = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut keystream = [0u8; 32];
+        Rc4::apply_keystream_static(&key, &mut keystream).unwrap();
+
+        let mut cipher = Rc4::new(&key).unwrap();
+        let mut interleaved = [0u8; 32];
+        for (n, byte) in interleaved.iter_mut().enumerate() {
+            match n % 3 {
+                0 => *byte = cipher.next_byte(),
+                _ => cipher.apply_keystream(core::slice::from_mut(byte)),
+            }
+        }
+        assert_eq!(interleaved, keystream);
+        // RFC 6229, 40-bit key, offset 0
+        assert_eq!(keystream[..4], [0xb2, 0x39, 0x63, 0x05]);
+    }
 
-            let mut out_buf: [u8; 4112] = [0x00; 4112]; 
 
+    #[test]
+    fn keystream_iterator_matches_the_ietf_vector() {
+        let mut cipher = Rc4::new(&[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
         #[rustfmt::skip]
-        let test_vectors: &[(usize, [u8; 16])] = &[
-            (0, [0xb2, 0x39, 0x63, 0x05, 0xf0, 0x3d, 0xc0, 0x27, 0xcc, 0xc3, 0x52, 0x4a, 0x0a, 0x11, 0x18, 0xa8]),
-            (16, [0x69, 0x82, 0x94, 0x4f, 0x18, 0xfc, 0x82, 0xd5, 0x89, 0xc4, 0x03, 0xa4, 0x7a, 0x0d, 0x09, 0x19]),
-            (240, [0x28, 0xcb, 0x11, 0x32, 0xc9, 0x6c, 0xe2, 0x86, 0x42, 0x1d, 0xca, 0xad, 0xb8, 0xb6, 0x9e, 0xae]),
-            (256, [0x1c, 0xfc, 0xf6, 0x2b, 0x03, 0xed, 0xdb, 0x64, 0x1d, 0x77, 0xdf, 0xcf, 0x7f, 0x8d, 0x8c, 0x93]),
-            (496, [0x42, 0xb7, 0xd0, 0xcd, 0xd9, 0x18, 0xa8, 0xa3, 0x3d, 0xd5, 0x17, 0x81, 0xc8, 0x1f, 0x40, 0x41]),
-            (512, [0x64, 0x59, 0x84, 0x44, 0x32, 0xa7, 0xda, 0x92, 0x3c, 0xfb, 0x3e, 0xb4, 0x98, 0x06, 0x61, 0xf6]),
-            (752, [0xec, 0x10, 0x32, 0x7b, 0xde, 0x2b, 0xee, 0xfd, 0x18, 0xf9, 0x27, 0x76, 0x80, 0x45, 0x7e, 0x22]),
-            (768, [0xeb, 0x62, 0x63, 0x8d, 0x4f, 0x0b, 0xa1, 0xfe, 0x9f, 0xca, 0x20, 0xe0, 0x5b, 0xf8, 0xff, 0x2b]),
-            (1008, [0x45, 0x12, 0x90, 0x48, 0xe6, 0xa0, 0xed, 0x0b, 0x56, 0xb4, 0x90, 0x33, 0x8f, 0x07, 0x8d, 0xa5]),
-            (1024, [0x30, 0xab, 0xbc, 0xc7, 0xc2, 0x0b, 0x01, 0x60, 0x9f, 0x23, 0xee, 0x2d, 0x5f, 0x6b, 0xb7, 0xdf]),
-            (1520, [0x32, 0x94, 0xf7, 0x44, 0xd8, 0xf9, 0x79, 0x05, 0x07, 0xe7, 0x0f, 0x62, 0xe5, 0xbb, 0xce, 0xea]),
-            (1536, [0xd8, 0x72, 0x9d, 0xb4, 0x18, 0x82, 0x25, 0x9b, 0xee, 0x4f, 0x82, 0x53, 0x25, 0xf5, 0xa1, 0x30]),
-            (2032, [0x1e, 0xb1, 0x4a, 0x0c, 0x13, 0xb3, 0xbf, 0x47, 0xfa, 0x2a, 0x0b, 0xa9, 0x3a, 0xd4, 0x5b, 0x8b]),
-            (2048, [0xcc, 0x58, 0x2f, 0x8b, 0xa9, 0xf2, 0x65, 0xe2, 0xb1, 0xbe, 0x91, 0x12, 0xe9, 0x75, 0xd2, 0xd7]),
-            (3056, [0xf2, 0xe3, 0x0f, 0x9b, 0xd1, 0x02, 0xec, 0xbf, 0x75, 0xaa, 0xad, 0xe9, 0xbc, 0x35, 0xc4, 0x3c]),
-            (3072, [0xec, 0x0e, 0x11, 0xc4, 0x79, 0xdc, 0x32, 0x9d, 0xc8, 0xda, 0x79, 0x68, 0xfe, 0x96, 0x56, 0x81]),
-            (4080, [0x06, 0x83, 0x26, 0xa2, 0x11, 0x84, 0x16, 0xd2, 0x1f, 0x9d, 0x04, 0xb2, 0xcd, 0x1c, 0xa0, 0x50]),
-            (4096, [0xff, 0x25, 0xb5, 0x89, 0x95, 0x99, 0x67, 0x07, 0xe5, 0x1f, 0xbd, 0xf0, 0x8b, 0x34, 0xd8, 0x75]),
-        ];
+        let expected = [0xb2, 0x39, 0x63, 0x05, 0xf0, 0x3d, 0xc0, 0x27, 0xcc, 0xc3, 0x52, 0x4a, 0x0a, 0x11, 0x18, 0xa8];
+        assert_eq!(cipher.keystream().take(16).collect::<Vec<_>>(), expected);
+
+        // Carries on where the last one stopped: RFC 6229, offset 16
+        let mut data = [0u8; 4];
+        for (byte, k) in data.iter_mut().zip(cipher.keystream()) {
+            *byte ^= k;
+        }
+        assert_eq!(data, [0x69, 0x82, 0x94, 0x4f]);
+    }
+
+
+    #[test]
+    fn skip_discards_the_start_of_the_keystream() {
+        let mut cipher = Rc4::new(&[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
+        cipher.skip(256);
+        let mut block = [0u8; 16];
+        cipher.apply_keystream(&mut block);
+        // RFC 6229, 40-bit key, offset 256
+        #[rustfmt::skip]
+        let expected = [0x1c, 0xfc, 0xf6, 0x2b, 0x03, 0xed, 0xdb, 0x64, 0x1d, 0x77, 0xdf, 0xcf, 0x7f, 0x8d, 0x8c, 0x93];
+        assert_eq!(block, expected);
+    }
+
+
+    #[test]
+    fn position_counts_keystream_bytes_produced() {
+        let mut cipher = Rc4::new(&[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
+        assert_eq!(cipher.position(), 0);
+        cipher.apply_keystream(&mut [0; 100]);
+        cipher.fill_keystream(&mut [0; 100]);
+        cipher.skip(50);
+        cipher.next_byte();
+        cipher.keystream().take(49).count();
+        assert_eq!(cipher.position(), 300);
+
+        // Clones carry on counting from the same point, and re-keying or forking starts over
+        let mut copy = cipher.clone();
+        copy.skip(1);
+        assert_eq!((cipher.position(), copy.position()), (300, 301));
+        assert_eq!(cipher.fork_at(b"tag").position(), 0);
+        cipher.reset(&[0x05, 0x04, 0x03, 0x02, 0x01]).unwrap();
+        assert_eq!(cipher.position(), 0);
+        assert_eq!(Rc4::with_drop(&[0x01, 0x02, 0x03, 0x04, 0x05], 768).unwrap().position(), 768);
+    }
+
+
+    #[test]
+    fn with_drop_is_new_then_skip() {
+        let key = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let (mut dropped, mut skipped) = (*b"This is a secret", *b"This is a secret");
+
+        Rc4::with_drop(&key, 256).unwrap().apply_keystream(&mut dropped);
+        let mut cipher = Rc4::new(&key).unwrap();
+        cipher.skip(256);
+        cipher.apply_keystream(&mut skipped);
+        assert_eq!(dropped, skipped);
+        assert_eq!(Rc4::with_drop(&[0; 4], 256).unwrap_err(), Rc4Error::KeyTooShort(5));
+    }
+
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn dropping_wipes_the_state() {
+        let mut cipher = Rc4::new(&[0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap();
+        cipher.skip(100);
+        cipher.wipe();
+        assert_eq!((cipher.s, cipher.i, cipher.j), ([0; 256], 0, 0));
+
+        let mut data = *b"This is a secret";
+        let mut cipher = Rc4::new(&[0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap();
+        cipher.apply_keystream(&mut data);
+        drop(cipher);
+    }
+
+
+    #[test]
+    fn debug_output_redacts_the_state() {
+        let cipher = Rc4::new(&[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
+        let debug = format!("{:?}", cipher);
+        assert_eq!(debug, "Rc4 { s: [redacted; 256], .. }");
+        // The state's first entries, as the derived `Debug` printed them
+        assert!(!debug.contains(&format!("{:?}", &cipher.s[..4])));
+        let mut cipher = cipher;
+        assert_eq!(format!("{:?}", cipher.keystream()), "Keystream(Rc4 { s: [redacted; 256], .. })");
+    }
+
+
+    #[test]
+    fn clone_resumes_from_the_same_point() {
+        let mut cipher = Rc4::new(&[0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap();
+        cipher.skip(37);
+        let mut checkpoint = cipher.clone();
+
+        let (mut original, mut resumed) = ([0u8; 64], [0u8; 64]);
+        cipher.apply_keystream(&mut original);
+        checkpoint.apply_keystream(&mut resumed);
+        assert_eq!(original, resumed);
+        assert_eq!(cipher.next_byte(), checkpoint.next_byte());
+    }
+
+
+    #[test]
+    fn reset_matches_a_fresh_cipher() {
+        let (key, other) = ([0x4b, 0x8e, 0x29, 0x87, 0x80], [0x01, 0x02, 0x03, 0x04, 0x05]);
+        let (mut fresh, mut reused) = (*b"This is a secret", *b"This is a secret");
+        Rc4::new(&key).unwrap().apply_keystream(&mut fresh);
+
+        let mut cipher = Rc4::new(&other).unwrap();
+        cipher.skip(123);
+        cipher.reset(&key).unwrap();
+        cipher.apply_keystream(&mut reused);
+        assert_eq!(fresh, reused);
+
+        let before = cipher.clone();
+        assert_eq!(cipher.reset(&[0; 4]), Err(Rc4Error::KeyTooShort(5)));
+        assert_eq!((cipher.s, cipher.i, cipher.j), (before.s, before.i, before.j));
+    }
+
+
+    #[test]
+    fn from_array_is_new_on_the_array() {
+        let (mut from_array, mut from_slice) = (*b"This is a secret", *b"This is a secret");
+        Rc4::from_array([0x01, 0x02, 0x03, 0x04, 0x05]).unwrap().apply_keystream(&mut from_array);
+        Rc4::new(&[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap().apply_keystream(&mut from_slice);
+        assert_eq!(from_array, from_slice);
+
+        assert_eq!(Rc4::from_array([0; 4]).unwrap_err(), Rc4Error::KeyTooShort(5));
+        assert_eq!(Rc4::from_array([0; 257]).unwrap_err(), Rc4Error::KeyTooLong(256));
+        assert!(Rc4::from_array([0; 256]).is_ok());
+    }
+
+
+    #[test]
+    fn parse_hex_key_skips_prefixes_and_whitespace() {
+        for text in ["4b8e298780", "0x4b 0x8e 0x29 0x87 0x80", " 4B8E\n2987 80 "] {
+            let (key, len) = parse_hex_key(text).unwrap();
+            assert_eq!(&key[..len], [0x4b, 0x8e, 0x29, 0x87, 0x80]);
+        }
+        assert_eq!(parse_hex_key(&"ab".repeat(256)).unwrap().1, 256);
+    }
+
+
+    #[test]
+    fn parse_hex_key_rejects_bad_input() {
+        assert_eq!(parse_hex_key("4b8e298780f"), Err(Rc4Error::OddHexDigits(11)));
+        assert_eq!(parse_hex_key("0x4b 0x8g 0x29"), Err(Rc4Error::InvalidHexDigit(8)));
+        assert_eq!(parse_hex_key("4b8x298780"), Err(Rc4Error::InvalidHexDigit(3)));
+        assert_eq!(parse_hex_key("4b8e2987"), Err(Rc4Error::KeyTooShort(5)));
+        assert_eq!(parse_hex_key(&"ab".repeat(257)), Err(Rc4Error::KeyTooLong(256)));
+        assert_eq!(decode_hex("", &mut []), Ok(0));
+    }
+
+
+    #[cfg(feature = "cipher")]
+    #[test]
+    fn key_init_builds_the_same_cipher() {
+        use cipher::KeyInit;
+
+        // As generic code holding only the trait would
+        fn init<C: KeyInit>(key: &[u8]) -> Result<C, cipher::InvalidLength> {
+            C::new_from_slice(key)
+        }
+
+        let (mut generic, mut direct) = (*b"This is a secret", *b"This is a secret");
+        init::<Rc4>(&[0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap().apply_keystream(&mut generic);
+        Rc4::new(&[0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap().apply_keystream(&mut direct);
+        assert_eq!(generic, direct);
+        assert!(init::<Rc4>(&[0; 4]).is_err());
+
+        let key = cipher::Key::<Rc4>::from([7; 16]);
+        assert_eq!(<Rc4 as KeyInit>::new(&key).next_byte(), Rc4::new(&[7; 16]).unwrap().next_byte());
+    }
+
+
+    #[cfg(feature = "cipher")]
+    #[test]
+    fn stream_cipher_traits_match_the_inherent_methods() {
+        use cipher::{StreamCipher, StreamCipherSeek};
+
+        // As generic code holding only the traits would
+        fn encrypt<C: StreamCipher + StreamCipherSeek>(cipher: &mut C, offset: u64, data: &mut [u8]) {
+            cipher.seek(offset);
+            cipher.apply_keystream(&mut data[..5]);
+            let (input, mut output) = (data[5..].to_vec(), [0; 11]);
+            cipher.apply_keystream_b2b(&input, &mut output).unwrap();
+            data[5..].copy_from_slice(&output);
+        }
+
+        let key = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut generic = *b"This is a secret";
+        let mut cipher = Rc4::new(&key).unwrap();
+        encrypt(&mut cipher, 1000, &mut generic);
+        assert_eq!(cipher.current_pos::<u64>(), 1016);
+
+        let mut direct = *b"This is a secret";
+        Rc4::apply_keystream_static_at(&key, 1000, &mut direct).unwrap();
+        assert_eq!(generic, direct);
+
+        // Only forward
+        assert!(cipher.try_seek(1016u64).is_ok());
+        assert!(cipher.try_seek(0u64).is_err());
+        assert_eq!(cipher.current_pos::<u32>(), 1016);
+    }
+
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn rng_is_reproducible_from_its_seed() {
+        use rand_core::{RngCore, SeedableRng};
+
+        let (mut a, mut b) = (super::Rc4Rng::from_seed([0x5a; 32]), super::Rc4Rng::from_seed([0x5a; 32]));
+        let first: Vec<u32> = (0..100).map(|_| a.next_u32()).collect();
+        assert_eq!(first, (0..100).map(|_| b.next_u32()).collect::<Vec<_>>());
+        assert_ne!(first, (0..100).map(|_| super::Rc4Rng::from_seed([0xa5; 32]).next_u32()).collect::<Vec<_>>());
+
+        // Words are little-endian runs of the keystream
+        let mut keystream = [0u8; 12];
+        Rc4::new(&[0x5a; 32]).unwrap().fill_keystream(&mut keystream);
+        let mut rng = super::Rc4Rng::from(Rc4::new(&[0x5a; 32]).unwrap());
+        assert_eq!(rng.next_u32(), u32::from_le_bytes(keystream[..4].try_into().unwrap()));
+        assert_eq!(rng.next_u64(), u64::from_le_bytes(keystream[4..].try_into().unwrap()));
+    }
+
+
+    #[test]
+    fn static_api_returns_key_errors_without_touching_data() {
+        let mut data = *b"This is a secret";
+        assert_eq!(Rc4::apply_keystream_static(&[0; 4], &mut data), Err(Rc4Error::KeyTooShort(5)));
+        assert_eq!(Rc4::apply_keystream_static_at(&[0; 257], 10, &mut data), Err(Rc4Error::KeyTooLong(256)));
+        assert_eq!(&data, b"This is a secret");
+    }
+
+
+    #[test]
+    fn errors_display_a_readable_message() {
+        assert_eq!(Rc4Error::KeyTooShort(5).to_string(), "RC4 key too short, keys are at least 5 bytes");
+        assert_eq!(Rc4Error::LengthMismatch(2, 3).to_string(), "length mismatch, expected 2 bytes but found 3");
+    }
+
 
-        Rc4::apply_keystream_static(&key, &mut out_buf).expect("Failed to create a Rc4 instance");
+    #[test]
+    fn ietf_40_bit_key_official_test_vectors(){
+            // Overwritten by the keystream, not XORed with it, so what it starts out holding doesn't matter
+            let mut out_buf: [u8; 4112] = [0xff; 4112]; 
+
+        Rc4::new(&super::IETF_40_BIT_KEY).expect("Failed to create a Rc4 instance").fill_keystream(&mut out_buf);
 
         // Validate against official test vectors
-        for (offset, expected) in test_vectors {
+        for (offset, expected) in super::IETF_40_BIT_VECTORS {
             assert_eq!(&out_buf[*offset..*offset+16] , expected)
         }
 
         println!("All IETF 40-bit key official test vectors passed!");
     }
 
+
+    #[test]
+    fn self_test_passes_and_catches_a_wrong_vector() {
+        assert_eq!(Rc4::self_test(), Ok(()));
+
+        let mut vectors = super::IETF_40_BIT_VECTORS.to_vec();
+        vectors[3].1[7] ^= 0x01;
+        assert_eq!(super::check_vectors(&super::IETF_40_BIT_KEY, &vectors), Err(Rc4Error::SelfTestFailed(256)));
+    }
+
+
+    #[test]
+    fn fork_at_domain_separation() {
+        let key: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut base = Rc4::new(&key).expect("Failed to create a Rc4 instance");
+
+        let keystream = |mut cipher: Rc4| {
+            let mut out = [0u8; 32];
+            cipher.apply_keystream(&mut out);
+            out
+        };
+
+        // Same tag is reproducible, different tags (including a repeated tag and the empty tag) diverge
+        let tx = keystream(base.fork_at(b"client->server"));
+        assert_eq!(tx, keystream(base.fork_at(b"client->server")));
+        assert_ne!(tx, keystream(base.fork_at(b"server->client")));
+        assert_ne!(keystream(base.fork_at(b"ab")), keystream(base.fork_at(b"abab")));
+        assert_ne!(keystream(base.fork_at(b"")), keystream(base.fork_at(&[0])));
+
+        // Forking doesn't advance the base stream: it still yields the IETF vector at offset 0
+        let mut out = [0u8; 16];
+        base.apply_keystream(&mut out);
+        assert_eq!(out, [0xb2, 0x39, 0x63, 0x05, 0xf0, 0x3d, 0xc0, 0x27, 0xcc, 0xc3, 0x52, 0x4a, 0x0a, 0x11, 0x18, 0xa8]);
+
+        // A fork from later in the base stream is a different sub-stream
+        assert_ne!(tx, keystream(base.fork_at(b"client->server")));
+    }
+
+
+    #[test]
+    fn static_api_at_offset_continues_keystream() {
+        let key: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+
+        // IETF vectors at offsets 240 and 256, in one call starting at 240
+        let mut out = [0u8; 32];
+        Rc4::apply_keystream_static_at(&key, 240, &mut out).expect("Failed to create a Rc4 instance");
+        assert_eq!(out[..16], [0x28, 0xcb, 0x11, 0x32, 0xc9, 0x6c, 0xe2, 0x86, 0x42, 0x1d, 0xca, 0xad, 0xb8, 0xb6, 0x9e, 0xae]);
+        assert_eq!(out[16..], [0x1c, 0xfc, 0xf6, 0x2b, 0x03, 0xed, 0xdb, 0x64, 0x1d, 0x77, 0xdf, 0xcf, 0x7f, 0x8d, 0x8c, 0x93]);
+
+        // Offset 0 is the plain static API
+        let (mut a, mut b) = ([0x42u8; 16], [0x42u8; 16]);
+        Rc4::apply_keystream_static_at(&key, 0, &mut a).expect("Failed to create a Rc4 instance");
+        Rc4::apply_keystream_static(&key, &mut b).expect("Failed to create a Rc4 instance");
+        assert_eq!(a, b);
+    }
+
+
+    // Single-pass KSA candidates, checked against `Rc4::new`'s two-pass one
+    fn naive_fused_ksa(key: &[u8]) -> [u8; 256] {
+        let mut s = [0u8; 256];
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            s[i] = i as u8;
+            j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+            if j as usize > i {
+                s[j as usize] = j;
+            }
+            s.swap(i, j as usize);
+        }
+        s
+    }
+
+    fn lazy_fused_ksa(key: &[u8]) -> [u8; 256] {
+        // `set[k]`: s[k] holds its real value, rather than still waiting for its identity value k
+        let (mut s, mut set) = ([0u8; 256], [false; 256]);
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            if !set[i] {
+                s[i] = i as u8;
+                set[i] = true;
+            }
+            j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+            if !set[j as usize] {
+                s[j as usize] = j;
+                set[j as usize] = true;
+            }
+            s.swap(i, j as usize);
+        }
+        s
+    }
+
+
+    #[test]
+    fn fused_ksa_needs_lazy_init() {
+        let mut naive_differs = false;
+        for n in 0u32..2000 {
+            let mut key = [0u8; 16];
+            key[..4].copy_from_slice(&n.to_le_bytes());
+            let key = &key[..5 + (n as usize % 12)];
+
+            let two_pass = Rc4::new(key).expect("Failed to create a Rc4 instance");
+            assert_eq!(lazy_fused_ksa(key), two_pass.s, "key {:?}", key);
+            assert_eq!((two_pass.i, two_pass.j), (0, 0));
+            naive_differs |= naive_fused_ksa(key) != two_pass.s;
+        }
+
+        // Re-initializing s[i] and s[j] on first sight clobbers values moved there by earlier swaps
+        assert!(naive_differs);
+    }
+
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_auto_matches_one_shot() {
+        let key = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let (one_shot_max, chunk) = (super::AUTO_ONE_SHOT_MAX, super::AUTO_CHUNK_SIZE);
+        for len in [0, 1, 4096, one_shot_max, one_shot_max + 1, one_shot_max + 3 * chunk + 7] {
+            let data: Vec<u8> = (0..len).map(|n| (n * 7) as u8).collect();
+
+            let mut expected = data.clone();
+            Rc4::apply_keystream_static(&key, &mut expected).expect("Failed to create a Rc4 instance");
+            assert_eq!(super::encrypt_auto(&key, &data).expect("Failed to create a Rc4 instance"), expected, "{} bytes", len);
+            assert_eq!(super::encrypt_with_chunk_size(&key, &data, 1000).expect("Failed to create a Rc4 instance"), expected);
+        }
+
+        assert!(super::encrypt_auto(&[0; 4], b"data").is_err());
+        assert_eq!(super::encrypt_with_chunk_size(&key, b"data", 0), Err(Rc4Error::ZeroChunkSize));
+    }
+
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_to_vec_round_trips_and_keeps_the_input() {
+        let key = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let plaintext = b"This is a secret".to_vec();
+
+        let ciphertext = super::encrypt_to_vec(&key, &plaintext).unwrap();
+        assert_ne!(ciphertext, plaintext);
+        assert_eq!(plaintext, b"This is a secret");
+        assert_eq!(super::encrypt_to_vec(&key, &ciphertext).unwrap(), plaintext);
+        assert_eq!(super::encrypt_to_vec(&[0; 4], &plaintext), Err(Rc4Error::KeyTooShort(5)));
+    }
+
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_chunks_is_one_continuous_stream() {
+        let key = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let data: Vec<u8> = (0..1000u32).map(|n| n as u8).collect();
+        let mut expected = data.clone();
+        Rc4::apply_keystream_static(&key, &mut expected).expect("Failed to create a Rc4 instance");
+
+        for chunk in [1, 7, 256, 1000, 4096] {
+            let chunks: Vec<Vec<u8>> = super::encrypt_chunks(&key, &data, chunk).expect("Failed to create a Rc4 instance").collect();
+            assert_eq!(chunks.len(), data.len().div_ceil(chunk));
+            assert!(chunks[..chunks.len() - 1].iter().all(|c| c.len() == chunk));
+
+            // Each chunk picks up the keystream where the previous one stopped
+            for (n, c) in chunks.iter().enumerate() {
+                assert_eq!(c[..], expected[n * chunk..n * chunk + c.len()], "chunk {} of size {}", n, chunk);
+            }
+            assert_eq!(chunks.concat(), expected);
+        }
+
+        assert_eq!(super::encrypt_chunks(&key, &[], 16).expect("Failed to create a Rc4 instance").count(), 0);
+        assert!(super::encrypt_chunks(&[0; 4], &data, 16).is_err());
+    }
+
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn rc4_errors_convert_to_io_errors() {
+        use std::io;
+
+        fn encrypt(key: &[u8]) -> io::Result<()> {
+            Rc4::apply_keystream_static(key, &mut [0; 4])?;
+            Ok(())
+        }
+
+        let short = encrypt(&[0; 4]).unwrap_err();
+        assert_eq!(short.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(short.to_string(), "RC4 key too short, keys are at least 5 bytes");
+
+        let long = io::Error::from(Rc4::apply_keystream_static(&[0; 257], &mut []).unwrap_err());
+        assert_eq!(long.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(long.to_string(), "RC4 key too long, keys are at most 256 bytes");
+
+        let mismatch = io::Error::from(xor_slices(&mut [0; 2], &[0; 3]).unwrap_err());
+        assert_eq!(mismatch.to_string(), "length mismatch, expected 2 bytes but found 3");
+        assert!(encrypt(&[0; 5]).is_ok());
+    }
+
+
+    #[test]
+    fn xor_slices_equal_and_mismatched_lengths() {
+        let mut a = [0b1010_1010, 0xff, 0x00];
+        xor_slices(&mut a, &[0b0110_0110, 0xff, 0x5a]).expect("Equal-length XOR failed");
+        assert_eq!(a, [0b1100_1100, 0x00, 0x5a]);
+
+        // XOR is its own inverse
+        xor_slices(&mut a, &[0b0110_0110, 0xff, 0x5a]).expect("Equal-length XOR failed");
+        assert_eq!(a, [0b1010_1010, 0xff, 0x00]);
+
+        let mut empty: [u8; 0] = [];
+        assert!(xor_slices(&mut empty, &[]).is_ok());
+
+        // Mismatched lengths are rejected and leave the destination untouched
+        assert!(matches!(xor_slices(&mut a, &[0x01, 0x02]), Err(Rc4Error::LengthMismatch(3, 2))));
+        assert_eq!(a, [0b1010_1010, 0xff, 0x00]);
+    }
+
+
+    #[test]
+    fn partial_keystream_with_partial_writes() {
+        let key: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let plaintext = *b"Partially flushed chunks stay aligned with the keystream";
+
+        // A sink that only has room for a few bytes per flush
+        let mut sink: Vec<u8> = Vec::new();
+        let capacity = [3, 0, 7, 1, 16];
+
+        let mut cipher = Rc4::new(&key).expect("Failed to create a Rc4 instance");
+        let mut pending = plaintext.to_vec();
+        for room in capacity.iter().cycle() {
+            if pending.is_empty() {
+                break;
+            }
+
+            // Only encrypt what the sink can accept right now, carry the rest over unencrypted
+            let advanced = cipher.apply_keystream_partial(&mut pending, *room);
+            assert_eq!(advanced, (*room).min(pending.len()));
+            sink.extend(pending.drain(..advanced));
+        }
+
+        // Asking for more than is left only advances by what's there
+        assert_eq!(cipher.apply_keystream_partial(&mut [0; 2], 10), 2);
+
+        // The emitted ciphertext is exactly the one-shot ciphertext
+        let mut expected = plaintext;
+        Rc4::apply_keystream_static(&key, &mut expected).expect("Encryption failed");
+        assert_eq!(sink, expected);
+
+        Rc4::apply_keystream_static(&key, &mut sink).expect("Decryption failed");
+        assert_eq!(sink, plaintext);
+    }
+
 }
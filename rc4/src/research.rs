@@ -0,0 +1,85 @@
+// Research helpers for studying RC4's known statistical weaknesses.
+// These exist to demonstrate why RC4 is broken, not to make it any safer to use.
+
+use crate::{Rc4, Rc4Error};
+
+// Bytes appended to the base key to tell samples apart, see `position_histogram`
+pub const SAMPLE_COUNTER_LEN: usize = 4;
+
+// Histogram of the keystream byte at `position` (0-based, so 1 is the famously biased second byte)
+// across `samples` related keys: sample `n` is keyed with `key || n`, `n` as a little-endian u32.
+// This mirrors how RC4 was used in practice, e.g. WEP's per-packet IV prepended to a fixed key.
+pub fn position_histogram(key: &[u8], samples: u32, position: usize) -> Result<[u64; 256], Rc4Error> {
+    let mut histogram = [0u64; 256];
+
+    let key_len = key.len() + SAMPLE_COUNTER_LEN;
+    let mut sample_key = [0u8; 256];
+    if key_len > sample_key.len() {
+        return Err(Rc4Error::KeyTooLong(sample_key.len() - SAMPLE_COUNTER_LEN));
+    }
+    sample_key[..key.len()].copy_from_slice(key);
+
+    for n in 0..samples {
+        sample_key[key.len()..key_len].copy_from_slice(&n.to_le_bytes());
+        let mut rc4 = Rc4::new(&sample_key[..key_len])?;
+
+        for _ in 0..position {
+            rc4.prga_next();
+        }
+        histogram[rc4.prga_next() as usize] += 1;
+    }
+
+    Ok(histogram)
+}
+
+// Pearson's chi-square statistic of a histogram against the uniform distribution.
+// With 255 degrees of freedom, a uniform byte scores around 255; above ~330 is significant at p = 0.001.
+pub fn chi_square(histogram: &[u64; 256]) -> f64 {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let expected = total as f64 / 256.0;
+    histogram
+        .iter()
+        .map(|&observed| {
+            let diff = observed as f64 - expected;
+            diff * diff / expected
+        })
+        .sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_counts_every_sample() {
+        let histogram = position_histogram(&[0x01, 0x02, 0x03], 1000, 0).unwrap();
+        assert_eq!(histogram.iter().sum::<u64>(), 1000);
+    }
+
+    #[test]
+    fn second_byte_is_biased_towards_zero() {
+        // Mantin-Shamir: the second keystream byte is 0 with probability ~2/256, twice the uniform rate
+        let samples = 1 << 16;
+        let histogram = position_histogram(&[0x4b, 0x8e, 0x29, 0x87], samples, 1).unwrap();
+
+        assert!(histogram[0] as f64 > 1.5 * samples as f64 / 256.0);
+        assert!(chi_square(&histogram) > 330.0);
+    }
+
+    #[test]
+    fn chi_square_of_uniform_is_zero() {
+        assert_eq!(chi_square(&[10; 256]), 0.0);
+        assert_eq!(chi_square(&[0; 256]), 0.0);
+    }
+
+    #[test]
+    fn key_too_long_for_counter() {
+        assert!(position_histogram(&[0; 253], 1, 0).is_err());
+        assert!(position_histogram(&[0; 252], 1, 0).is_ok());
+    }
+}
@@ -0,0 +1,249 @@
+// Guards the crate's public API: adding, removing or changing a public item should be a deliberate
+// change to this file, not a side effect. Keep it in sync with the API list in the README.
+
+use rc4::framing::Framer;
+use rc4::{Rc4, Rc4Error};
+
+
+// Every `pub` declaration in the crate's sources, as `pub <kind> <name>` (`pub field <name>` for struct fields).
+// `pub(crate)` and narrower items are private and aren't listed.
+const PUBLIC_ITEMS: &[(&str, &[&str])] = &[
+    (
+        "lib.rs",
+        &[
+            "pub mod research",
+            "pub mod fms",
+            "pub mod framing",
+            "pub mod io",
+            "pub struct Rc4",
+            "pub struct Keystream",
+            "pub struct Rc4Rng",
+            "pub enum Rc4Error",
+            "pub fn decode_hex",
+            "pub fn parse_hex_key",
+            "pub fn xor_slices",
+            "pub const AUTO_ONE_SHOT_MAX",
+            "pub const AUTO_CHUNK_SIZE",
+            "pub fn encrypt_auto",
+            "pub fn encrypt_to_vec",
+            "pub fn encrypt_with_chunk_size",
+            "pub fn encrypt_chunks",
+            "pub fn new",
+            "pub fn from_array",
+            "pub fn reset",
+            "pub fn with_drop",
+            "pub fn next_byte",
+            "pub fn position",
+            "pub fn skip",
+            "pub fn keystream",
+            "pub fn apply_keystream",
+            "pub fn fill_keystream",
+            "pub fn apply_keystream_partial",
+            "pub fn fork_at",
+            "pub fn self_test",
+            "pub fn apply_keystream_static",
+            "pub fn apply_keystream_static_at",
+        ],
+    ),
+    (
+        "framing.rs",
+        &[
+            "pub const LEN_PREFIX",
+            "pub struct Framer",
+            "pub fn new",
+            "pub fn frame_message",
+            "pub fn read_framed",
+        ],
+    ),
+    (
+        "research.rs",
+        &[
+            "pub const SAMPLE_COUNTER_LEN",
+            "pub fn position_histogram",
+            "pub fn chi_square",
+        ],
+    ),
+    (
+        "fms.rs",
+        &[
+            "pub const IV_LEN",
+            "pub struct Sample",
+            "pub field iv",
+            "pub field first_byte",
+            "pub fn weak_iv_sample",
+            "pub fn vote_next_byte",
+            "pub fn recover_secret",
+        ],
+    ),
+    (
+        "io.rs",
+        &[
+            "pub struct Rc4Reader",
+            "pub fn new",
+            "pub fn into_inner",
+            "pub struct Rc4Writer",
+            "pub fn new",
+            "pub fn into_inner",
+        ],
+    ),
+];
+
+const SOURCES: &[(&str, &str)] = &[
+    ("lib.rs", include_str!("../src/lib.rs")),
+    ("framing.rs", include_str!("../src/framing.rs")),
+    ("research.rs", include_str!("../src/research.rs")),
+    ("fms.rs", include_str!("../src/fms.rs")),
+    ("io.rs", include_str!("../src/io.rs")),
+];
+
+
+// `pub <kind> <name>` of each public declaration in `source`, in order
+fn public_items(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| line.starts_with("pub "))
+        .map(|line| {
+            let mut words = line.split_whitespace();
+            let (kind, name) = match words.nth(1).unwrap_or("") {
+                // A public struct field, `pub name: Type`
+                field if field.ends_with(':') => ("field", field),
+                kind => (kind, words.next().unwrap_or("")),
+            };
+            let name = name.split(|c: char| !(c.is_alphanumeric() || c == '_')).next().unwrap();
+            format!("pub {} {}", kind, name)
+        })
+        .collect()
+}
+
+
+#[test]
+fn public_items_are_exactly_the_intended_ones() {
+    for (file, source) in SOURCES {
+        let expected = PUBLIC_ITEMS.iter().find(|(f, _)| f == file).map(|(_, items)| *items).unwrap();
+        assert_eq!(public_items(source), expected, "public items of src/{} changed", file);
+    }
+}
+
+
+// Spelling the signatures out in full is the point here
+#[allow(clippy::type_complexity)]
+#[test]
+fn public_signatures_are_unchanged() {
+    // Coercing to fn pointers fails to compile if a signature changes
+    let _: fn(&str, &mut [u8]) -> Result<usize, Rc4Error> = rc4::decode_hex;
+    let _: fn(&str) -> Result<([u8; 256], usize), Rc4Error> = rc4::parse_hex_key;
+    let _: fn(&mut [u8], &[u8]) -> Result<(), Rc4Error> = rc4::xor_slices;
+    let _: fn(&[u8]) -> Result<Rc4, Rc4Error> = Rc4::new;
+    let _: fn([u8; 16]) -> Result<Rc4, Rc4Error> = Rc4::from_array::<16>;
+    let _: fn(&mut Rc4, &[u8]) -> Result<(), Rc4Error> = Rc4::reset;
+    let _: fn(&[u8], usize) -> Result<Rc4, Rc4Error> = Rc4::with_drop;
+    let _: fn(&mut Rc4) -> u8 = Rc4::next_byte;
+    let _: fn(&Rc4) -> u64 = Rc4::position;
+    let _: fn(&mut Rc4, usize) = Rc4::skip;
+    let _: fn(&mut Rc4) -> rc4::Keystream<'_> = Rc4::keystream;
+    let _: Vec<u8> = Rc4::new(&[0; 5]).unwrap().keystream().take(3).collect();
+    let _: fn(&mut Rc4, &mut [u8]) = Rc4::apply_keystream;
+    let _: fn(&mut Rc4, &mut [u8]) = Rc4::fill_keystream;
+    let _: fn(&mut Rc4, &mut [u8], usize) -> usize = Rc4::apply_keystream_partial;
+    let _: fn(&Rc4, &[u8]) -> Rc4 = Rc4::fork_at;
+    let _: fn() -> Result<(), Rc4Error> = Rc4::self_test;
+    let _: fn(&[u8], &mut [u8]) -> Result<(), Rc4Error> = Rc4::apply_keystream_static;
+    let _: fn(&[u8], u64, &mut [u8]) -> Result<(), Rc4Error> = Rc4::apply_keystream_static_at;
+
+    let _: usize = rc4::framing::LEN_PREFIX;
+    let _: fn(&[u8]) -> Result<Framer, Rc4Error> = Framer::new;
+    let _: fn(&mut Framer, &[u8], &mut [u8]) -> Result<usize, Rc4Error> = Framer::frame_message;
+    let _: for<'a> fn(&mut Framer, &'a mut [u8]) -> Option<(&'a [u8], usize)> = Framer::read_framed;
+
+    fn cipher_traits<C: Clone + core::fmt::Debug>() {}
+    cipher_traits::<Rc4>();
+
+    fn error_traits<E: Clone + Eq + core::fmt::Debug + core::fmt::Display>() {}
+    error_traits::<Rc4Error>();
+
+    #[cfg(feature = "std")]
+    let _: fn(Rc4Error) -> std::io::Error = From::from;
+    #[cfg(feature = "cipher")]
+    {
+        let _: fn(&[u8]) -> Result<Rc4, cipher::InvalidLength> = <Rc4 as cipher::KeyInit>::new_from_slice;
+        fn stream_traits<C: cipher::StreamCipher + cipher::StreamCipherSeek>() {}
+        stream_traits::<Rc4>();
+    }
+    #[cfg(feature = "std")]
+    let _: &dyn std::error::Error = &Rc4Error::KeyTooShort(5);
+
+    #[cfg(feature = "std")]
+    {
+        use rc4::io::Rc4Reader;
+        use std::io::Cursor;
+        let _: fn(&[u8], Cursor<Vec<u8>>) -> Result<Rc4Reader<Cursor<Vec<u8>>>, Rc4Error> = Rc4Reader::new;
+        let _: fn(Rc4Reader<Cursor<Vec<u8>>>) -> Cursor<Vec<u8>> = Rc4Reader::into_inner;
+        fn reader<R: std::io::Read>(_: R) {}
+        reader(Rc4Reader::new(&[0; 5], Cursor::new(Vec::new())).unwrap());
+
+        use rc4::io::Rc4Writer;
+        let _: fn(&[u8], Vec<u8>) -> Result<Rc4Writer<Vec<u8>>, Rc4Error> = Rc4Writer::new;
+        let _: fn(Rc4Writer<Vec<u8>>) -> Vec<u8> = Rc4Writer::into_inner;
+        fn writer<W: std::io::Write>(_: W) {}
+        writer(Rc4Writer::new(&[0; 5], Vec::new()).unwrap());
+    }
+
+    #[cfg(feature = "alloc")]
+    {
+        let _: usize = rc4::AUTO_ONE_SHOT_MAX + rc4::AUTO_CHUNK_SIZE;
+        let _: fn(&[u8], &[u8]) -> Result<Vec<u8>, Rc4Error> = rc4::encrypt_auto;
+        let _: fn(&[u8], &[u8]) -> Result<Vec<u8>, Rc4Error> = rc4::encrypt_to_vec;
+        let _: fn(&[u8], &[u8], usize) -> Result<Vec<u8>, Rc4Error> = rc4::encrypt_with_chunk_size;
+        // `impl Iterator` can't be named, check what can be done with it instead
+        let chunks: Vec<Vec<u8>> = rc4::encrypt_chunks(&[0; 5], &[0; 10], 4).unwrap().collect();
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[cfg(feature = "research")]
+    {
+        let _: usize = rc4::research::SAMPLE_COUNTER_LEN;
+        let _: fn(&[u8], u32, usize) -> Result<[u64; 256], Rc4Error> = rc4::research::position_histogram;
+        let _: fn(&[u64; 256]) -> f64 = rc4::research::chi_square;
+    }
+
+    #[cfg(feature = "rand_core")]
+    {
+        fn rng_traits<R: rand_core::RngCore + rand_core::SeedableRng<Seed = [u8; 32]> + Clone + core::fmt::Debug>() {}
+        rng_traits::<rc4::Rc4Rng>();
+        let _: fn(Rc4) -> rc4::Rc4Rng = From::from;
+    }
+
+    #[cfg(feature = "fms")]
+    {
+        use rc4::fms::{Sample, IV_LEN};
+        let _: Sample = Sample { iv: [0; IV_LEN], first_byte: 0 };
+        let _: fn(&[u8], u8, u8) -> Result<Sample, Rc4Error> = rc4::fms::weak_iv_sample;
+        let _: fn(&[Sample], &[u8]) -> [u32; 256] = rc4::fms::vote_next_byte;
+        let _: fn(&[Sample], &mut [u8]) = rc4::fms::recover_secret;
+    }
+}
+
+
+#[test]
+fn error_variants_are_unchanged() {
+    // Exhaustive, so adding a variant fails to compile
+    fn describe(e: &Rc4Error) -> &'static str {
+        match e {
+            Rc4Error::KeyTooShort(_) => "key too short",
+            Rc4Error::KeyTooLong(_) => "key too long",
+            Rc4Error::LengthMismatch(_, _) => "length mismatch",
+            Rc4Error::FrameTooLong(_) => "frame too long",
+            Rc4Error::InvalidHexDigit(_) => "invalid hex digit",
+            Rc4Error::OddHexDigits(_) => "odd hex digits",
+            Rc4Error::SelfTestFailed(_) => "self-test failed",
+            Rc4Error::ZeroChunkSize => "zero chunk size",
+        }
+    }
+
+    assert_eq!(describe(&Rc4::apply_keystream_static(&[0; 4], &mut []).unwrap_err()), "key too short");
+    assert_eq!(describe(&Rc4::apply_keystream_static(&[0; 257], &mut []).unwrap_err()), "key too long");
+    assert_eq!(describe(&rc4::xor_slices(&mut [0; 2], &[0; 3]).unwrap_err()), "length mismatch");
+    assert_eq!(describe(&rc4::parse_hex_key("4b 8z").unwrap_err()), "invalid hex digit");
+    assert_eq!(describe(&rc4::parse_hex_key("4b 8").unwrap_err()), "odd hex digits");
+}
@@ -0,0 +1,91 @@
+// How `process_file` reads a file in and writes the result out, against other buffering setups, on large files.
+// Run with `cargo bench -p rcli --bench io_buffer`. Files are read back warm from the page cache, so this measures
+// copies and syscalls rather than the disk.
+
+use std::fs::File;
+use std::hint::black_box;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const FILE_SIZES: &[usize] = &[64 * 1024, 4 * 1024 * 1024, 64 * 1024 * 1024];
+
+// `read_chunked`: read through `buffer` into a vector sized up front
+fn read_chunked<R: Read>(mut reader: R, buffer: &mut [u8], capacity: usize) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::with_capacity(capacity);
+    loop {
+        match reader.read(buffer)? {
+            0 => return Ok(contents),
+            n => contents.extend_from_slice(&buffer[..n]),
+        }
+    }
+}
+
+// Reads `path` (of `len` bytes) in full
+type ReadFn = fn(&Path, usize) -> io::Result<Vec<u8>>;
+
+// Writes `contents` to `path` in one `write_all`
+type WriteFn = fn(&Path, &[u8]) -> io::Result<()>;
+
+fn reads() -> Vec<(&'static str, ReadFn)> {
+    vec![
+        ("BufReader (8 KiB) + 4 KiB chunks", |path, len| {
+            read_chunked(BufReader::new(File::open(path)?), &mut [0; 4096], len)
+        }),
+        ("File + 4 KiB chunks", |path, len| read_chunked(File::open(path)?, &mut [0; 4096], len)),
+        ("File + 64 KiB chunks", |path, len| read_chunked(File::open(path)?, &mut vec![0; 64 * 1024], len)),
+        ("BufReader (64 KiB) + 64 KiB chunks", |path, len| {
+            read_chunked(BufReader::with_capacity(64 * 1024, File::open(path)?), &mut vec![0; 64 * 1024], len)
+        }),
+        ("File + 1 MiB chunks", |path, len| read_chunked(File::open(path)?, &mut vec![0; 1024 * 1024], len)),
+    ]
+}
+
+// Whichever write runs first measures ~10% faster, whatever it is: swap these to check a difference is real
+fn writes() -> Vec<(&'static str, WriteFn)> {
+    vec![
+        ("File", |path, contents| File::create(path)?.write_all(contents)),
+        ("BufWriter (8 KiB)", |path, contents| {
+            let mut writer = BufWriter::new(File::create(path)?);
+            writer.write_all(contents)?;
+            writer.flush()
+        }),
+    ]
+}
+
+// Best of several runs of at least ~100ms each, in MB/s
+fn measure<F: FnMut() -> io::Result<()>>(len: usize, mut f: F) -> f64 {
+    let mut best = f64::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        let mut runs = 0;
+        while start.elapsed() < Duration::from_millis(100) {
+            f().unwrap();
+            runs += 1;
+        }
+        best = best.min(start.elapsed().as_secs_f64() / runs as f64);
+    }
+    len as f64 / best / 1e6
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let target = dir.path().join("target");
+
+    for &len in FILE_SIZES {
+        let contents: Vec<u8> = (0..len).map(|n| (n % 251) as u8).collect();
+        std::fs::write(&source, &contents).unwrap();
+
+        println!("{} bytes", len);
+        for (name, read) in reads() {
+            assert!(read(&source, len).unwrap() == contents, "{}", name);
+            let speed = measure(len, || read(&source, len).map(|data| drop(black_box(data))));
+            println!("  read  {:<36} {:>9.1} MB/s", name, speed);
+        }
+        for (name, write) in writes() {
+            let speed = measure(len, || write(&target, black_box(&contents)));
+            println!("  write {:<36} {:>9.1} MB/s", name, speed);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use rc4::Rc4;
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+/// Sidecar recording how much keystream an append-only log has consumed
+pub fn sidecar_path(file_path: &str) -> String {
+    format!("{}.rc4pos", file_path)
+}
+
+/// Encrypt `data` continuing the keystream of the log at `file_path` and append it, creating the
+/// log if needed. Returns the new keystream position (= log length).
+///
+/// The sidecar only stores the keystream offset, never the cipher state itself: the state is
+/// key-equivalent, and RC4's state update can be run backwards, so a leaked state would expose the
+/// keystream of everything already in the log as well as everything appended later.
+pub fn append(file_path: &str, key_bytes: &[u8], data: &[u8]) -> io::Result<u64> {
+    let sidecar = sidecar_path(file_path);
+    let offset = match fs::metadata(file_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+
+    // A mismatch means the log was modified outside of `--append` (or a previous append was interrupted),
+    // continuing from either position would reuse or skip keystream
+    match fs::read_to_string(&sidecar) {
+        Ok(text) => {
+            let recorded: u64 = text
+                .trim()
+                .parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{}: not a keystream position", sidecar)))?;
+            if recorded != offset {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is {} bytes but {} records {}, refusing to append", file_path, offset, sidecar, recorded),
+                ));
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut encrypted = data.to_vec();
+    Rc4::apply_keystream_static_at(key_bytes, offset, &mut encrypted)?;
+
+    let mut log = File::options().create(true).append(true).open(file_path)?;
+    log.write_all(&encrypted)?;
+    log.sync_all()?;
+
+    let position = offset + encrypted.len() as u64;
+    fs::write(&sidecar, format!("{}\n", position))?;
+    Ok(position)
+}
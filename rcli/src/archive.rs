@@ -0,0 +1,332 @@
+use crate::header::NONCE_LEN;
+use crate::nonce::{self, Nonce};
+use rc4::Rc4;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use tempfile::NamedTempFile;
+
+// Magic bytes identifying an archive written by `--archive`
+pub const MAGIC: &[u8; 4] = b"RC4A";
+
+// Starts the decrypted index, so a wrong key is told apart from a corrupt archive
+const INDEX_CHECK: &[u8; 4] = b"INDX";
+
+// Keeps the index key apart from the data key it's derived from
+const INDEX_DOMAIN: &[u8] = b"rcli-archive-index";
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// One archived file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path relative to the archived directory, `/`-separated
+    pub path: String,
+    /// Where its contents start, counted from the first entry's
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Bundle `files`, found under `root`, into a new archive at `out`:
+///
+/// ```text
+/// magic (4 bytes) | nonce (16 bytes) | contents of every file | index | index length (u32, big-endian)
+/// ```
+///
+/// The contents run through one keystream under SHA-256(key || nonce), the index (path, offset and length of
+/// every file) through its own under a key derived from that one. Only the archive's total size and the size of
+/// its index are in the clear. `out` must not exist yet.
+pub fn create(root: &Path, files: &[PathBuf], key: &[u8], nonce: Nonce, out: &Path) -> io::Result<Vec<Entry>> {
+    let dir = match out.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let data_key = nonce::derive_key(key, &nonce);
+    let mut cipher = Rc4::new(&data_key)?;
+
+    // Dropped (and deleted) on any error below
+    let mut temp = NamedTempFile::new_in(dir)?;
+    let archive = temp.as_file_mut();
+    archive.write_all(MAGIC)?;
+    archive.write_all(&nonce)?;
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut entries = Vec::with_capacity(files.len());
+    let mut offset = 0;
+    for file in files {
+        let path = entry_path(root, file)?;
+        let mut source = File::open(file)?;
+        let mut len = 0;
+        loop {
+            let n = source.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            cipher.apply_keystream(&mut buffer[..n]);
+            archive.write_all(&buffer[..n])?;
+            len += n as u64;
+        }
+        entries.push(Entry { path, offset, len });
+        offset += len;
+    }
+
+    let mut index = encode_index(&entries);
+    Rc4::apply_keystream_static(&index_key(&data_key), &mut index)?;
+    archive.write_all(&index)?;
+    archive.write_all(&(index.len() as u32).to_be_bytes())?;
+    archive.sync_all()?;
+
+    temp.persist_noclobber(out).map_err(|e| io::Error::new(e.error.kind(), format!("{}: {}", out.display(), e.error)))?;
+    Ok(entries)
+}
+
+/// Extract the archive at `archive` under `dir`, recreating the archived tree. Every path in the
+/// index is checked before anything is written, and existing files are never overwritten.
+pub fn extract(archive: &Path, key: &[u8], dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut file = File::open(archive)?;
+    let archive_len = file.metadata()?.len();
+
+    let mut start = [0u8; MAGIC.len() + NONCE_LEN];
+    file.read_exact(&mut start).map_err(|_| invalid("not an rcli archive"))?;
+    if !start.starts_with(MAGIC) {
+        return Err(invalid("not an rcli archive"));
+    }
+    let nonce: Nonce = start[MAGIC.len()..].try_into().expect("NONCE_LEN bytes");
+
+    let mut index_len = [0u8; 4];
+    file.seek(SeekFrom::End(-(index_len.len() as i64))).map_err(|_| invalid(TRUNCATED))?;
+    file.read_exact(&mut index_len)?;
+    let index_len = u32::from_be_bytes(index_len) as u64;
+    let data_len = archive_len
+        .checked_sub(start.len() as u64 + index_len + 4)
+        .ok_or_else(|| invalid(TRUNCATED))?;
+
+    let data_key = nonce::derive_key(key, &nonce);
+    let mut index = vec![0u8; index_len as usize];
+    file.seek(SeekFrom::Start(start.len() as u64 + data_len))?;
+    file.read_exact(&mut index)?;
+    Rc4::apply_keystream_static(&index_key(&data_key), &mut index)?;
+    let entries = decode_index(&index, data_len)?;
+
+    let targets = entries.iter().map(|entry| extract_path(dir, &entry.path)).collect::<io::Result<Vec<_>>>()?;
+
+    // Entries are stored back to back, so one keystream runs through all of them in order
+    let mut cipher = Rc4::new(&data_key)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    file.seek(SeekFrom::Start(start.len() as u64))?;
+    for (entry, target) in entries.iter().zip(&targets) {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::options()
+            .write(true)
+            .create_new(true)
+            .open(target)
+            .map_err(|e| io::Error::new(e.kind(), format!("can't extract {}: {}", target.display(), e)))?;
+
+        let mut left = entry.len;
+        while left > 0 {
+            let n = left.min(CHUNK_SIZE as u64) as usize;
+            file.read_exact(&mut buffer[..n])?;
+            cipher.apply_keystream(&mut buffer[..n]);
+            out.write_all(&buffer[..n])?;
+            left -= n as u64;
+        }
+        out.sync_all()?;
+    }
+    crate::wipe::wipe_slice(&mut buffer);
+
+    Ok(entries)
+}
+
+// Index key: SHA-256(data key || "rcli-archive-index")
+fn index_key(data_key: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(data_key);
+    hasher.update(INDEX_DOMAIN);
+    hasher.finalize().into()
+}
+
+// `file`'s path in the index: relative to `root`, or just its name for a lone file
+fn entry_path(root: &Path, file: &Path) -> io::Result<String> {
+    let relative = match file.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        _ => file.file_name().map(Path::new).unwrap_or(file),
+    };
+
+    let parts = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_str().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a UTF-8 path", file.display()))
+            })),
+            _ => None,
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(parts.join("/"))
+}
+
+// Where the entry at `path` is extracted under `dir`. An index can say anything, so anything that
+// isn't a plain relative path (`..`, an absolute path, a drive prefix) is refused rather than
+// written outside `dir`
+fn extract_path(dir: &Path, path: &str) -> io::Result<PathBuf> {
+    let mut target = dir.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => target.push(part),
+            _ => return Err(invalid(&format!("entry {:?} would be extracted outside {}", path, dir.display()))),
+        }
+    }
+    if target == dir {
+        return Err(invalid(&format!("entry {:?} has no file name", path)));
+    }
+    Ok(target)
+}
+
+// `INDX | entry count (u32) | entries`, each `path length (u16) | path | offset (u64) | length (u64)`
+fn encode_index(entries: &[Entry]) -> Vec<u8> {
+    let mut out = INDEX_CHECK.to_vec();
+    out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for entry in entries {
+        out.extend_from_slice(&(entry.path.len() as u16).to_be_bytes());
+        out.extend_from_slice(entry.path.as_bytes());
+        out.extend_from_slice(&entry.offset.to_be_bytes());
+        out.extend_from_slice(&entry.len.to_be_bytes());
+    }
+    out
+}
+
+// Parse an index, checking its entries cover the `data_len` bytes of contents back to back
+fn decode_index(mut index: &[u8], data_len: u64) -> io::Result<Vec<Entry>> {
+    if take(&mut index, INDEX_CHECK.len())? != INDEX_CHECK {
+        return Err(invalid("wrong key, or the archive is corrupt"));
+    }
+
+    let count = u32::from_be_bytes(take(&mut index, 4)?.try_into().expect("4 bytes"));
+    let mut entries = Vec::new();
+    let mut next = 0;
+    for _ in 0..count {
+        let path_len = u16::from_be_bytes(take(&mut index, 2)?.try_into().expect("2 bytes")) as usize;
+        let path = String::from_utf8(take(&mut index, path_len)?.to_vec()).map_err(|_| invalid("entry path is not UTF-8"))?;
+        let offset = u64::from_be_bytes(take(&mut index, 8)?.try_into().expect("8 bytes"));
+        let len = u64::from_be_bytes(take(&mut index, 8)?.try_into().expect("8 bytes"));
+        if offset != next {
+            return Err(invalid(TRUNCATED));
+        }
+        next = offset.checked_add(len).ok_or_else(|| invalid(TRUNCATED))?;
+        entries.push(Entry { path, offset, len });
+    }
+
+    if next != data_len || !index.is_empty() {
+        return Err(invalid(TRUNCATED));
+    }
+    Ok(entries)
+}
+
+fn take<'a>(data: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if data.len() < n {
+        return Err(invalid(TRUNCATED));
+    }
+    let (head, rest) = data.split_at(n);
+    *data = rest;
+    Ok(head)
+}
+
+const TRUNCATED: &str = crate::header::TRUNCATED;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid rcli archive: {}", msg))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+    const NONCE: Nonce = [0xab; NONCE_LEN];
+
+    // `a.txt`, `sub/b.bin` and an empty `sub/deeper/c` under a temp dir
+    fn tree() -> (tempfile::TempDir, Vec<PathBuf>) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("tree");
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::write(root.join("a.txt"), b"This is a secret").unwrap();
+        fs::write(root.join("sub/b.bin"), (0..100_000u32).map(|n| n as u8).collect::<Vec<_>>()).unwrap();
+        fs::write(root.join("sub/deeper/c"), b"").unwrap();
+        let files = ["a.txt", "sub/b.bin", "sub/deeper/c"].iter().map(|name| root.join(name)).collect();
+        (dir, files)
+    }
+
+    #[test]
+    fn round_trips_a_tree() {
+        let (dir, files) = tree();
+        let root = dir.path().join("tree");
+        let archive = dir.path().join("tree.rc4a");
+
+        let entries = create(&root, &files, &KEY, NONCE, &archive).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt", "sub/b.bin", "sub/deeper/c"]);
+        let contents = fs::read(&archive).unwrap();
+        assert!(!contents.windows(16).any(|w| w == b"This is a secret"));
+        assert!(!contents.windows(5).any(|w| w == b"a.txt"));
+
+        let out = dir.path().join("out");
+        assert_eq!(extract(&archive, &KEY, &out).unwrap(), entries);
+        for file in &files {
+            let extracted = out.join(file.strip_prefix(&root).unwrap());
+            assert_eq!(fs::read(extracted).unwrap(), fs::read(file).unwrap());
+        }
+
+        // Nothing is overwritten
+        assert!(extract(&archive, &KEY, &out).is_err());
+    }
+
+    #[test]
+    fn wrong_key_or_truncation_is_detected() {
+        let (dir, files) = tree();
+        let archive = dir.path().join("tree.rc4a");
+        create(&dir.path().join("tree"), &files, &KEY, NONCE, &archive).unwrap();
+
+        let err = extract(&archive, &[1, 2, 3, 4, 5], &dir.path().join("out")).unwrap_err();
+        assert!(err.to_string().contains("wrong key"), "{}", err);
+
+        let mut contents = fs::read(&archive).unwrap();
+        contents.remove(100);
+        fs::write(&archive, &contents).unwrap();
+        assert!(extract(&archive, &KEY, &dir.path().join("out")).is_err());
+        assert!(!dir.path().join("out").exists());
+    }
+
+    #[test]
+    fn entries_outside_the_directory_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        for path in ["../escaped", "sub/../../escaped", "/tmp/escaped", "", "."] {
+            assert!(extract_path(&out, path).is_err(), "{:?}", path);
+        }
+        assert_eq!(extract_path(&out, "sub/./c").unwrap(), out.join("sub").join("c"));
+
+        // An archive crafted with such a path writes nothing at all
+        let data_key = nonce::derive_key(&KEY, &NONCE);
+        let mut contents = [&MAGIC[..], &NONCE].concat();
+        let mut data = *b"goodevil";
+        Rc4::apply_keystream_static(&data_key, &mut data).unwrap();
+        contents.extend_from_slice(&data);
+        let entries = [
+            Entry { path: "good".to_string(), offset: 0, len: 4 },
+            Entry { path: "../evil".to_string(), offset: 4, len: 4 },
+        ];
+        let mut index = encode_index(&entries);
+        Rc4::apply_keystream_static(&index_key(&data_key), &mut index).unwrap();
+        contents.extend_from_slice(&index);
+        contents.extend_from_slice(&(index.len() as u32).to_be_bytes());
+        let archive = dir.path().join("evil.rc4a");
+        fs::write(&archive, contents).unwrap();
+
+        let err = extract(&archive, &KEY, &out).unwrap_err();
+        assert!(err.to_string().contains("outside"), "{}", err);
+        assert!(!out.exists() && !dir.path().join("evil").exists());
+    }
+}
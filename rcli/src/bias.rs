@@ -0,0 +1,86 @@
+use rc4::research;
+use std::fs;
+use std::io;
+
+/// `rcli report-bias`: distribution of the keystream byte at `position` across `samples` keys
+/// derived from `key_bytes`, summarized on stdout and optionally written to a `byte,count` CSV
+pub fn report_bias(key_bytes: &[u8], samples: u32, position: usize, csv: Option<&str>) -> io::Result<()> {
+    let histogram = research::position_histogram(key_bytes, samples, position)?;
+
+    print!("{}", summary(&histogram, position));
+
+    if let Some(path) = csv {
+        fs::write(path, to_csv(&histogram))?;
+        println!("Wrote distribution to {}", path);
+    }
+
+    Ok(())
+}
+
+fn summary(histogram: &[u64; 256], position: usize) -> String {
+    let total: u64 = histogram.iter().sum();
+    let expected = total as f64 / 256.0;
+    let chi_square = research::chi_square(histogram);
+
+    // Most over-represented byte value, lowest value on ties
+    let (top_byte, top_count) = histogram
+        .iter()
+        .enumerate()
+        .fold((0, 0), |best, (byte, &count)| if count > best.1 { (byte, count) } else { best });
+
+    let verdict = if chi_square > 330.5 {
+        "significantly non-uniform (p < 0.001)"
+    } else {
+        "consistent with uniform"
+    };
+
+    let mut out = String::new();
+    out.push_str(&format!("Keystream byte at offset {} over {} samples\n", position, total));
+    out.push_str(&format!("Expected count per value (uniform): {:.2}\n", expected));
+    out.push_str(&format!(
+        "Most frequent: 0x{:02x} seen {} times ({:.2}x uniform)\n",
+        top_byte,
+        top_count,
+        if expected > 0.0 { top_count as f64 / expected } else { 0.0 }
+    ));
+    out.push_str(&format!("Chi-square (255 dof): {:.2}, {}\n", chi_square, verdict));
+    out
+}
+
+fn to_csv(histogram: &[u64; 256]) -> String {
+    let mut out = String::from("byte,count\n");
+    for (byte, count) in histogram.iter().enumerate() {
+        out.push_str(&format!("{},{}\n", byte, count));
+    }
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_lists_every_byte_value() {
+        let mut histogram = [0u64; 256];
+        histogram[0] = 7;
+        histogram[255] = 3;
+
+        let csv = to_csv(&histogram);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 257);
+        assert_eq!(lines[0], "byte,count");
+        assert_eq!(lines[1], "0,7");
+        assert_eq!(lines[256], "255,3");
+    }
+
+    #[test]
+    fn summary_reports_most_frequent_byte() {
+        let mut histogram = [1u64; 256];
+        histogram[0x2a] = 100;
+
+        let summary = summary(&histogram, 1);
+        assert!(summary.contains("over 355 samples"));
+        assert!(summary.contains("Most frequent: 0x2a seen 100 times"));
+    }
+}
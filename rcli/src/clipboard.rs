@@ -0,0 +1,136 @@
+use crate::header::NONCE_LEN;
+use crate::nonce::{self, NonceRegistry, NonceSource};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rc4::Rc4;
+use std::io;
+
+// Ciphertext placed on the clipboard is tagged so the next run can tell it apart from plaintext
+// (plain base64 alone is ambiguous: "abcd" is both valid text and valid base64)
+const PREFIX: &str = "rc4:";
+
+/// Clipboard text format for a payload, the nonce followed by the ciphertext: `rc4:<base64>`
+pub fn encode(payload: &[u8]) -> String {
+    format!("{}{}", PREFIX, STANDARD.encode(payload))
+}
+
+/// Parse clipboard text back into payload bytes, `None` if it isn't in our format
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let encoded = text.trim().strip_prefix(PREFIX)?;
+    STANDARD.decode(encoded).ok()
+}
+
+/// Apply the cipher to clipboard text, returning the new clipboard text and whether it was encrypted.
+/// Tagged ciphertext is decrypted back into plaintext (which must be UTF-8 text), anything else is
+/// treated as plaintext and encrypted.
+///
+/// Each encryption draws its own nonce and is stored as `rc4:<base64(nonce || ciphertext)>`, the
+/// way `--env` stores values: two secrets copied under one keystream could be XORed against each
+/// other to cancel it out.
+pub fn transform<S: NonceSource>(text: &str, key_bytes: &[u8], nonces: &NonceRegistry<S>) -> io::Result<(String, bool)> {
+    match decode(text) {
+        Some(payload) => {
+            if payload.len() < NONCE_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "encrypted clipboard contents are too short"));
+            }
+            let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+            let mut contents = ciphertext.to_vec();
+            Rc4::apply_keystream_static(&nonce::derive_key(key_bytes, nonce.try_into().unwrap()), &mut contents)?;
+
+            let plaintext = String::from_utf8(contents).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "decrypted clipboard contents are not valid UTF-8 (wrong key?)")
+            })?;
+            Ok((plaintext, false))
+        }
+        None => {
+            let nonce = nonces.draw()?;
+            let mut contents = text.as_bytes().to_vec();
+            Rc4::apply_keystream_static(&nonce::derive_key(key_bytes, &nonce), &mut contents)?;
+
+            let mut payload = nonce.to_vec();
+            payload.extend_from_slice(&contents);
+            Ok((encode(&payload), true))
+        }
+    }
+}
+
+/// En/decrypt the current clipboard contents in place
+pub fn process_clipboard<S: NonceSource>(key_bytes: &[u8], nonces: &NonceRegistry<S>) -> io::Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(io::Error::other)?;
+    let text = clipboard.get_text().map_err(io::Error::other)?;
+
+    let (output, encrypted) = transform(&text, key_bytes, nonces)?;
+    clipboard.set_text(output).map_err(io::Error::other)?;
+
+    if encrypted {
+        println!("Encrypted clipboard");
+    } else {
+        println!("Decrypted clipboard");
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nonce::OsNonceSource;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let bytes = [0x00, 0xff, 0x10, 0x80, 0x7f];
+        let text = encode(&bytes);
+        assert!(text.starts_with(PREFIX));
+        assert_eq!(decode(&text).unwrap(), bytes);
+
+        // Surrounding whitespace (e.g. a trailing newline from a chat client) is tolerated
+        assert_eq!(decode(&format!("  {}\n", text)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn untagged_text_is_plaintext() {
+        assert_eq!(decode("abcd"), None);
+        assert_eq!(decode("rc4:not base64!"), None);
+    }
+
+    #[test]
+    fn transform_round_trip() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        let (ciphertext, encrypted) = transform("This is a secret", &KEY, &nonces).unwrap();
+        assert!(encrypted);
+        assert!(ciphertext.starts_with(PREFIX));
+
+        let (plaintext, encrypted) = transform(&ciphertext, &KEY, &nonces).unwrap();
+        assert!(!encrypted);
+        assert_eq!(plaintext, "This is a secret");
+    }
+
+    #[test]
+    fn transform_wrong_key_is_an_error() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        let (ciphertext, _) = transform("This is a secret", &KEY, &nonces).unwrap();
+        assert!(transform(&ciphertext, &[0x01, 0x02, 0x03, 0x04, 0x05], &nonces).is_err());
+    }
+
+    #[test]
+    fn same_text_encrypts_differently_each_time() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        let (first, _) = transform("This is a secret", &KEY, &nonces).unwrap();
+        let (second, _) = transform("This is a secret", &KEY, &nonces).unwrap();
+        assert_ne!(first, second);
+
+        // Nor is the ciphertext plain RC4 under the key given
+        let mut plain_rc4 = b"This is a secret".to_vec();
+        Rc4::apply_keystream_static(&KEY, &mut plain_rc4).unwrap();
+        assert!(!decode(&first).unwrap().ends_with(&plain_rc4));
+    }
+
+    #[test]
+    fn payload_shorter_than_a_nonce_is_an_error() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        assert!(transform(&encode(&[0x01, 0x02, 0x03]), &KEY, &nonces).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Step of processing a file, named in the errors that happen during it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Open,
+    Read,
+    Encrypt,
+    Decrypt,
+    Backup,
+    WriteBack,
+    Verify,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Open => "open",
+            Phase::Read => "read",
+            Phase::Encrypt => "encryption",
+            Phase::Decrypt => "decryption",
+            Phase::Backup => "backup",
+            Phase::WriteBack => "write-back",
+            Phase::Verify => "verification",
+        })
+    }
+}
+
+/// Wrap an error from processing `path` during `phase`, keeping its kind:
+/// `error processing '<path>': <error> (during <phase>)`
+pub fn in_phase(path: &Path, phase: Phase) -> impl FnOnce(io::Error) -> io::Error + '_ {
+    move |e| io::Error::new(e.kind(), format!("error processing '{}': {} (during {})", path.display(), e, phase))
+}
+
+/// Name the operation a lower-level error came from, keeping its kind: `<op> failed: <error>`
+pub fn failed(op: &'static str) -> impl FnOnce(io::Error) -> io::Error {
+    move |e| io::Error::new(e.kind(), format!("{} failed: {}", op, e))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_path_operation_and_phase() {
+        let e = io::Error::new(io::ErrorKind::StorageFull, "No space left on device");
+        let e = in_phase(Path::new("/a/b.txt"), Phase::WriteBack)(failed("write")(e));
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(e.to_string(), "error processing '/a/b.txt': write failed: No space left on device (during write-back)");
+    }
+}
@@ -0,0 +1,137 @@
+/// Shannon entropy of `data`, in bits per byte (0 for empty data, at most 8)
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    let mut counts = [0; 256];
+    count_bytes(&mut counts, data);
+    entropy_of_counts(&counts)
+}
+
+/// Add how often each byte value occurs in `data` to `counts`, so data read in chunks can be
+/// measured as a whole
+pub fn count_bytes(counts: &mut [u64; 256], data: &[u8]) {
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+}
+
+/// Shannon entropy of data whose byte values occur `counts` times, see `shannon_entropy`
+pub fn entropy_of_counts(counts: &[u64; 256]) -> f64 {
+    let len = counts.iter().sum::<u64>() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// How many windows of a file look encrypted, see `windowed_entropy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowReport {
+    pub window: usize,
+    pub windows: usize,
+    pub encrypted: usize,
+}
+
+impl WindowReport {
+    /// Fraction of windows that look encrypted, 0 for an empty file
+    pub fn fraction(&self) -> f64 {
+        if self.windows == 0 {
+            0.0
+        } else {
+            self.encrypted as f64 / self.windows as f64
+        }
+    }
+}
+
+/// Entropy of consecutive `window`-byte windows of `data`, counting those that look encrypted.
+///
+/// A single entropy figure for a file that's part text, part compressed or encrypted (an office
+/// document, an archive with a text manifest) lands somewhere in between and says little about
+/// either part. Per window, the two tell apart clearly. A trailing window shorter than half of
+/// `window` is folded into the one before, since too few bytes say little about their entropy.
+///
+/// A window looks encrypted when its entropy is above 80% of the most its size allows
+/// (`log2(min(window, 256))`): random bytes come close to it, text stays well below.
+pub fn windowed_entropy(data: &[u8], window: usize) -> WindowReport {
+    assert!(window > 0, "window must not be empty");
+
+    let mut starts: Vec<usize> = (0..data.len()).step_by(window).collect();
+    if starts.len() > 1 && data.len() - starts[starts.len() - 1] < window / 2 {
+        starts.pop();
+    }
+
+    let mut report = WindowReport { window, windows: starts.len(), encrypted: 0 };
+    for (idx, &start) in starts.iter().enumerate() {
+        let end = starts.get(idx + 1).copied().unwrap_or(data.len());
+        let chunk = &data[start..end];
+        let max_entropy = (chunk.len().min(256) as f64).log2();
+        if shannon_entropy(chunk) > 0.8 * max_entropy {
+            report.encrypted += 1;
+        }
+    }
+    report
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"Files with both text and binary sections confuse a single global entropy figure. ";
+
+    fn text(len: usize) -> Vec<u8> {
+        TEXT.iter().copied().cycle().take(len).collect()
+    }
+
+    // Deterministic random-looking bytes: RC4 keystream
+    fn random(len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn entropy_bounds() {
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[7; 100]), 0.0);
+        assert_eq!(shannon_entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+        assert!(shannon_entropy(&text(4096)) < 5.0);
+        assert!(shannon_entropy(&random(4096)) > 7.9);
+    }
+
+    #[test]
+    fn counts_add_up_across_chunks() {
+        let data = random(3000);
+        let mut counts = [0; 256];
+        for chunk in data.chunks(700) {
+            count_bytes(&mut counts, chunk);
+        }
+        assert_eq!(counts.iter().sum::<u64>(), 3000);
+        assert_eq!(entropy_of_counts(&counts), shannon_entropy(&data));
+        assert_eq!(entropy_of_counts(&[0; 256]), 0.0);
+    }
+
+    #[test]
+    fn windows_reflect_half_text_half_random() {
+        let mut data = text(8192);
+        data.extend(random(8192));
+
+        // The whole file is in between, too random for text and not random enough for ciphertext
+        let global = shannon_entropy(&data);
+        assert!(global > 5.0 && global < 7.5, "{}", global);
+
+        let report = windowed_entropy(&data, 512);
+        assert_eq!((report.windows, report.encrypted), (32, 16));
+        assert_eq!(report.fraction(), 0.5);
+    }
+
+    #[test]
+    fn short_tail_joins_previous_window() {
+        assert_eq!(windowed_entropy(&random(1100), 512).windows, 2);
+        assert_eq!(windowed_entropy(&random(1400), 512).windows, 3);
+        assert_eq!(windowed_entropy(&random(100), 512).windows, 1);
+        assert_eq!(windowed_entropy(&[], 512).fraction(), 0.0);
+    }
+}
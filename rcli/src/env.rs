@@ -0,0 +1,161 @@
+use crate::header::NONCE_LEN;
+use crate::nonce::{self, NonceRegistry, NonceSource};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rc4::Rc4;
+use std::io;
+
+// Encrypted values are tagged so they can be told apart from plaintext ones
+const PREFIX: &str = "rc4:";
+
+/// Encrypt the value of every `KEY=value` line of a `.env` file, returning the new text and how
+/// many values were encrypted.
+///
+/// Everything but the values is kept byte for byte: keys, comments, blank lines, line endings.
+/// Each value becomes `rc4:<base64(nonce || ciphertext)>` under its own nonce, since values
+/// encrypted under one keystream could be XORed against each other. Empty and already-encrypted
+/// values are left alone.
+pub fn encrypt_values<S: NonceSource>(
+    text: &str,
+    key_bytes: &[u8],
+    nonces: &NonceRegistry<S>,
+) -> io::Result<(String, usize)> {
+    map_values(text, |value| {
+        if value.is_empty() || value.starts_with(PREFIX) {
+            return Ok(None);
+        }
+
+        let nonce = nonces.draw()?;
+        let mut ciphertext = value.as_bytes().to_vec();
+        apply(&nonce::derive_key(key_bytes, &nonce), &mut ciphertext)?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(Some(format!("{}{}", PREFIX, STANDARD.encode(payload))))
+    })
+}
+
+/// Decrypt every `rc4:` value written by `encrypt_values`, returning the new text and how many
+/// values were decrypted. Plaintext values are left alone.
+pub fn decrypt_values(text: &str, key_bytes: &[u8]) -> io::Result<(String, usize)> {
+    map_values(text, |value| {
+        let Some(encoded) = value.strip_prefix(PREFIX) else {
+            return Ok(None);
+        };
+
+        let payload = STANDARD
+            .decode(encoded)
+            .ok()
+            .filter(|payload| payload.len() >= NONCE_LEN)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("malformed encrypted value '{}'", value)))?;
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+
+        let mut plaintext = ciphertext.to_vec();
+        apply(&nonce::derive_key(key_bytes, nonce.try_into().unwrap()), &mut plaintext)?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "decrypted value is not valid UTF-8 (wrong key?)"))
+    })
+}
+
+/// Whether any value in the file is encrypted
+pub fn has_encrypted_values(text: &str) -> bool {
+    text.lines().filter_map(split_assignment).any(|(_, value)| value.starts_with(PREFIX))
+}
+
+// Rebuild `text`, replacing each assignment's value with `f(value)` when it returns `Some`
+fn map_values<F>(text: &str, mut f: F) -> io::Result<(String, usize)>
+where
+    F: FnMut(&str) -> io::Result<Option<String>>,
+{
+    let mut out = String::with_capacity(text.len());
+    let mut changed = 0;
+
+    for raw in text.split_inclusive('\n') {
+        let content = raw.trim_end_matches(['\r', '\n']);
+        let line_ending = &raw[content.len()..];
+
+        match split_assignment(content) {
+            Some((name, value)) => match f(value)? {
+                Some(new_value) => {
+                    out.push_str(name);
+                    out.push('=');
+                    out.push_str(&new_value);
+                    out.push_str(line_ending);
+                    changed += 1;
+                }
+                None => out.push_str(raw),
+            },
+            None => out.push_str(raw),
+        }
+    }
+
+    Ok((out, changed))
+}
+
+// `KEY=value` into `("KEY", "value")`, `None` for comments, blank lines and other lines without an `=`.
+// The name keeps any surrounding whitespace or `export ` prefix, the value is everything after the first `=`.
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    if line.trim_start().starts_with('#') {
+        return None;
+    }
+    line.split_once('=').filter(|(name, _)| !name.trim().is_empty())
+}
+
+fn apply(key: &[u8], data: &mut [u8]) -> io::Result<()> {
+    Ok(Rc4::apply_keystream_static(key, data)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nonce::OsNonceSource;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    const ENV: &str = "# Database settings\r\nDB_USER=admin\r\nDB_PASS=s3cr3t=with=equals\r\n\r\nexport API_TOKEN=\"abc def\"\nEMPTY=\n  # indented comment\nnot an assignment\n";
+
+    #[test]
+    fn round_trip_keeps_structure() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        let (encrypted, count) = encrypt_values(ENV, &KEY, &nonces).unwrap();
+        assert_eq!(count, 3);
+        assert!(has_encrypted_values(&encrypted));
+
+        let lines: Vec<&str> = encrypted.split_inclusive('\n').collect();
+        assert_eq!(lines[0], "# Database settings\r\n");
+        assert!(lines[1].starts_with("DB_USER=rc4:") && lines[1].ends_with("\r\n"));
+        assert!(lines[2].starts_with("DB_PASS=rc4:"));
+        assert_eq!(lines[3], "\r\n");
+        assert!(lines[4].starts_with("export API_TOKEN=rc4:"));
+        assert_eq!(lines[5..], ["EMPTY=\n", "  # indented comment\n", "not an assignment\n"]);
+        assert!(!encrypted.contains("admin") && !encrypted.contains("s3cr3t"));
+
+        let (decrypted, count) = decrypt_values(&encrypted, &KEY).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(decrypted, ENV);
+    }
+
+    #[test]
+    fn equal_values_encrypt_differently() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        let (encrypted, _) = encrypt_values("A=same\nB=same\n", &KEY, &nonces).unwrap();
+        let values: Vec<&str> = encrypted.lines().map(|l| l.split_once('=').unwrap().1).collect();
+        assert_ne!(values[0], values[1]);
+    }
+
+    #[test]
+    fn encrypted_values_are_not_encrypted_again() {
+        let nonces = NonceRegistry::new(OsNonceSource);
+        let (once, _) = encrypt_values("A=value\n", &KEY, &nonces).unwrap();
+        let (twice, count) = encrypt_values(&once, &KEY, &nonces).unwrap();
+        assert_eq!((twice, count), (once, 0));
+    }
+
+    #[test]
+    fn malformed_value_is_an_error() {
+        assert!(decrypt_values("A=rc4:not base64!\n", &KEY).is_err());
+        assert!(decrypt_values("A=rc4:AAAA\n", &KEY).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::header::Header;
+use crate::{entropy, looks_like_plaintext, printable_ratio, Action, Guess, Mode, Options, ENTROPY_MIN_LEN, ENTROPY_THRESHOLD};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `--explain`: what a run would do with one file and why, worked out the way `process_file`
+/// decides it, without writing anything
+#[derive(Debug)]
+pub struct Explanation {
+    pub path: PathBuf,
+    pub action: Action,
+    /// What drove `action`
+    pub reason: String,
+    /// The header found at the start of the file, if any (not looked for with `--encrypt`)
+    pub header: Option<Header>,
+    /// Share of printable text, in 0..=1
+    pub printable_ratio: f64,
+    /// Shannon entropy, in bits per byte
+    pub entropy: f64,
+    /// Where the key comes from, e.g. `--key (5 bytes)`
+    pub key_source: String,
+    /// Where the result would be written, `None` to replace the file
+    pub output_path: Option<PathBuf>,
+    /// Suffix of the backup the original would be kept as
+    pub backup: Option<String>,
+}
+
+/// Explain what would happen to `path`. `nonce` is whether `--nonce` is set
+pub fn explain(path: &Path, output_path: Option<&Path>, opts: &Options, nonce: bool, key_source: &str) -> io::Result<Explanation> {
+    let contents = fs::read(path)?;
+    let ratio = printable_ratio(&contents);
+    let entropy = entropy::shannon_entropy(&contents);
+
+    let header = match opts.mode {
+        Some(Mode::Encrypt) => None,
+        _ => Header::parse(&contents)?.map(|(header, _)| header),
+    };
+
+    let (action, reason) = match (&header, opts.mode) {
+        // `process_file` leaves empty files alone whatever the mode
+        _ if contents.is_empty() => (Action::Skip, "the file is empty".to_string()),
+        (_, Some(Mode::Encrypt)) => (Action::Encrypt, "--encrypt given, any header is ignored".to_string()),
+        (Some(_), _) => (Action::Decrypt, "the file starts with an rcli header".to_string()),
+        (None, Some(Mode::Decrypt)) => (Action::Decrypt, "--decrypt given".to_string()),
+        (None, None) if nonce || opts.writes_header() => {
+            (Action::Encrypt, "no rcli header, and the options given write one".to_string())
+        }
+        (None, None) => {
+            let mut counts = [0; 256];
+            entropy::count_bytes(&mut counts, &contents);
+            let text = looks_like_plaintext(&counts, opts.guess);
+            let action = if text { Action::Encrypt } else { Action::Decrypt };
+            let reason = match opts.guess {
+                Guess::Entropy if contents.len() as u64 >= ENTROPY_MIN_LEN => format!(
+                    "no rcli header, and {:.2} bits/byte of entropy is {} the {} bits/byte that reads as ciphertext",
+                    entropy,
+                    if text { "not above" } else { "above" },
+                    ENTROPY_THRESHOLD
+                ),
+                guess => format!(
+                    "no rcli header, and {:.1}% printable text is {} the 70% that reads as plaintext{}",
+                    100.0 * ratio,
+                    if text { "above" } else { "not above" },
+                    if guess == Guess::Entropy { " (too short to judge by entropy)" } else { "" }
+                ),
+            };
+            (action, reason)
+        }
+    };
+
+    Ok(Explanation {
+        path: path.to_path_buf(),
+        action,
+        reason,
+        header,
+        printable_ratio: ratio,
+        entropy,
+        key_source: key_source.to_string(),
+        output_path: output_path.map(Path::to_path_buf),
+        backup: opts.backup.clone(),
+    })
+}
+
+impl Explanation {
+    /// `--dry-run`'s line for the file, e.g. `Would encrypt a.txt` or `Would decrypt a.txt to out/a.txt`
+    pub fn dry_run(&self) -> String {
+        let mut line = match self.action {
+            Action::Encrypt => format!("Would encrypt {}", self.path.display()),
+            Action::Decrypt => format!("Would decrypt {}", self.path.display()),
+            Action::Skip => return format!("Would skip {}: empty", self.path.display()),
+        };
+        if let Some(output_path) = &self.output_path {
+            line.push_str(&format!(" to {}", output_path.display()));
+        }
+        line
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.path.display())?;
+        let action = match self.action {
+            Action::Encrypt => "encrypt",
+            Action::Decrypt => "decrypt",
+            Action::Skip => "skip",
+        };
+        writeln!(f, "  action:  {} ({})", action, self.reason)?;
+
+        match &self.header {
+            None => writeln!(f, "  header:  none")?,
+            Some(header) => {
+                let mut fields = Vec::new();
+                if header.nonce.is_some() {
+                    fields.push("nonce".to_string());
+                }
+                if let Some(layers) = header.layers {
+                    fields.push(format!("{} key layers", layers));
+                }
+                if header.crlf {
+                    fields.push("CRLF normalized".to_string());
+                }
+                if header.trailing_eol.is_some() {
+                    fields.push("trailing newline trimmed".to_string());
+                }
+                if let Some(block) = header.pad_to {
+                    fields.push(format!("padded to {} bytes", block));
+                }
+                if let Some(interval) = header.rekey_every {
+                    fields.push(format!("rekeyed every {} bytes", interval));
+                }
+                if let Some(len) = header.payload_len {
+                    fields.push(format!("{}-byte payload", len));
+                }
+                writeln!(f, "  header:  found ({})", fields.join(", "))?;
+            }
+        }
+
+        writeln!(f, "  content: {:.1}% printable, {:.2} bits/byte", 100.0 * self.printable_ratio, self.entropy)?;
+        writeln!(f, "  key:     {}", self.key_source)?;
+        match &self.output_path {
+            Some(output_path) => write!(f, "  output:  {}", output_path.display()),
+            None => match &self.backup {
+                Some(suffix) => write!(f, "  output:  {} (replaced, original kept as {})", self.path.display(), suffix),
+                None => write!(f, "  output:  {} (replaced)", self.path.display()),
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit::OpenFiles;
+    use crate::nonce::{NonceRegistry, RunNonceSource};
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    fn options() -> Options {
+        Options {
+            mode: None,
+            keys: vec![KEY.to_vec()],
+            transforms: Vec::new(),
+            skip_locked: false,
+            backup: None,
+            rekey_every: None,
+            verify: false,
+            open_files: OpenFiles::new(1),
+            seed: None,
+            normalize_key: false,
+            guess: Guess::Entropy,
+            header: false,
+        }
+    }
+
+    #[test]
+    fn explains_action_and_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"This is a secret").unwrap();
+
+        let explanation = explain(&path, None, &options(), false, "--key (5 bytes)").unwrap();
+        assert_eq!(explanation.action, Action::Encrypt);
+        assert!(explanation.header.is_none());
+        let text = explanation.to_string();
+        assert!(text.contains("action:  encrypt (no rcli header, and 100.0% printable"), "{}", text);
+        assert!(text.contains("header:  none"), "{}", text);
+
+        crate::process_file(&path, None, &options(), Some(&NonceRegistry::new(RunNonceSource::new(None)))).unwrap();
+        let explanation = explain(&path, Some(Path::new("out/file")), &options(), false, "--key (5 bytes)").unwrap();
+        assert_eq!(explanation.action, Action::Decrypt);
+        let text = explanation.to_string();
+        assert!(text.contains("action:  decrypt (the file starts with an rcli header)"), "{}", text);
+        assert!(text.contains("header:  found (nonce, 16-byte payload)"), "{}", text);
+        assert!(text.contains("output:  out/file"), "{}", text);
+    }
+}
@@ -0,0 +1,106 @@
+use rc4::framing::{Framer, LEN_PREFIX};
+use std::io::{self, BufRead, Read, Write};
+
+/// `rcli --frame`: stdin to stdout, each line framed and encrypted, or with `--decrypt`, frames
+/// decrypted back to one line each
+pub fn frame_stdio(key_bytes: &[u8], decrypt: bool) -> io::Result<()> {
+    let framer = Framer::new(key_bytes)?;
+    let (stdin, stdout) = (io::stdin(), io::stdout());
+    let mut output = io::BufWriter::new(stdout.lock());
+
+    if decrypt {
+        deframe(framer, stdin.lock(), &mut output)?;
+    } else {
+        frame_lines(framer, stdin.lock(), &mut output)?;
+    }
+    output.flush()
+}
+
+/// Frame every line of `input` (without its line ending) as one message
+pub fn frame_lines<R: BufRead, W: Write>(mut framer: Framer, input: R, output: &mut W) -> io::Result<()> {
+    let mut frame = Vec::new();
+    for line in input.split(b'\n') {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        frame.resize(LEN_PREFIX + line.len(), 0);
+        framer.frame_message(&line, &mut frame)?;
+        output.write_all(&frame)?;
+    }
+    Ok(())
+}
+
+/// Decrypt the frames read from `input`, writing each message as a line.
+/// Reads arrive in arbitrary pieces, so bytes are buffered until a whole frame is in.
+pub fn deframe<R: Read, W: Write>(mut framer: Framer, mut input: R, output: &mut W) -> io::Result<()> {
+    let mut pending = Vec::new();
+    let mut chunk = vec![0; 4096];
+    loop {
+        let bytes_read = input.read(&mut chunk)?;
+        if bytes_read == 0 {
+            break;
+        }
+        pending.extend_from_slice(&chunk[..bytes_read]);
+
+        let mut start = 0;
+        while let Some((message, used)) = framer.read_framed(&mut pending[start..]) {
+            output.write_all(message)?;
+            output.write_all(b"\n")?;
+            start += used;
+        }
+        pending.drain(..start);
+    }
+
+    if !pending.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("stream ended {} byte(s) into an incomplete frame", pending.len()),
+        ));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    // Hands out at most `n` bytes per read, like a slow pipe
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(buf.len()).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn lines_round_trip_through_partial_reads() {
+        let mut framed = Vec::new();
+        frame_lines(Framer::new(&KEY).unwrap(), &b"first message\r\nsecond\n"[..], &mut framed).unwrap();
+        assert_eq!(framed.len(), 2 * LEN_PREFIX + 13 + 6);
+
+        for n in [1, 3, 7, 4096] {
+            let mut out = Vec::new();
+            deframe(Framer::new(&KEY).unwrap(), Trickle(&framed, n), &mut out).unwrap();
+            assert_eq!(out, b"first message\nsecond\n");
+        }
+    }
+
+    #[test]
+    fn truncated_stream_is_an_error() {
+        let mut framed = Vec::new();
+        frame_lines(Framer::new(&KEY).unwrap(), &b"message"[..], &mut framed).unwrap();
+        framed.pop();
+
+        let err = deframe(Framer::new(&KEY).unwrap(), &framed[..], &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
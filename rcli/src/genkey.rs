@@ -0,0 +1,25 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::ValueEnum;
+use std::io;
+
+/// How `gen-key` prints the key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum KeyEncoding {
+    /// Hex digits, as `--key` and hex key files take them
+    Hex,
+    /// Base64, as `--key-base64` takes it
+    Base64,
+}
+
+/// `rcli gen-key`: `length` random bytes from the OS CSPRNG, printed on stdout as `encoding`
+pub fn gen_key(length: usize, encoding: KeyEncoding) -> io::Result<()> {
+    let mut key = vec![0; length];
+    getrandom::getrandom(&mut key)?;
+
+    match encoding {
+        KeyEncoding::Hex => println!("{}", key.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+        KeyEncoding::Base64 => println!("{}", STANDARD.encode(&key)),
+    }
+    Ok(())
+}
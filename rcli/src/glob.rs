@@ -0,0 +1,67 @@
+use std::path::Path;
+
+/// `--include`/`--exclude`: whether `path`, relative to the directory being walked, matches
+/// `pattern`.
+///
+/// `*` matches within a path component, `**` across components as well (`a/**/b` matches `a/b`
+/// too) and `?` any one character but `/`. A pattern without a `/` matches any component, so
+/// `*.log` catches a log file at any depth and `.git` everything inside a `.git` directory; one
+/// with a `/` has to match the whole path.
+pub fn matches(pattern: &str, path: &Path) -> bool {
+    let components: Vec<Vec<char>> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().chars().collect())
+        .collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    if !pattern.contains(&'/') {
+        return components.iter().any(|component| wildcard(&pattern, component));
+    }
+    let joined = components.join(&'/');
+    wildcard(&pattern, &joined)
+}
+
+// Backtracking match of a whole string; patterns are short, so the worst case doesn't matter
+fn wildcard(pattern: &[char], s: &[char]) -> bool {
+    match pattern {
+        [] => s.is_empty(),
+        // Zero or more whole components
+        ['*', '*', '/', rest @ ..] => {
+            wildcard(rest, s) || (0..s.len()).any(|i| s[i] == '/' && wildcard(rest, &s[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=s.len()).any(|i| wildcard(rest, &s[i..])),
+        ['*', rest @ ..] => (0..=s.len())
+            .take_while(|&i| i == 0 || s[i - 1] != '/')
+            .any(|i| wildcard(rest, &s[i..])),
+        ['?', rest @ ..] => matches!(s, [c, tail @ ..] if *c != '/' && wildcard(rest, tail)),
+        [c, rest @ ..] => matches!(s, [d, tail @ ..] if c == d && wildcard(rest, tail)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn component_patterns_match_at_any_depth() {
+        assert!(matches("*.log", Path::new("skip.log")));
+        assert!(matches("*.log", Path::new("logs/2024/skip.log")));
+        assert!(!matches("*.log", Path::new("skip.log.txt")));
+        assert!(matches(".git", Path::new(".git/objects/ab/cdef")));
+        assert!(matches("file?.txt", Path::new("dir/file1.txt")));
+        assert!(!matches("file?.txt", Path::new("dir/file10.txt")));
+    }
+
+    #[test]
+    fn path_patterns_match_the_whole_path() {
+        assert!(matches("src/*.rs", Path::new("src/main.rs")));
+        assert!(!matches("src/*.rs", Path::new("src/bin/main.rs")));
+        assert!(!matches("src/*.rs", Path::new("vendor/src/main.rs")));
+        assert!(matches("src/**/*.rs", Path::new("src/bin/tool/main.rs")));
+        assert!(matches("src/**/*.rs", Path::new("src/main.rs")));
+        assert!(matches("**/secret/*", Path::new("a/b/secret/key")));
+        assert!(matches("docs/**", Path::new("docs/a/b")));
+        assert!(!matches("s*/main.rs", Path::new("src/bin/main.rs")));
+    }
+}
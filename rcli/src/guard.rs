@@ -0,0 +1,104 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// System directories a recursive run refuses to walk without `--i-know-what-im-doing`, on top of
+/// any filesystem root and the user's home directory
+#[cfg(unix)]
+pub const HIGH_RISK_DIRS: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib64", "/opt", "/proc", "/root", "/sbin", "/srv", "/sys",
+    "/usr", "/var", "/Applications", "/Library", "/System", "/Users",
+];
+
+#[cfg(windows)]
+pub const HIGH_RISK_DIRS: &[&str] = &[r"C:\Windows", r"C:\Program Files", r"C:\Program Files (x86)", r"C:\Users"];
+
+#[cfg(not(any(unix, windows)))]
+pub const HIGH_RISK_DIRS: &[&str] = &[];
+
+/// Refuse to recurse into `target` if it is (after resolving symlinks and `..`) a filesystem
+/// root, `home` or one of `HIGH_RISK_DIRS`: one mistyped path would en/decrypt a whole system
+/// or every file its user owns.
+pub fn check_recursive_target(target: &Path, home: Option<&Path>) -> io::Result<()> {
+    // A target that doesn't resolve fails later, with the usual error for a missing path
+    let Ok(target) = target.canonicalize() else {
+        return Ok(());
+    };
+
+    let is_root = target.parent().is_none();
+    let is_home = home.and_then(|home| home.canonicalize().ok()).is_some_and(|home| home == target);
+    let is_listed = HIGH_RISK_DIRS
+        .iter()
+        .filter_map(|dir| Path::new(dir).canonicalize().ok())
+        .any(|dir| dir == target);
+
+    if is_root || is_home || is_listed {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "refusing to run recursively on {}, pass --i-know-what-im-doing if you really mean it",
+                target.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Ask on stderr before a recursive run modifies `count` files under `dir` in place, reading the
+/// answer as a line of `input`. Only `y` or `yes` goes ahead, anything else (no answer at all
+/// included) doesn't
+pub fn confirm<R: BufRead>(count: usize, dir: &Path, mut input: R) -> io::Result<bool> {
+    eprint!("About to modify {} file(s) under {} in place. Continue? [y/N] ", count, dir.display());
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The current user's home directory, if known
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_filesystem_root() {
+        let root = env::current_dir().unwrap().ancestors().last().unwrap().to_path_buf();
+        assert!(check_recursive_target(&root, None).is_err());
+    }
+
+    #[test]
+    fn refuses_home_however_spelled() {
+        let home = tempfile::tempdir().unwrap();
+        std::fs::create_dir(home.path().join("projects")).unwrap();
+
+        assert!(check_recursive_target(home.path(), Some(home.path())).is_err());
+        assert!(check_recursive_target(&home.path().join("projects/.."), Some(home.path())).is_err());
+        assert!(check_recursive_target(&home.path().join("projects"), Some(home.path())).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_system_dirs() {
+        assert!(check_recursive_target(Path::new("/etc"), None).is_err());
+        assert!(check_recursive_target(Path::new("/usr/.."), None).is_err());
+    }
+
+    #[test]
+    fn only_yes_confirms() {
+        for (answer, confirmed) in [("y\n", true), ("YES\r\n", true), ("n\n", false), ("yep\n", false), ("\n", false), ("", false)] {
+            assert_eq!(confirm(3, Path::new("dir"), answer.as_bytes()).unwrap(), confirmed, "{:?}", answer);
+        }
+    }
+
+    #[test]
+    fn missing_target_is_left_to_the_walk() {
+        assert!(check_recursive_target(Path::new("does/not/exist"), None).is_ok());
+    }
+}
@@ -0,0 +1,199 @@
+use std::io;
+
+// Magic bytes identifying a file written by `rcli` with a header
+pub const MAGIC: &[u8; 4] = b"RC4\x01";
+
+// Field tags. Each field is stored as `tag (u8) | len (u8) | value`, so new fields can be added
+// without changing the layout of existing ones.
+const TAG_NONCE: u8 = 0x01;
+const TAG_LAYERS: u8 = 0x02;
+const TAG_CRLF: u8 = 0x03;
+const TAG_TRAILING_EOL: u8 = 0x04;
+const TAG_PAD_TO: u8 = 0x05;
+const TAG_PAYLOAD_LEN: u8 = 0x06;
+const TAG_REKEY_EVERY: u8 = 0x07;
+const TAG_NORMALIZED_KEY: u8 = 0x08;
+
+pub const NONCE_LEN: usize = 16;
+
+pub const TRUNCATED: &str = "file appears truncated or corrupt";
+
+/// Metadata prepended to an encrypted file:
+///
+/// ```text
+/// magic (4 bytes) | fields length (u16, big-endian) | fields...
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Per-file random nonce mixed into the key
+    pub nonce: Option<[u8; NONCE_LEN]>,
+
+    /// Number of keys the payload was encrypted under (`--key-layer`), absent for a single key
+    pub layers: Option<u8>,
+
+    /// CRLF line endings were normalized to LF before encryption (`--normalize-eol`)
+    pub crlf: bool,
+
+    /// Line endings stripped from the end of the file before encryption (`--trim-trailing-newline`),
+    /// possibly none
+    pub trailing_eol: Option<Vec<u8>>,
+
+    /// The plaintext was padded to a multiple of this many bytes before encryption (`--pad-to`)
+    pub pad_to: Option<u8>,
+
+    /// Length of the encrypted payload following the header, so a truncated file is detected
+    /// rather than decrypted to partial plaintext. Absent in files written before it was added
+    pub payload_len: Option<u64>,
+
+    /// Every key was replaced by a derived one every this many bytes (`--rekey-every`)
+    pub rekey_every: Option<u64>,
+
+    /// Every key was hashed into a 32-byte one before use (`--normalize-key`)
+    pub normalized_key: bool,
+}
+
+impl Header {
+    /// Serialize the header, magic included
+    pub fn encode(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        if let Some(nonce) = &self.nonce {
+            push_field(&mut fields, TAG_NONCE, nonce);
+        }
+        if let Some(layers) = self.layers {
+            push_field(&mut fields, TAG_LAYERS, &[layers]);
+        }
+        if self.crlf {
+            push_field(&mut fields, TAG_CRLF, &[]);
+        }
+        if let Some(eol) = &self.trailing_eol {
+            push_field(&mut fields, TAG_TRAILING_EOL, eol);
+        }
+        if let Some(block) = self.pad_to {
+            push_field(&mut fields, TAG_PAD_TO, &[block]);
+        }
+        if let Some(len) = self.payload_len {
+            push_field(&mut fields, TAG_PAYLOAD_LEN, &len.to_be_bytes());
+        }
+        if let Some(interval) = self.rekey_every {
+            push_field(&mut fields, TAG_REKEY_EVERY, &interval.to_be_bytes());
+        }
+        if self.normalized_key {
+            push_field(&mut fields, TAG_NORMALIZED_KEY, &[]);
+        }
+
+        let mut out = Vec::with_capacity(MAGIC.len() + 2 + fields.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+        out.extend_from_slice(&fields);
+        out
+    }
+
+    /// Parse a header from the start of `data`.
+    /// Returns `Ok(None)` if `data` doesn't start with the magic bytes, otherwise the header and
+    /// the number of bytes it occupies.
+    pub fn parse(data: &[u8]) -> io::Result<Option<(Header, usize)>> {
+        if !data.starts_with(MAGIC) {
+            return Ok(None);
+        }
+
+        let rest = &data[MAGIC.len()..];
+        if rest.len() < 2 {
+            return Err(invalid(TRUNCATED));
+        }
+        let fields_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let mut fields = rest
+            .get(2..2 + fields_len)
+            .ok_or_else(|| invalid(TRUNCATED))?;
+
+        let mut header = Header::default();
+        while !fields.is_empty() {
+            if fields.len() < 2 {
+                return Err(invalid(TRUNCATED));
+            }
+            let (tag, len) = (fields[0], fields[1] as usize);
+            let value = fields
+                .get(2..2 + len)
+                .ok_or_else(|| invalid(TRUNCATED))?;
+
+            match tag {
+                TAG_NONCE => {
+                    let nonce = value.try_into().map_err(|_| invalid("bad nonce length"))?;
+                    header.nonce = Some(nonce);
+                }
+                TAG_LAYERS => match value {
+                    [layers] => header.layers = Some(*layers),
+                    _ => return Err(invalid("bad layer count length")),
+                },
+                TAG_CRLF => header.crlf = true,
+                TAG_TRAILING_EOL => header.trailing_eol = Some(value.to_vec()),
+                TAG_PAD_TO => match value {
+                    [block] if *block > 0 => header.pad_to = Some(*block),
+                    _ => return Err(invalid("bad padding block size")),
+                },
+                TAG_PAYLOAD_LEN => {
+                    let len = value.try_into().map_err(|_| invalid("bad payload length"))?;
+                    header.payload_len = Some(u64::from_be_bytes(len));
+                }
+                TAG_REKEY_EVERY => match value.try_into().map(u64::from_be_bytes) {
+                    Ok(interval) if interval > 0 => header.rekey_every = Some(interval),
+                    _ => return Err(invalid("bad rekey interval")),
+                },
+                TAG_NORMALIZED_KEY => header.normalized_key = true,
+                // An unknown field may change how the payload has to be decrypted, so don't guess
+                _ => return Err(invalid(&format!("unknown header field 0x{:02x}", tag))),
+            }
+            fields = &fields[2 + len..];
+        }
+
+        Ok(Some((header, MAGIC.len() + 2 + fields_len)))
+    }
+}
+
+fn push_field(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.push(value.len() as u8);
+    out.extend_from_slice(value);
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid rcli header: {}", msg))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let header = Header {
+            nonce: Some([0xab; NONCE_LEN]),
+            layers: Some(3),
+            crlf: true,
+            trailing_eol: Some(b"\r\n\n".to_vec()),
+            pad_to: Some(16),
+            payload_len: Some(7),
+            rekey_every: Some(1 << 20),
+            normalized_key: true,
+        };
+        let mut data = header.encode();
+        let header_len = data.len();
+        data.extend_from_slice(b"payload");
+
+        let (parsed, len) = Header::parse(&data).unwrap().unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(len, header_len);
+        assert_eq!(&data[len..], b"payload");
+    }
+
+    #[test]
+    fn no_magic_is_not_a_header() {
+        assert!(Header::parse(b"This is a secret").unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_header_is_an_error() {
+        let data = Header { nonce: Some([0xab; NONCE_LEN]), ..Default::default() }.encode();
+        assert!(Header::parse(&data[..data.len() - 1]).is_err());
+    }
+}
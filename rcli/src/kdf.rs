@@ -0,0 +1,130 @@
+use sha2::{Digest, Sha256};
+use std::io::{self, BufRead, IsTerminal};
+
+/// PBKDF2 rounds for `--passphrase`. Changing it changes every passphrase's key
+pub const ITERATIONS: u32 = 100_000;
+
+/// Length of a key derived from a passphrase
+pub const KEY_LEN: usize = 16;
+
+// Salt for `--passphrase`. It's fixed rather than random so the key can be derived before any file
+// is read; `--nonce` is what keeps the same passphrase from giving every file the same keystream
+const SALT: &[u8] = b"rcli-passphrase-v1";
+
+const BLOCK_LEN: usize = 64;
+
+/// The RC4 key for `passphrase`: PBKDF2-HMAC-SHA256 over it with a fixed salt and `ITERATIONS` rounds
+pub fn passphrase_key(passphrase: &str) -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    pbkdf2_sha256(passphrase.as_bytes(), SALT, ITERATIONS, &mut key);
+    key
+}
+
+/// Ask for the passphrase of a `--passphrase` given no value. On a terminal it's read with echo
+/// off, twice when `confirm` is set so a typo can't lock a file away; otherwise it's the first line
+/// of stdin
+pub fn read_passphrase(confirm: bool) -> io::Result<String> {
+    let passphrase = if io::stdin().is_terminal() {
+        let term = console::Term::stderr();
+        let prompt = |text: &str| -> io::Result<String> {
+            term.write_str(text)?;
+            term.read_secure_line()
+        };
+        let passphrase = prompt("Passphrase: ")?;
+        if confirm && prompt("Repeat the passphrase: ")? != passphrase {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the passphrases don't match"));
+        }
+        passphrase
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        line.truncate(line.trim_end_matches(['\r', '\n']).len());
+        line
+    };
+
+    match passphrase.is_empty() {
+        true => Err(io::Error::new(io::ErrorKind::InvalidInput, "the passphrase can't be empty")),
+        false => Ok(passphrase),
+    }
+}
+
+/// PBKDF2 (RFC 8018) with HMAC-SHA256 as the PRF, filling `out`
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let prf = Hmac::new(password);
+    for (i, block) in out.chunks_mut(32).enumerate() {
+        let mut u = prf.mac(&[salt, &(i as u32 + 1).to_be_bytes()]);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = prf.mac(&[&u]);
+            t.iter_mut().zip(&u).for_each(|(t, u)| *t ^= u);
+        }
+        block.copy_from_slice(&t[..block.len()]);
+    }
+}
+
+// HMAC-SHA256 (RFC 2104) under a fixed key, with the padded keys hashed once up front
+struct Hmac {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl Hmac {
+    fn new(key: &[u8]) -> Self {
+        let mut block = [0; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            block[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let pad = |byte: u8| {
+            let mut hasher = Sha256::new();
+            hasher.update(block.map(|b| b ^ byte));
+            hasher
+        };
+        Hmac { inner: pad(0x36), outer: pad(0x5c) }
+    }
+
+    fn mac(&self, message: &[&[u8]]) -> [u8; 32] {
+        let mut inner = self.inner.clone();
+        message.iter().for_each(|part| inner.update(part));
+        let mut outer = self.outer.clone();
+        outer.update(inner.finalize());
+        outer.finalize().into()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn pbkdf2_matches_known_vectors() {
+        // PBKDF2-HMAC-SHA256 vectors for P = "password", S = "salt"
+        for (iterations, expected) in [
+            (1, "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+            (2, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+            (4096, "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"),
+        ] {
+            let mut out = [0; 32];
+            pbkdf2_sha256(b"password", b"salt", iterations, &mut out);
+            assert_eq!(hex(&out), expected, "{} iterations", iterations);
+        }
+
+        // RFC 7914, section 11: more than one block, the second one cut short
+        let mut out = [0; 40];
+        pbkdf2_sha256(b"passwd", b"salt", 1, &mut out);
+        assert_eq!(hex(&out), "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645");
+    }
+
+    #[test]
+    fn passphrase_key_is_reproducible() {
+        assert_eq!(passphrase_key("correct horse"), passphrase_key("correct horse"));
+        assert_ne!(passphrase_key("correct horse"), passphrase_key("correct horsf"));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::keyfile::{self, KeyFormat};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rc4::Rc4;
+use std::io;
+
+/// Read the key a key source names: `hex:<hex bytes>`, `base64:<base64>`, `file:<path>` (a key
+/// file, its format auto-detected as for `--key-file`) or, with the `passphrase` feature,
+/// `passphrase:<text>` (the key `--passphrase` derives from it)
+pub fn read_key_source(spec: &str) -> io::Result<Vec<u8>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    match spec.split_once(':') {
+        Some(("hex", hex)) => keyfile::parse_key_file(hex.as_bytes(), KeyFormat::Hex).map(|(key, _)| key),
+        Some(("base64", text)) => STANDARD.decode(text.trim()).map_err(|e| invalid(format!("{}: invalid base64: {}", spec, e))),
+        Some(("file", path)) => keyfile::read_key_file(path, KeyFormat::Auto),
+        #[cfg(feature = "passphrase")]
+        Some(("passphrase", "")) => Err(invalid(format!("{}: the passphrase can't be empty", spec))),
+        #[cfg(feature = "passphrase")]
+        Some(("passphrase", passphrase)) => Ok(crate::kdf::passphrase_key(passphrase).to_vec()),
+        _ => Err(invalid(format!("{}: expected a key source like hex:4b8e298780, base64:S44ph4A= or file:PATH", spec))),
+    }
+}
+
+// Keystream bytes generated and compared at a time, so `--bytes` up to `u32::MAX` needs no more memory
+const CHUNK_SIZE: usize = 4096;
+
+/// Compare the first `len` keystream bytes of two keys, returning the offset of the first byte
+/// that differs, or `None` if they all match
+pub fn first_difference(first: &[u8], second: &[u8], len: usize) -> io::Result<Option<usize>> {
+    let (mut first, mut second) = (Rc4::new(first)?, Rc4::new(second)?);
+    let (mut a, mut b) = ([0; CHUNK_SIZE], [0; CHUNK_SIZE]);
+    let mut offset = 0;
+    while offset < len {
+        let n = CHUNK_SIZE.min(len - offset);
+        first.fill_keystream(&mut a[..n]);
+        second.fill_keystream(&mut b[..n]);
+        if let Some(i) = a[..n].iter().zip(&b[..n]).position(|(x, y)| x != y) {
+            return Ok(Some(offset + i));
+        }
+        offset += n;
+    }
+    Ok(None)
+}
+
+/// `rcli keystream-only-verify`: check that two key sources give the same cipher, without touching any file
+pub fn verify(first: &str, second: &str, len: usize) -> io::Result<()> {
+    let (first_key, second_key) = (read_key_source(first)?, read_key_source(second)?);
+    match first_difference(&first_key, &second_key, len)? {
+        None => {
+            println!("Keystreams match over the first {} bytes", len);
+            Ok(())
+        }
+        Some(offset) => Err(io::Error::other(format!("keystreams differ, first at byte {} of {}", offset, len))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_and_base64_of_one_key_match() {
+        let hex = read_key_source("hex:4b8e298780").unwrap();
+        let spaced = read_key_source("hex:0x4b 0x8e 0x29 0x87 0x80").unwrap();
+        let base64 = read_key_source("base64:S44ph4A=").unwrap();
+        assert_eq!(hex, [0x4b, 0x8e, 0x29, 0x87, 0x80]);
+        assert_eq!(hex, spaced);
+        assert_eq!(first_difference(&hex, &base64, 1024).unwrap(), None);
+    }
+
+    #[test]
+    fn different_keys_differ() {
+        let offset = first_difference(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &[0x4b, 0x8e, 0x29, 0x87, 0x81], 1024).unwrap();
+        assert!(offset.is_some());
+
+        // The key schedule cycles through the key, so a key repeated is the same key, however it looks
+        assert_eq!(first_difference(&[1, 2, 3, 4, 5], &[1, 2, 3, 4, 5, 1, 2, 3, 4, 5], 1024).unwrap(), None);
+        assert!(read_key_source("4b8e298780").is_err());
+        assert!(first_difference(&[1, 2, 3], &[1, 2, 3], 16).is_err());
+    }
+
+    #[cfg(feature = "passphrase")]
+    #[test]
+    fn passphrase_source_is_the_derived_key() {
+        let derived = read_key_source("passphrase:correct horse").unwrap();
+        assert_eq!(derived, crate::kdf::passphrase_key("correct horse"));
+        // Everything after the first `:` is the passphrase
+        assert_eq!(read_key_source("passphrase:a:b").unwrap(), crate::kdf::passphrase_key("a:b"));
+        assert!(read_key_source("passphrase:").is_err());
+    }
+
+    #[test]
+    fn comparison_runs_across_chunks() {
+        let len = 3 * CHUNK_SIZE + 10;
+        assert_eq!(first_difference(&[1, 2, 3, 4, 5], &[1, 2, 3, 4, 5, 1, 2, 3, 4, 5], len).unwrap(), None);
+
+        // Same answer as comparing the whole keystreams at once
+        let mut changed = [0x5a; 256];
+        changed[255] = 0x5b;
+        let (mut a, mut b) = (vec![0; len], vec![0; len]);
+        Rc4::apply_keystream_static(&[0x5a; 256], &mut a).unwrap();
+        Rc4::apply_keystream_static(&changed, &mut b).unwrap();
+        let expected = a.iter().zip(&b).position(|(x, y)| x != y);
+        assert_eq!(first_difference(&[0x5a; 256], &changed, len).unwrap(), expected);
+    }
+}
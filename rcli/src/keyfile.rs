@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+
+/// How the contents of a `--key-file` are interpreted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum KeyFormat {
+    /// Hex text if the file is valid hex, raw bytes otherwise
+    Auto,
+    /// Hex text, e.g. `4b8e298780` or `0x4b 0x8e 0x29 0x87 0x80`
+    Hex,
+    /// The file's bytes are the key, as-is
+    Raw,
+}
+
+/// Read a key from `path`
+pub fn read_key_file(path: &str, format: KeyFormat) -> io::Result<Vec<u8>> {
+    let contents = fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+
+    let (key, ambiguous) = parse_key_file(&contents, format)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    if ambiguous {
+        eprintln!(
+            "Warning: key file {} could be hex text or raw bytes, reading it as hex (use --key-format raw to read it as raw bytes)",
+            path
+        );
+    }
+
+    Ok(key)
+}
+
+/// Interpret key file contents, returning the key and whether the auto-detection was ambiguous
+pub fn parse_key_file(contents: &[u8], format: KeyFormat) -> io::Result<(Vec<u8>, bool)> {
+    // Hex text as `rc4::decode_hex` reads it: whitespace-separated tokens, each with an optional `0x` prefix
+    let hex = || -> io::Result<Vec<u8>> {
+        let not_hex = |reason: String| io::Error::new(io::ErrorKind::InvalidData, format!("key file is not valid hex: {}", reason));
+        let text = std::str::from_utf8(contents).map_err(|_| not_hex("not UTF-8 text".to_string()))?;
+        let mut key = vec![0; text.len() / 2];
+        let len = rc4::decode_hex(text, &mut key).map_err(|e| not_hex(io::Error::from(e).to_string()))?;
+        if len == 0 {
+            return Err(not_hex("no hex digits".to_string()));
+        }
+        key.truncate(len);
+        Ok(key)
+    };
+
+    match format {
+        KeyFormat::Raw => Ok((contents.to_vec(), false)),
+        KeyFormat::Hex => hex().map(|key| (key, false)),
+        KeyFormat::Auto => match hex() {
+            // Whitespace, a newline or `0x` prefixes mark the file as text. A bare run of hex digits
+            // is also a perfectly good raw key, so auto-detection can only guess.
+            Ok(key) => {
+                let bare = contents.iter().all(u8::is_ascii_hexdigit);
+                Ok((key, bare))
+            }
+            Err(_) => Ok((contents.to_vec(), false)),
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    #[test]
+    fn auto_detects_hex_text() {
+        for text in ["4b8e298780\n", "0x4b 0x8e 0x29 0x87 0x80\n", "4b 8e\n29 87 80"] {
+            assert_eq!(parse_key_file(text.as_bytes(), KeyFormat::Auto).unwrap(), (KEY.to_vec(), false));
+        }
+    }
+
+    #[test]
+    fn auto_detects_raw_bytes() {
+        assert_eq!(parse_key_file(&KEY, KeyFormat::Auto).unwrap(), (KEY.to_vec(), false));
+
+        // Odd number of hex digits isn't hex text
+        assert_eq!(parse_key_file(b"4b8e2", KeyFormat::Auto).unwrap(), (b"4b8e2".to_vec(), false));
+    }
+
+    #[test]
+    fn bare_hex_digits_are_ambiguous() {
+        assert_eq!(parse_key_file(b"4b8e298780", KeyFormat::Auto).unwrap(), (KEY.to_vec(), true));
+        assert_eq!(parse_key_file(b"4b8e298780", KeyFormat::Raw).unwrap(), (b"4b8e298780".to_vec(), false));
+        assert_eq!(parse_key_file(b"4b8e298780", KeyFormat::Hex).unwrap(), (KEY.to_vec(), false));
+    }
+
+    #[test]
+    fn forced_hex_rejects_binary() {
+        assert!(parse_key_file(&KEY, KeyFormat::Hex).is_err());
+        let err = parse_key_file(b"4b8e2", KeyFormat::Hex).unwrap_err();
+        assert!(err.to_string().starts_with("key file is not valid hex: odd number of hex digits"), "{}", err);
+    }
+}
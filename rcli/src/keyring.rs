@@ -0,0 +1,180 @@
+use crate::keyfile::{self, KeyFormat};
+use rc4::Rc4;
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+
+/// A secret store holding keys under a service and an account
+pub trait SecretStore {
+    /// The secret stored for `service`/`account`, `None` if there isn't one
+    fn get(&self, service: &str, account: &str) -> io::Result<Option<String>>;
+
+    /// Store `secret` for `service`/`account`, replacing any previous one
+    fn set(&self, service: &str, account: &str, secret: &str) -> io::Result<()>;
+}
+
+/// The desktop's secret store (GNOME Keyring, KWallet, ...) over the Secret Service API, through
+/// libsecret's `secret-tool`. Secrets go through its stdin and stdout, never its arguments.
+/// This shells out rather than using the `keyring` crate, which would pull in a D-Bus stack
+pub struct OsKeyring;
+
+impl OsKeyring {
+    fn secret_tool(args: &[&str]) -> io::Result<std::process::Child> {
+        Command::new("secret-tool")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("the OS keyring needs secret-tool (libsecret) installed: {}", e)))
+    }
+
+    // What a finished `secret-tool lookup` found. It exits with 1 and prints nothing at all when
+    // there's no such secret; any other failure (no Secret Service running, a locked collection
+    // the user refused to unlock, ...) says why on stderr
+    fn lookup_result(output: Output) -> io::Result<Option<String>> {
+        if !output.status.success() {
+            if output.status.code() == Some(1) && output.stdout.is_empty() && output.stderr.is_empty() {
+                return Ok(None);
+            }
+            return Err(Self::failure("look up the key", &output));
+        }
+        String::from_utf8(output.stdout)
+            .map(Some)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the keyring entry is not text"))
+    }
+
+    fn failure(action: &str, output: &Output) -> io::Error {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => io::Error::other(format!("secret-tool failed to {} ({})", action, output.status)),
+            reason => io::Error::other(format!("secret-tool failed to {}: {}", action, reason)),
+        }
+    }
+}
+
+impl SecretStore for OsKeyring {
+    fn get(&self, service: &str, account: &str) -> io::Result<Option<String>> {
+        let output = Self::secret_tool(&["lookup", "service", service, "account", account])?.wait_with_output()?;
+        Self::lookup_result(output)
+    }
+
+    fn set(&self, service: &str, account: &str, secret: &str) -> io::Result<()> {
+        let label = format!("rcli key {}/{}", service, account);
+        let mut child = Self::secret_tool(&["store", "--label", &label, "service", service, "account", account])?;
+        // Closing stdin tells it the secret is complete
+        child.stdin.take().expect("stdin is piped").write_all(secret.as_bytes())?;
+        let output = child.wait_with_output()?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(Self::failure("store the key", &output)),
+        }
+    }
+}
+
+/// Split a `SERVICE/ACCOUNT` entry name at its first `/`
+pub fn parse_entry(entry: &str) -> io::Result<(&str, &str)> {
+    match entry.split_once('/') {
+        Some((service, account)) if !service.is_empty() && !account.is_empty() => Ok((service, account)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("keyring entry '{}' is not SERVICE/ACCOUNT", entry))),
+    }
+}
+
+/// Store `key` as hex text under `entry`, refusing a key RC4 can't use
+pub fn store_key(store: &impl SecretStore, entry: &str, key: &[u8]) -> io::Result<()> {
+    let (service, account) = parse_entry(entry)?;
+    Rc4::new(key)?;
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    store.set(service, account, &hex)
+}
+
+/// The key stored under `entry`, checked to be one RC4 can use
+pub fn retrieve_key(store: &impl SecretStore, entry: &str) -> io::Result<Vec<u8>> {
+    let (service, account) = parse_entry(entry)?;
+    let secret = store
+        .get(service, account)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no key in the keyring for '{}'", entry)))?;
+    let (key, _) = keyfile::parse_key_file(secret.as_bytes(), KeyFormat::Hex)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("the keyring entry '{}' is not a hex key", entry)))?;
+    Rc4::new(&key)?;
+    Ok(key)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    // An in-memory stand-in for the OS keyring
+    #[derive(Default)]
+    struct MockStore(RefCell<HashMap<(String, String), String>>);
+
+    impl SecretStore for MockStore {
+        fn get(&self, service: &str, account: &str) -> io::Result<Option<String>> {
+            Ok(self.0.borrow().get(&(service.to_string(), account.to_string())).cloned())
+        }
+
+        fn set(&self, service: &str, account: &str, secret: &str) -> io::Result<()> {
+            self.0.borrow_mut().insert((service.to_string(), account.to_string()), secret.to_string());
+            Ok(())
+        }
+    }
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    #[test]
+    fn stored_key_is_retrieved_and_encrypts_the_same() {
+        let store = MockStore::default();
+        store_key(&store, "rcli/backups", &KEY).unwrap();
+        assert_eq!(store.get("rcli", "backups").unwrap().unwrap(), "4b8e298780");
+
+        let key = retrieve_key(&store, "rcli/backups").unwrap();
+        let (mut with_stored, mut with_original) = (b"This is a secret".to_vec(), b"This is a secret".to_vec());
+        Rc4::apply_keystream_static(&key, &mut with_stored).unwrap();
+        Rc4::apply_keystream_static(&KEY, &mut with_original).unwrap();
+        assert_eq!(with_stored, with_original);
+    }
+
+    #[test]
+    fn key_lengths_are_checked_both_ways() {
+        let store = MockStore::default();
+        assert_eq!(store_key(&store, "rcli/short", &[1, 2, 3]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(store.get("rcli", "short").unwrap().is_none());
+
+        store.set("rcli", "short", "010203").unwrap();
+        assert_eq!(retrieve_key(&store, "rcli/short").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn missing_and_malformed_entries_are_refused() {
+        let store = MockStore::default();
+        assert_eq!(retrieve_key(&store, "rcli/missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+        store.set("rcli", "text", "not hex").unwrap();
+        assert_eq!(retrieve_key(&store, "rcli/text").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        for entry in ["rcli", "/account", "service/"] {
+            assert!(parse_entry(entry).is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_a_silent_lookup_failure_means_no_secret() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        // Wait statuses carry the exit code in their second byte
+        let output = |code: i32, stdout: &str, stderr: &str| Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+
+        assert_eq!(OsKeyring::lookup_result(output(0, "4b8e298780", "")).unwrap().as_deref(), Some("4b8e298780"));
+        assert_eq!(OsKeyring::lookup_result(output(1, "", "")).unwrap(), None);
+
+        let error = OsKeyring::lookup_result(output(1, "", "Cannot autolaunch D-Bus without X11 $DISPLAY\n")).unwrap_err();
+        assert!(error.to_string().contains("Cannot autolaunch D-Bus"), "{}", error);
+        assert!(OsKeyring::lookup_result(output(2, "", "")).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::apply_keystream;
+use crate::header::Header;
+use crate::nonce;
+use crate::rekey;
+use rc4::io::Rc4Writer;
+use rc4::Rc4;
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+
+/// Effective RC4 key of every layer, outermost last, for a payload written with `header`:
+/// normalized if it says so, then mixed with its nonce, if any.
+///
+/// Layering RC4 doesn't stack security the way it sounds: every layer is a keystream XORed over
+/// the data, XOR is commutative, and the layers collapse into a single combined keystream. Worse,
+/// the same key given twice would cancel itself out and leave plaintext. So with more than one
+/// layer, each key is bound to its position, `SHA-256(key || layer index)`. That keeps repeated
+/// keys from cancelling, and means the keys only decrypt when given in the order used to encrypt.
+pub fn layer_keys(keys: &[Vec<u8>], header: &Header) -> Vec<Vec<u8>> {
+    keys.iter()
+        .enumerate()
+        .map(|(index, key)| {
+            let key = match header.normalized_key {
+                true => normalize_key(key),
+                false => key.clone(),
+            };
+            let key = match &header.nonce {
+                Some(nonce) => nonce::derive_key(&key, nonce).to_vec(),
+                None => key,
+            };
+            if keys.len() > 1 {
+                let mut hasher = Sha256::new();
+                hasher.update(&key);
+                hasher.update([index as u8]);
+                hasher.finalize().to_vec()
+            } else {
+                key
+            }
+        })
+        .collect()
+}
+
+/// `--normalize-key`: a key of any length hashed into a 32-byte one RC4 accepts, `SHA-256(key)`.
+/// This only fixes the length: a 3-byte key is still as easy to guess as 3 bytes are
+pub fn normalize_key(key: &[u8]) -> Vec<u8> {
+    Sha256::digest(key).to_vec()
+}
+
+/// Encrypt through each layer in order, each rekeyed every `rekey_every` bytes if given.
+/// Every layer is a keystream XORed over the data, so running this again decrypts
+pub fn encrypt(layer_keys: &[Vec<u8>], rekey_every: Option<u64>, data: &mut [u8]) -> io::Result<()> {
+    for key in layer_keys {
+        apply_layer(key, rekey_every, data)?;
+    }
+    Ok(())
+}
+
+fn apply_layer(key: &[u8], rekey_every: Option<u64>, data: &mut [u8]) -> io::Result<()> {
+    match rekey_every {
+        Some(interval) => rekey::apply(key, interval, data),
+        None => apply_keystream(key, data),
+    }
+}
+
+/// One layer's keystream as it continues from call to call, for en/decrypting a stream piece by
+/// piece. With `--rekey-every`, it switches to the next segment's key at every boundary the same
+/// way `rekey::apply` does
+pub struct LayerStream {
+    key: Vec<u8>,
+    rekey_every: Option<u64>,
+    cipher: Rc4,
+    segment: u64,
+    // Bytes of the current segment used up
+    used: u64,
+}
+
+impl LayerStream {
+    pub fn new(key: Vec<u8>, rekey_every: Option<u64>) -> io::Result<Self> {
+        let cipher = match rekey_every {
+            Some(_) => Rc4::new(&rekey::segment_key(&key, 0))?,
+            None => Rc4::new(&key)?,
+        };
+        Ok(LayerStream { key, rekey_every, cipher, segment: 0, used: 0 })
+    }
+
+    pub fn apply(&mut self, mut data: &mut [u8]) -> io::Result<()> {
+        let Some(interval) = self.rekey_every else {
+            self.cipher.apply_keystream(data);
+            return Ok(());
+        };
+
+        while !data.is_empty() {
+            if self.used == interval {
+                self.segment += 1;
+                self.used = 0;
+                self.cipher = Rc4::new(&rekey::segment_key(&self.key, self.segment))?;
+            }
+            let take = usize::try_from(interval - self.used).unwrap_or(usize::MAX).min(data.len());
+            let (now, rest) = data.split_at_mut(take);
+            self.cipher.apply_keystream(now);
+            self.used += take as u64;
+            data = rest;
+        }
+        Ok(())
+    }
+}
+
+
+/// One layer's keystream applied to everything written through it, for encrypting a stream on its
+/// way into `inner`: an `rc4::io::Rc4Writer` under the layer's key, or with `--rekey-every` under
+/// each segment's key in turn, replaced at every boundary the way `rekey::apply` switches keys
+pub struct LayerWriter<W: Write> {
+    key: Vec<u8>,
+    rekey_every: Option<u64>,
+    // Only `None` while it's being replaced at a segment boundary
+    writer: Option<Rc4Writer<W>>,
+    segment: u64,
+    // Bytes of the current segment written
+    used: u64,
+}
+
+impl<W: Write> LayerWriter<W> {
+    pub fn new(key: Vec<u8>, rekey_every: Option<u64>, inner: W) -> io::Result<Self> {
+        let writer = match rekey_every {
+            Some(_) => Rc4Writer::new(&rekey::segment_key(&key, 0), inner)?,
+            None => Rc4Writer::new(&key, inner)?,
+        };
+        Ok(LayerWriter { key, rekey_every, writer: Some(writer), segment: 0, used: 0 })
+    }
+}
+
+impl<W: Write> Write for LayerWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let writer = self.writer.as_mut().expect("replaced at the last segment boundary");
+        let Some(interval) = self.rekey_every else {
+            return writer.write(buf);
+        };
+
+        if self.used == interval {
+            let inner = self.writer.take().expect("replaced at the last segment boundary").into_inner();
+            self.segment += 1;
+            self.used = 0;
+            self.writer = Some(Rc4Writer::new(&rekey::segment_key(&self.key, self.segment), inner)?);
+        }
+        // A write never crosses a boundary, `write_all` comes back for the rest
+        let take = usize::try_from(interval - self.used).unwrap_or(usize::MAX).min(buf.len());
+        let n = self.writer.as_mut().expect("just replaced").write(&buf[..take])?;
+        self.used += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().expect("replaced at the last segment boundary").flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+    const KEY_B: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+
+    #[test]
+    fn single_layer_is_plain_rc4() {
+        let keys = layer_keys(&[KEY_A.to_vec()], &Header::default());
+        assert_eq!(keys, [KEY_A.to_vec()]);
+    }
+
+    #[test]
+    fn repeated_key_does_not_cancel() {
+        let keys = layer_keys(&[KEY_A.to_vec(), KEY_A.to_vec()], &Header::default());
+        let mut data = *b"This is a secret";
+        encrypt(&keys, None, &mut data).unwrap();
+        assert_ne!(&data, b"This is a secret");
+
+        encrypt(&keys, None, &mut data).unwrap();
+        assert_eq!(&data, b"This is a secret");
+    }
+
+    #[test]
+    fn swapped_keys_do_not_decrypt() {
+        let mut data = *b"This is a secret";
+        encrypt(&layer_keys(&[KEY_A.to_vec(), KEY_B.to_vec()], &Header::default()), None, &mut data).unwrap();
+
+        let mut swapped = data;
+        encrypt(&layer_keys(&[KEY_B.to_vec(), KEY_A.to_vec()], &Header::default()), None, &mut swapped).unwrap();
+        assert_ne!(&swapped, b"This is a secret");
+    }
+
+    #[test]
+    fn writer_matches_the_in_memory_layers() {
+        let original: Vec<u8> = (0..5000u32).map(|n| (n % 251) as u8).collect();
+        for rekey_every in [None, Some(1), Some(777), Some(4096), Some(5000)] {
+            let keys = layer_keys(&[KEY_A.to_vec(), KEY_B.to_vec()], &Header { rekey_every, ..Default::default() });
+            let mut expected = original.clone();
+            encrypt(&keys, rekey_every, &mut expected).unwrap();
+
+            // Written in uneven pieces, through both layers stacked
+            let mut out = Vec::new();
+            let inner = LayerWriter::new(keys[0].clone(), rekey_every, &mut out).unwrap();
+            let mut writer = LayerWriter::new(keys[1].clone(), rekey_every, inner).unwrap();
+            for piece in original.chunks(333) {
+                writer.write_all(piece).unwrap();
+            }
+            drop(writer);
+            assert!(out == expected, "{:?}", rekey_every);
+        }
+    }
+
+    #[test]
+    fn normalized_keys_of_any_length_round_trip() {
+        let header = Header { normalized_key: true, ..Default::default() };
+        for key in [vec![0x4b, 0x8e, 0x29], vec![0xa5; 500]] {
+            let keys = layer_keys(std::slice::from_ref(&key), &header);
+            assert_eq!(keys, [normalize_key(&key)]);
+            assert_eq!(keys[0].len(), 32);
+
+            let mut data = *b"This is a secret";
+            encrypt(&keys, None, &mut data).unwrap();
+            assert_ne!(&data, b"This is a secret");
+            encrypt(&layer_keys(&[key], &header), None, &mut data).unwrap();
+            assert_eq!(&data, b"This is a secret");
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::{Condvar, Mutex};
+
+/// Most descriptors processing one file holds open at once: the locked source, plus either the
+/// backup and a second handle on the source while copying it, or the temp file the result goes to
+pub const FILES_PER_TASK: usize = 3;
+
+/// `--max-open-files` when not given, well below the usual soft limits (256 on macOS, 1024 on Linux)
+pub const DEFAULT_MAX_OPEN_FILES: u32 = 64;
+
+/// `--max-open-files`: bounds how many files are processed at once, so a run over a huge tree
+/// can't exhaust the process's file descriptors and fail with `Too many open files` halfway.
+///
+/// A counting semaphore: each file takes a `Permit` for as long as it has anything open, so the
+/// bound holds however many `--jobs` workers process files at once.
+pub struct OpenFiles {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl OpenFiles {
+    /// Allow as many files at once as fit in `max_open` descriptors, at least one
+    pub fn new(max_open: usize) -> Self {
+        OpenFiles { available: Mutex::new((max_open / FILES_PER_TASK).max(1)), released: Condvar::new() }
+    }
+
+    /// Wait for a file's worth of descriptors to be free and take them
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        Permit(self)
+    }
+}
+
+/// A file's share of `OpenFiles`, handed back when dropped
+pub struct Permit<'a>(&'a OpenFiles);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn limit_leaves_room_for_every_descriptor() {
+        assert_eq!(*OpenFiles::new(64).available.lock().unwrap(), 21);
+        assert_eq!(*OpenFiles::new(1).available.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn never_more_holders_than_permits() {
+        let limit = OpenFiles::new(2 * FILES_PER_TASK);
+        let (holding, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        let _permit = limit.acquire();
+                        let now = holding.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        std::thread::yield_now();
+                        holding.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert!(most.load(Ordering::SeqCst) <= 2);
+        assert_eq!(*limit.available.lock().unwrap(), 2);
+    }
+}
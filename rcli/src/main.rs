@@ -1,30 +1,398 @@
-use clap::Parser; 
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum}; 
+use fs2::FileExt;
 use rc4::Rc4; 
-use std::fs::File; 
-use std::io::prelude::{Read, Seek, Write};
-use std::io::{self, BufReader, BufWriter}; 
+use std::collections::HashSet;
+use std::fs::{self, File}; 
+use std::io::prelude::{Read, Write};
+use std::io::{self, BufReader}; 
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
 use walkdir::WalkDir; 
 
+mod append;
+mod archive;
+mod bias;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+mod context;
+mod entropy;
+mod env;
+mod explain;
+mod frame;
+mod genkey;
+mod glob;
+mod guard;
+mod header;
+#[cfg(feature = "passphrase")]
+mod kdf;
+mod keycheck;
+mod keyfile;
+#[cfg(feature = "keyring")]
+mod keyring;
+mod layers;
+mod limit;
+mod nonce;
+mod output;
+mod profile;
+#[cfg(feature = "progress")]
+mod progress;
+mod reader;
+mod rekey;
+mod stream;
+mod summary;
+mod transform;
+mod vectors;
+mod verify;
+mod wipe;
+
+use context::{failed, in_phase, Phase};
+use genkey::KeyEncoding;
+use header::Header;
+use keyfile::KeyFormat;
+use limit::OpenFiles;
+use nonce::{NonceRegistry, RunNonceSource};
+use reader::HeaderReader;
+use summary::Summary;
+use transform::Transform;
+use verify::Verification;
+use wipe::Wiped;
+
 /// RC4 file en/decryption
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true, args_override_self = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+
+// The options of an en/decryption run: at the top level, where the direction is guessed unless `--encrypt` or
+// `--decrypt` is given, or under `rcli encrypt` and `rcli decrypt`
+#[derive(clap::Args, Debug)]
 struct Args {
     /// Name of file to en/decrypt
-    #[arg(short, long, required = true, value_name = "FILE_NAME")]
-    file: String,
+    #[arg(short, long, value_name = "FILE_NAME")]
+    #[cfg_attr(not(feature = "clipboard"), arg(required_unless_present_any = ["frame", "self_check_vectors_from"]))]
+    #[cfg_attr(feature = "clipboard", arg(required_unless_present_any = ["clipboard", "frame", "self_check_vectors_from"]))]
+    #[cfg_attr(feature = "keyring", arg(required_unless_present = "store_key"))]
+    file: Option<String>,
 
     /// En/Decryption key (hexadecimal bytes)
     #[arg(
         short,
         long,
-        required = true,
+        required_unless_present_any = ["self_check_vectors_from", "key_file", "key_base64", "entropy_window"],
         value_name = "HEX_BYTE",
-        num_args = 5..=256, 
+        num_args = 1.., 
+        action = clap::ArgAction::Set,
     )]
+    #[cfg_attr(feature = "keyring", arg(required_unless_present = "key_from_keyring"))]
+    #[cfg_attr(feature = "passphrase", arg(required_unless_present = "passphrase"))]
     key: Vec<String>,
 
+    /// Read the key from a file instead, keeping it out of shell history and the process list
+    #[arg(long, value_name = "PATH", conflicts_with = "key")]
+    key_file: Option<String>,
+
+    /// The key as base64, e.g. from tooling that emits keys that way
+    #[arg(long, value_name = "STRING", conflicts_with_all = ["key", "key_file"])]
+    key_base64: Option<String>,
+
+    /// How to read `--key-file`: hex text, raw bytes, or auto-detect
+    #[arg(long, value_enum, default_value_t = KeyFormat::Auto, requires = "key_file")]
+    key_format: KeyFormat,
+
+    /// Derive a 16-byte key from a passphrase (PBKDF2-HMAC-SHA256), instead of giving the key itself.
+    /// Without a value, it's asked for on the terminal (twice with `--encrypt`), or read from stdin's first line
+    #[cfg(feature = "passphrase")]
+    #[arg(long, value_name = "STRING", num_args = 0..=1, conflicts_with_all = ["key", "key_file", "key_base64"])]
+    #[cfg_attr(feature = "keyring", arg(conflicts_with = "key_from_keyring"))]
+    passphrase: Option<Option<String>>,
+
+    /// Fetch the key from the desktop keyring entry SERVICE/ACCOUNT
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "SERVICE/ACCOUNT", conflicts_with_all = ["key", "key_file", "key_base64"])]
+    key_from_keyring: Option<String>,
+
+    /// Store the key (`--key` or `--key-file`) in the desktop keyring entry SERVICE/ACCOUNT and exit
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "SERVICE/ACCOUNT", conflicts_with_all = ["file", "key_from_keyring", "key_layer"])]
+    store_key: Option<String>,
+
+    /// Extra key layered over `--key`, as quoted hex bytes (repeatable). Decrypt with the same keys in the same order
+    #[arg(long, value_name = "HEX_BYTES")]
+    key_layer: Vec<String>,
+
+    /// Hash each key into a 32-byte one, so keys shorter than 5 or longer than 256 bytes can be used.
+    /// Recorded in the header: decryption hashes the key again even without it, but only accepts a
+    /// key of such a length with it
+    #[arg(long, conflicts_with_all = ["append", "frame", "env", "archive", "extract"])]
+    normalize_key: bool,
+
     /// Recursively process files in dirs
     #[arg(short, long)]
     recursive: bool, 
+
+    /// Allow `--recursive` on a filesystem root, the home directory or a system directory
+    #[arg(long, requires = "recursive")]
+    i_know_what_im_doing: bool,
+
+    /// Don't ask before a recursive run modifies files in place
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Print only the concluding summary (`3 encrypted, 0 decrypted, 0 skipped, 12.4 MiB processed`), not a line per file
+    #[arg(short, long, conflicts_with_all = ["explain", "dry_run"])]
+    quiet: bool,
+
+    /// Follow symlinks in the directory, processing the files they lead to (each once). Without it they're skipped
+    #[arg(long, requires = "recursive")]
+    follow_symlinks: bool,
+
+    /// Only descend this many levels below the directory (1 = just its own files)
+    #[arg(long, value_name = "N", requires = "recursive", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_depth: Option<usize>,
+
+    /// Only process files at least this many levels below the directory
+    #[arg(long, value_name = "N", requires = "recursive")]
+    min_depth: Option<usize>,
+
+    /// Only process files whose path below the directory matches this glob (repeatable). `*` and `?` stay
+    /// within a path component and `**` spans them; a pattern without a `/` matches any component
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    include: Vec<String>,
+
+    /// Leave out files and directories whose path below the directory matches this glob (repeatable), even
+    /// if `--include` matches too, e.g. `--exclude .git --exclude '*.rc4'`
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    exclude: Vec<String>,
+
+    /// How to guess the direction of a file without a header: by its byte entropy, or by its share of
+    /// printable text (the original heuristic, which takes binary plaintext for ciphertext)
+    #[arg(long, value_enum, default_value_t = Guess::Entropy)]
+    guess: Guess,
+
+    /// Encrypt, rather than guessing the direction from the file contents
+    #[arg(long, conflicts_with = "decrypt")]
+    encrypt: bool,
+
+    /// Decrypt, rather than guessing the direction from the file contents
+    #[arg(long)]
+    decrypt: bool,
+
+    /// Show progress over the files of the run on stderr: a bar on a terminal, plain text lines otherwise
+    #[cfg(feature = "progress")]
+    #[arg(long)]
+    progress: bool,
+
+    /// En/decrypt the clipboard contents instead of a file (ciphertext is stored as base64)
+    #[cfg(feature = "clipboard")]
+    #[arg(long, conflicts_with_all = ["file", "recursive", "key_layer", "append", "output", "output_dir", "frame", "env", "pad_to", "rekey_every", "explain", "verify_results", "archive", "extract", "normalize_key"])]
+    clipboard: bool,
+
+    /// Write the result to this file and leave `--file` untouched (a directory needs `--output-dir`)
+    #[arg(short, long, value_name = "PATH", conflicts_with_all = ["output_dir", "backup", "append", "env", "frame", "archive", "extract"])]
+    output: Option<String>,
+
+    /// Write results under this directory, mirroring the source tree, and leave the originals untouched
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["backup", "append"])]
+    output_dir: Option<String>,
+
+    /// Encrypt stdin and append it to `--file` (created if missing), continuing the file's keystream.
+    /// Decrypt the whole log with `--decrypt`
+    #[arg(long, conflicts_with_all = ["recursive", "encrypt", "decrypt", "key_layer", "nonce", "normalize_eol", "trim_trailing_newline", "pad_to", "rekey_every", "backup", "explain", "verify_results"])]
+    append: bool,
+
+    /// Treat `--file` as a `.env` file: en/decrypt only the values of its `KEY=value` lines
+    #[arg(long, conflicts_with_all = ["recursive", "key_layer", "nonce", "normalize_eol", "trim_trailing_newline", "pad_to", "rekey_every", "append", "output_dir", "explain", "verify_results"])]
+    env: bool,
+
+    /// Encrypt each line of stdin to stdout as a length-prefixed frame, or with `--decrypt`, decrypt frames back to lines
+    #[arg(long, conflicts_with_all = ["file", "recursive", "key_layer", "append", "output_dir", "pad_to", "rekey_every", "explain", "verify_results"])]
+    frame: bool,
+
+    /// Bundle `--file` (with `--recursive`, every file below it) into one new encrypted archive at this path,
+    /// leaving the originals untouched
+    #[arg(long, value_name = "OUT", conflicts_with_all = ["extract", "key_layer", "encrypt", "decrypt", "append", "env", "frame", "output_dir", "nonce", "normalize_eol", "trim_trailing_newline", "pad_to", "rekey_every", "backup", "explain", "verify_results"])]
+    archive: Option<String>,
+
+    /// Extract the archive `--file` into this directory, recreating the archived tree
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["recursive", "key_layer", "encrypt", "decrypt", "append", "env", "frame", "output_dir", "nonce", "normalize_eol", "trim_trailing_newline", "pad_to", "rekey_every", "backup", "explain", "verify_results"])]
+    extract: Option<String>,
+
+    /// Encrypt without marking the result with an rcli header, for other RC4 tools to read. Decrypting such a
+    /// file again relies on `--decrypt` or `--guess`
+    #[arg(long, conflicts_with_all = ["nonce", "key_layer", "normalize_key", "rekey_every", "normalize_eol", "trim_trailing_newline", "pad_to"])]
+    no_header: bool,
+
+    /// Encrypt each file under a fresh random nonce, stored in a header (decryption detects it)
+    #[arg(long)]
+    nonce: bool,
+
+    /// Store CRLF line endings as LF, restoring them on decryption
+    #[arg(long)]
+    normalize_eol: bool,
+
+    /// Strip line endings at the end of the file before encryption, restoring them on decryption
+    #[arg(long)]
+    trim_trailing_newline: bool,
+
+    /// Switch to a fresh key derived from the key and a counter every this many bytes, so no keystream runs longer
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    rekey_every: Option<u64>,
+
+    /// Pad the plaintext to a multiple of N bytes (1 to 255) before encryption, so the ciphertext fits
+    /// fixed-size records. Stripped again on decryption
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..))]
+    pad_to: Option<u8>,
+
+    /// Print what would be done to each file and why (direction, header, key source, output), without changing anything
+    #[arg(long)]
+    explain: bool,
+
+    /// Print what would be done to each file (`Would encrypt <file>`), opening nothing for writing
+    #[arg(long, conflicts_with_all = ["explain", "append", "env", "frame", "archive", "extract", "verify_results"])]
+    dry_run: bool,
+
+    /// Most files to hold open at once, so a run over a huge tree can't run out of file descriptors
+    #[arg(long, value_name = "N", default_value_t = limit::DEFAULT_MAX_OPEN_FILES, value_parser = clap::value_parser!(u32).range(1..))]
+    max_open_files: u32,
+
+    /// Process this many files at once, each on its own thread (with `--recursive`)
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), requires = "recursive")]
+    jobs: u16,
+
+    /// Read each result back after writing it, checking it against what was meant to be written, and list every
+    /// file with its status and checksum in this file. Fails the run if any result doesn't check out
+    #[arg(long, value_name = "FILE")]
+    verify_results: Option<String>,
+
+    /// Skip files another process holds a lock on, instead of waiting for them
+    #[arg(long)]
+    skip_locked: bool,
+
+    /// Copy each file to `{file}{SUFFIX}` (`{file}.bak` without one) before modifying it
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, default_missing_value = ".bak", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    backup: Option<String>,
+
+    /// Start from the flags of a named profile in the profiles file (`$RCLI_PROFILES`, or
+    /// `~/.config/rcli/profiles.conf`). Options given explicitly override the profile's
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Report how much of each file looks encrypted, judged by the entropy of windows of this many bytes, and exit
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u32).range(16..))]
+    entropy_window: Option<u32>,
+
+    /// Check the cipher against the built-in RFC 6229 vectors before touching any file, aborting if it fails
+    #[arg(long)]
+    verify_vectors_on_start: bool,
+
+    /// Draw nonces from a deterministic generator seeded with N instead of the OS, so runs are reproducible.
+    /// For tests only: the nonces are as predictable as the seed
+    #[arg(long, value_name = "N", hide = true)]
+    seed: Option<u64>,
+
+    /// Validate the keystream against a file of `key,offset,expected-hex` test vectors and exit
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["file", "key", "key_file"])]
+    self_check_vectors_from: Option<String>,
+}
+
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Encrypt, taking the same options as a plain `rcli` run
+    #[command(args_override_self = true)]
+    Encrypt(Args),
+
+    /// Decrypt, taking the same options as a plain `rcli` run
+    #[command(args_override_self = true)]
+    Decrypt(Args),
+
+    /// Check the cipher against the RFC 6229 test vectors
+    Selftest,
+
+    /// Measure how non-uniform the keystream byte at a given offset is for a key
+    ///
+    /// Each sample appends a 4-byte little-endian counter to the key, like a per-packet IV.
+    ReportBias {
+        /// Base key (hexadecimal bytes)
+        #[arg(short, long, required = true, value_name = "HEX_BYTE", num_args = 1..=252)]
+        key: Vec<String>,
+
+        /// Number of keys to sample
+        #[arg(long, default_value_t = 65536)]
+        samples: u32,
+
+        /// 0-based keystream offset to measure (1 is the famously biased second byte)
+        #[arg(long, default_value_t = 1)]
+        position: usize,
+
+        /// Also write the full distribution as `byte,count` CSV to this path
+        #[arg(long, value_name = "PATH")]
+        csv: Option<String>,
+    },
+
+    /// Check that two key sources give the same cipher, by comparing their keystreams, without touching any file
+    ///
+    /// A source is `hex:<hex bytes>`, `base64:<base64>` or `file:<path>` (read like `--key-file`).
+    KeystreamOnlyVerify {
+        /// First key source
+        #[arg(long, value_name = "SOURCE")]
+        first: String,
+
+        /// Second key source
+        #[arg(long, value_name = "SOURCE")]
+        second: String,
+
+        /// Number of keystream bytes to compare
+        #[arg(long, default_value_t = 1024, value_parser = clap::value_parser!(u32).range(1..))]
+        bytes: u32,
+    },
+
+    /// Print a new random key, from the OS's secure random number generator
+    GenKey {
+        /// Key length in bytes
+        #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(5..=256))]
+        length: u16,
+
+        /// How to print it
+        #[arg(long, value_enum, default_value_t = KeyEncoding::Hex)]
+        format: KeyEncoding,
+    },
+}
+
+
+// Hex bytes as given on the command line, e.g. `0x4b 0x8e`. Each token is decoded on its own, so a lone digit
+// (`1`, `0x1`) is a whole byte, as `--key 1 2 3 4 5` always was; a longer token such as `4b8e` holds several
+// bytes. The length isn't checked here: some uses take keys of any length
+fn parse_key<S: AsRef<str>>(hex_bytes: &[S]) -> io::Result<Vec<u8>> {
+    let mut key = Vec::new();
+    for original in hex_bytes.iter().flat_map(|s| s.as_ref().split_whitespace()) {
+        let token = original;
+        let digits = token.strip_prefix("0x").unwrap_or(token);
+        let padded;
+        let token = match digits.len() {
+            1 => {
+                padded = format!("0{}", digits);
+                &padded
+            }
+            _ => token,
+        };
+        let mut bytes = vec![0; token.len() / 2];
+        let len = rc4::decode_hex(token, &mut bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("'{}': {}", original, e)))?;
+        key.extend_from_slice(&bytes[..len]);
+    }
+    Ok(key)
 }
 
 
@@ -37,81 +405,1134 @@ fn is_printable_ascii(byte: u8) -> bool {
 
 
 
-fn process_file(file_path: &str, key_bytes: &[u8]) -> std::io::Result<()> {
-    
-    // `?` operator tells the function to short circuit if an operation fails and immediately return the error
-    // Open the file for both reading and writing 
-    let file = File::options().read(true).write(true).open(file_path)?;
+// Share of printable text in `contents`, which decides the direction for a file without a header with `--guess printable`.
+// Nothing at all is none of it printable, rather than a NaN that every comparison quietly fails
+fn printable_ratio(contents: &[u8]) -> f64 {
+    if contents.is_empty() {
+        return 0.0;
+    }
+    let printable_count = contents.iter().filter(|&&byte| is_printable_ascii(byte)).count();
+    printable_count as f64 / contents.len() as f64
+}
 
-    let mut reader = BufReader::new(file.try_clone()?); 
-    let mut writer = BufWriter::new(file); 
 
-    let chunk_size = 4096; // 4KB
-    let mut buffer = vec![0; chunk_size]; 
-    
-    let mut contents = Vec::new();
+// How the direction of a file without a header is guessed (`--guess`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Guess {
+    /// Byte entropy: ciphertext and compressed data come close to 8 bits a byte, plaintext, binary or not, stays below
+    Entropy,
+    /// Share of printable ASCII text
+    Printable,
+}
+
+// Entropy above which a file is taken to be ciphertext, in bits per byte
+const ENTROPY_THRESHOLD: f64 = 7.5;
+
+// Fewest bytes the entropy guess is trusted on. Entropy measured over fewer bytes falls short of 8 bits even for
+// random data (some 7.2 bits for 256 random bytes, 7.8 for 1 KiB), so shorter files are judged by their printable text
+const ENTROPY_MIN_LEN: u64 = 1024;
+
+// Whether a file without a header, whose byte values occur `counts` times, looks like plaintext to be encrypted
+fn looks_like_plaintext(counts: &[u64; 256], guess: Guess) -> bool {
+    let total: u64 = counts.iter().sum();
+    match guess {
+        Guess::Entropy if total >= ENTROPY_MIN_LEN => entropy::entropy_of_counts(counts) <= ENTROPY_THRESHOLD,
+        _ => printable_share(counts) > 0.7,
+    }
+}
+
+// `printable_ratio` from byte counts
+fn printable_share(counts: &[u64; 256]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let printable: u64 = (0..=255u8).filter(|&byte| is_printable_ascii(byte)).map(|byte| counts[byte as usize]).sum();
+    printable as f64 / total as f64
+}
+
+
+// Apply the keystream for `key_bytes` in place, surfacing key errors as `io::Error`
+pub(crate) fn apply_keystream(key_bytes: &[u8], data: &mut [u8]) -> io::Result<()> {
+    Ok(Rc4::apply_keystream_static(key_bytes, data)?)
+}
+
+
+// Explicit direction of a run (`--encrypt`/`--decrypt`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Encrypt,
+    Decrypt,
+}
+
+
+// Settings shared by every file of a run
+struct Options {
+    // Direction, guessed per file when `None`
+    mode: Option<Mode>,
+    // `--key` followed by any `--key-layer`s
+    keys: Vec<Vec<u8>>,
+    // Enabled pre/post-encryption transforms
+    transforms: Vec<Box<dyn Transform>>,
+    // Skip locked files rather than wait
+    skip_locked: bool,
+    // Keep a copy of each original, at its path plus this suffix
+    backup: Option<String>,
+    // `--rekey-every`
+    rekey_every: Option<u64>,
+    // Read each result back (`--verify-results`)
+    verify: bool,
+    // `--max-open-files`
+    open_files: OpenFiles,
+    // `--seed`, for deterministic nonces
+    seed: Option<u64>,
+    // `--normalize-key`
+    normalize_key: bool,
+    // `--guess`
+    guess: Guess,
+    // Mark every encrypted file with a header, even when nothing needs recording (not `--no-header`)
+    header: bool,
+}
+
+impl Options {
+    // Whether encrypting a file writes a header to record how, `--nonce` aside
+    fn writes_header(&self) -> bool {
+        self.keys.len() > 1 || self.rekey_every.is_some() || !self.transforms.is_empty() || self.normalize_key
+    }
+
+    // For a file without a header: whether to encrypt it, and whether to write a header with the result. The direction
+    // is `--encrypt`/`--decrypt`, or encryption when the options given (or a `nonce` drawn) need a header, which only
+    // encrypting writes. Otherwise `plaintext` guesses it, and is only called then
+    fn headerless_direction<F: FnOnce() -> io::Result<bool>>(&self, nonce: bool, plaintext: F) -> io::Result<(bool, bool)> {
+        let needs_header = nonce || self.writes_header();
+        let encrypt = match self.mode {
+            Some(mode) => mode == Mode::Encrypt,
+            None if needs_header => true,
+            None => plaintext()?,
+        };
+        Ok((encrypt, encrypt && (needs_header || self.header)))
+    }
+
+    // Everything `process_file` and both streaming paths need to en/decrypt `path` when it has no header: what
+    // `headerless_direction` decides, the header to write ahead of the result (with a nonce drawn from `nonces`, and
+    // the payload length left for the caller), and the key of each layer to apply. Input decrypted without a header
+    // must have been encrypted under one plain key, since key layers and a normalized key always write one
+    fn headerless_plan<F: FnOnce() -> io::Result<bool>>(
+        &self,
+        path: &Path,
+        nonces: Option<&NonceRegistry<RunNonceSource>>,
+        plaintext: F,
+    ) -> io::Result<(Action, Option<Header>, Vec<Vec<u8>>)> {
+        let (encrypt, with_header) = self.headerless_direction(nonces.is_some(), plaintext)?;
+        if with_header {
+            let nonce = nonces.map(|registry| registry.draw()).transpose().map_err(in_phase(path, Phase::Encrypt))?;
+            let header = Header {
+                nonce,
+                layers: (self.keys.len() > 1).then_some(self.keys.len() as u8),
+                rekey_every: self.rekey_every,
+                normalized_key: self.normalize_key,
+                ..Default::default()
+            };
+            let layer_keys = layers::layer_keys(&self.keys, &header);
+            return Ok((Action::Encrypt, Some(header), layer_keys));
+        }
+
+        if self.keys.len() > 1 {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "no header, but files encrypted under key layers always carry one");
+            return Err(in_phase(path, Phase::Decrypt)(e));
+        }
+        if self.normalize_key {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "no header, but files encrypted with --normalize-key always carry one");
+            return Err(in_phase(path, Phase::Decrypt)(e));
+        }
+        Ok((if encrypt { Action::Encrypt } else { Action::Decrypt }, None, self.keys.clone()))
+    }
+}
+
+
+// What `process_file` decided to do with a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Encrypt,
+    Decrypt,
+    // Left untouched, see `Status`
+    Skip,
+}
+
+
+// How processing a file ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    // The file was replaced with the result
+    Written,
+    // Another process holds the file's lock and `--skip-locked` is set
+    Locked,
+    // The file is empty, there's nothing to en/decrypt
+    Empty,
+}
+
+
+// Result of `process_file`, printed by the caller
+#[derive(Debug)]
+struct FileOutcome {
+    path: PathBuf,
+    action: Action,
+    // Size of the file as read
+    bytes: usize,
+    status: Status,
+    // Where the result went and how it read back, with `--verify-results`
+    verification: Option<(PathBuf, Verification)>,
+}
+
+impl FileOutcome {
+    fn report(&self) {
+        match (self.action, self.status) {
+            (_, Status::Locked) => println!("Skipped {}: locked by another process", self.path.display()),
+            (_, Status::Empty) => println!("Skipped {}: empty", self.path.display()),
+            (Action::Encrypt, _) => println!("Encrypted {} ({} bytes)", self.path.display(), self.bytes),
+            _ => println!("Decrypted {} ({} bytes)", self.path.display(), self.bytes),
+        }
+    }
+}
+
+
+// Copy the original to `{file}{suffix}` before it's replaced, refusing to overwrite an existing backup
+fn backup_file(file_path: &Path, suffix: &str) -> io::Result<()> {
+    let mut backup_path = file_path.as_os_str().to_owned();
+    backup_path.push(suffix);
+    let backup_path = PathBuf::from(backup_path);
+    let mut backup = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&backup_path)
+        .map_err(|e| io::Error::new(e.kind(), format!("can't create backup {}: {}", backup_path.display(), e)))?;
+
+    io::copy(&mut File::open(file_path)?, &mut backup).map_err(failed("copy"))?;
+    backup.sync_all().map_err(failed("sync"))
+}
+
+
+// Open `file_path` holding an exclusive advisory lock, so concurrent `rcli` runs don't clobber each other's work.
+// Waits for the lock, or returns `None` if it's held elsewhere and `skip_locked` is set.
+fn lock_file(file_path: &Path, skip_locked: bool) -> io::Result<Option<File>> {
     loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break; 
+        let file = File::open(file_path)?;
+        if skip_locked {
+            if let Err(e) = file.try_lock_exclusive() {
+                if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                    return Ok(None);
+                }
+                return Err(e);
+            }
+        } else {
+            file.lock_exclusive()?;
+        }
+
+        // Whoever held the lock may have renamed a new file over the path, leaving us holding a lock on
+        // the old, unlinked one. Start over on the file the path names now.
+        if is_same_file(&file, file_path)? {
+            return Ok(Some(file));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, file_path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let (opened, current) = (file.metadata()?, fs::metadata(file_path)?);
+    Ok(opened.dev() == current.dev() && opened.ino() == current.ino())
+}
+
+// Windows refuses to rename over a file that's open, so the path can't have moved on
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _file_path: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+
+// Replace the contents of `file_path` with what `write` writes.
+// The new contents go to a temp file in the same directory, which is then renamed over the original:
+// no handle ever reads and writes the same file, and an interrupted run leaves the original intact.
+fn replace_file<F: FnOnce(&mut File) -> io::Result<()>>(file_path: &Path, write: F) -> io::Result<()> {
+    let metadata = fs::metadata(file_path)?;
+
+    // Renaming would sidestep a read-only file's permissions, refuse like opening it for writing would
+    if metadata.permissions().readonly() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "the file is read-only"));
+    }
+
+    write_file_with(file_path, metadata.permissions(), metadata.modified().ok(), write)
+}
+
+
+// Write to `path` through a temp file renamed into place, with the given permissions and modification time.
+// `write` fills in the temp file
+fn write_file_with<F: FnOnce(&mut File) -> io::Result<()>>(
+    path: &Path,
+    permissions: fs::Permissions,
+    modified: Option<SystemTime>,
+    write: F,
+) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    // Dropped (and deleted) on any error below
+    let mut temp = NamedTempFile::new_in(dir).map_err(failed("creating the temp file"))?;
+    write(temp.as_file_mut())?;
+
+    // The contents changed, but keep the original's time so tools going by it (make, rsync, backups)
+    // see the file as it was last edited rather than when it was en/decrypted
+    if let Some(modified) = modified {
+        temp.as_file().set_modified(modified).map_err(failed("setting the modification time"))?;
+    }
+
+    // On disk before the rename, or a power loss right after it could leave the path naming a file
+    // whose data never made it out of the page cache
+    temp.as_file().sync_all().map_err(failed("sync"))?;
+
+    // Temp files are created owner-only, carry over the original's permissions
+    fs::set_permissions(temp.path(), permissions).map_err(failed("setting permissions"))?;
+    temp.persist(path).map_err(|e| failed("rename")(e.error))?;
+
+    Ok(())
+}
+
+
+// Add write permission for the owner, leaving the rest of the permissions alone
+#[cfg(unix)]
+fn set_owner_writable(permissions: &mut fs::Permissions) {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.set_mode(permissions.mode() | 0o200);
+}
+
+// Permissions elsewhere are just the read-only flag
+#[cfg(not(unix))]
+fn set_owner_writable(permissions: &mut fs::Permissions) {
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+}
+
+
+// Read `reader` to the end through `buffer`, which is wiped afterwards
+fn read_chunked<R: Read>(mut reader: R, buffer: &mut [u8], capacity: usize) -> io::Result<Wiped> {
+    let mut contents = Wiped::with_capacity(capacity);
+    let result = loop {
+        match reader.read(buffer) {
+            Ok(0) => break Ok(()),
+            Ok(bytes_read) => contents.extend_from_slice(&buffer[..bytes_read]),
+            Err(e) => break Err(e),
         }
-        contents.extend_from_slice(&buffer[..bytes_read]);  
+    };
+
+    wipe::wipe_slice(buffer);
+    result.map(|()| contents)
+}
+
+
+// En/decrypt `file_path`, replacing it, or writing the result to `output_path` instead when given
+fn process_file(
+    file_path: &Path,
+    output_path: Option<&Path>,
+    opts: &Options,
+    nonces: Option<&NonceRegistry<RunNonceSource>>,
+) -> std::io::Result<FileOutcome> {
+    let keys = &opts.keys;
+    let _permit = opts.open_files.acquire();
+    
+    // `?` operator tells the function to short circuit if an operation fails and immediately return the error
+    // The result replaces the file wholesale (see `replace_file`), so the source only needs to be opened for reading.
+    // The lock is held until `file` is dropped, after the replacement is renamed into place.
+    let file = match lock_file(file_path, opts.skip_locked).map_err(in_phase(file_path, Phase::Open))? {
+        Some(file) => file,
+        None => return Ok(FileOutcome { path: file_path.to_path_buf(), action: Action::Skip, bytes: 0, status: Status::Locked, verification: None }),
+    };
+    let len = file.metadata().map_err(in_phase(file_path, Phase::Read))?.len();
+    if len == 0 {
+        return Ok(FileOutcome { path: file_path.to_path_buf(), action: Action::Skip, bytes: 0, status: Status::Empty, verification: None });
     }
 
+    // Every file goes through a chunk at a time, so memory use doesn't grow with its size, unless it
+    // needs transforming as a whole
+    if let Some(outcome) = stream::stream_file(file_path, &file, output_path, opts, nonces)? {
+        return Ok(outcome);
+    }
+
+    // Read straight from the file, without a `BufReader`: 64 KiB reads need no buffering on top, and
+    // were the fastest setup measured (see `benches/io_buffer.rs`)
+    let chunk_size = 64 * 1024;
+    let mut buffer = vec![0; chunk_size];
+
+    // Sized up front, so growing it doesn't leave copies of the data behind (see `Wiped`)
+    let mut contents = read_chunked(&file, &mut buffer, len as usize)
+        .map_err(failed("read"))
+        .map_err(in_phase(file_path, Phase::Read))?;
+
     // Read all file contents into memory
     // file.read_to_end(&mut contents)?;
+    let bytes = contents.len();
 
-    // Heuristic: Count the number of printable ASCII characters
-    let printable_count = contents.iter().filter(|&&byte| is_printable_ascii(byte)).count();
-    let printable_ratio = printable_count as f64 / contents.len() as f64;  
+    // Explicitly encrypting means even a file that's already encrypted gets another pass
+    let header = match opts.mode {
+        Some(Mode::Encrypt) => None,
+        _ => Header::parse(&contents).map_err(in_phase(file_path, Phase::Read))?,
+    };
 
-    // En/decrypt file contents in-memory
-    Rc4::apply_keystream_static(key_bytes, &mut contents)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Rc4 Error: {:?}", e)))?;
-    
-    // Overwrite existing file with the result
-    // file.rewind()?; 
-    // file.write_all(&contents);
-
-    // Move the file cursor to the beginning and write the entire contents buffer into the file
-    writer.seek(io::SeekFrom::Start(0))?;
-    writer.write_all(&contents)?; 
-    writer.flush()?;  
-
-    // Print success message
-    if printable_ratio > 0.7 {
-        println!("Encrypted {}", file_path);
+    let (contents, encrypted) = match header {
+        // A header means the file was encrypted by `rcli` with a nonce or key layers: strip it and
+        // decrypt with the keys it describes
+        Some((_, header_len)) => {
+            let mut reader = HeaderReader::open(keys, &contents[..]).map_err(in_phase(file_path, Phase::Decrypt))?;
+
+            // Sized up front like `contents`, for the same reason
+            let mut payload = Wiped::with_capacity(contents.len() - header_len);
+            reader.read_to_end(&mut payload).map_err(in_phase(file_path, Phase::Decrypt))?;
+            let header = reader.header().clone();
+
+            // Undo whatever the header says was applied, whether or not it's enabled for this run
+            let payload = transform::post(&transform::registered(), payload.take(), &header)
+                .map_err(in_phase(file_path, Phase::Decrypt))?;
+            let payload = Wiped::from(payload);
+            (payload, false)
+        }
+
+        None => {
+            let (action, header, layer_keys) = opts.headerless_plan(file_path, nonces, || {
+                let mut counts = [0; 256];
+                entropy::count_bytes(&mut counts, &contents);
+                Ok(looks_like_plaintext(&counts, opts.guess))
+            })?;
+
+            match header {
+                // Encrypt under a header, recording any fresh nonce (unique within this run), key layers, normalized key,
+                // rekeying and transforms
+                Some(mut header) => {
+                    let mut contents = Wiped::from(transform::pre(&opts.transforms, contents.take(), &mut header));
+                    header.payload_len = Some(contents.len() as u64);
+                    layers::encrypt(&layer_keys, opts.rekey_every, &mut contents).map_err(in_phase(file_path, Phase::Encrypt))?;
+
+                    let mut output = Wiped::from(header.encode());
+                    output.extend_from_slice(&contents);
+                    (output, true)
+                }
+
+                // En/decrypt file contents in-memory
+                None => {
+                    let phase = if action == Action::Encrypt { Phase::Encrypt } else { Phase::Decrypt };
+                    apply_keystream(&layer_keys[0], &mut contents).map_err(in_phase(file_path, phase))?;
+                    (contents, action == Action::Encrypt)
+                }
+            }
+        }
+    };
+
+    // One `write_all` of the whole result, which a `BufWriter` would only pass through
+    write_result(file_path, &file, output_path, opts.backup.as_deref(), |out| out.write_all(&contents).map_err(failed("write")))?;
+
+    let written = output_path.unwrap_or(file_path);
+    let verification = match opts.verify {
+        true => Some((written.to_path_buf(), verify::check_written(written, &contents).map_err(in_phase(file_path, Phase::Verify))?)),
+        false => None,
+    };
+
+    Ok(FileOutcome {
+        path: file_path.to_path_buf(),
+        action: if encrypted { Action::Encrypt } else { Action::Decrypt },
+        bytes,
+        status: Status::Written,
+        verification,
+    })
+}
+
+
+// Write the result for `file_path`, open as `file`, to `output_path` when given, or else over the file
+// itself (backing it up first to its path plus the `backup` suffix). `write` fills in the result
+fn write_result<F: FnOnce(&mut File) -> io::Result<()>>(
+    file_path: &Path,
+    file: &File,
+    output_path: Option<&Path>,
+    backup: Option<&str>,
+    write: F,
+) -> io::Result<()> {
+    if let (Some(suffix), None) = (backup, output_path) {
+        backup_file(file_path, suffix).map_err(in_phase(file_path, Phase::Backup))?;
+    }
+    match output_path {
+        Some(output_path) => write_output(file, output_path, write),
+        None => replace_file(file_path, write),
+    }
+    .map_err(in_phase(file_path, Phase::WriteBack))
+}
+
+
+// Write the result for the source `file` to `output_path`, creating its directory as needed
+fn write_output<F: FnOnce(&mut File) -> io::Result<()>>(file: &File, output_path: &Path, write: F) -> io::Result<()> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(failed("creating the output directory"))?;
+    }
+    // The source may be read-only (e.g. on a read-only mount), the result is left for the user to modify
+    let metadata = file.metadata()?;
+    let mut permissions = metadata.permissions();
+    set_owner_writable(&mut permissions);
+    write_file_with(output_path, permissions, metadata.modified().ok(), write)
+}
+
+
+// `--env`: en/decrypt the values of a `.env` file in place.
+// Decrypts if any value is encrypted, unless the direction is given explicitly.
+fn process_env_file(file_path: &Path, opts: &Options) -> io::Result<FileOutcome> {
+    let _permit = opts.open_files.acquire();
+    let file = match lock_file(file_path, opts.skip_locked).map_err(in_phase(file_path, Phase::Open))? {
+        Some(file) => file,
+        None => return Ok(FileOutcome { path: file_path.to_path_buf(), action: Action::Skip, bytes: 0, status: Status::Locked, verification: None }),
+    };
+    let text = io::read_to_string(BufReader::new(&file))
+        .map_err(|e| io::Error::new(e.kind(), format!("not a UTF-8 .env file: {}", e)))
+        .map_err(in_phase(file_path, Phase::Read))?;
+
+    let decrypt = match opts.mode {
+        Some(mode) => mode == Mode::Decrypt,
+        None => env::has_encrypted_values(&text),
+    };
+    let (output, _) = if decrypt {
+        env::decrypt_values(&text, &opts.keys[0]).map_err(in_phase(file_path, Phase::Decrypt))?
     } else {
-        println!("Decrypted {}", file_path);
+        env::encrypt_values(&text, &opts.keys[0], &NonceRegistry::new(RunNonceSource::new(opts.seed)))
+            .map_err(in_phase(file_path, Phase::Encrypt))?
+    };
+
+    write_result(file_path, &file, None, opts.backup.as_deref(), |out| out.write_all(output.as_bytes()).map_err(failed("write")))?;
+
+    Ok(FileOutcome {
+        path: file_path.to_path_buf(),
+        action: if decrypt { Action::Decrypt } else { Action::Encrypt },
+        bytes: text.len(),
+        status: Status::Written,
+        verification: None,
+    })
+}
+
+
+// `rcli encrypt` and `rcli decrypt`: `args` run in the direction the subcommand names, which the flags picking a
+// direction of their own would contradict
+fn with_direction(mut args: Args, encrypt: bool) -> Args {
+    let own_direction = [
+        ("--encrypt", args.encrypt),
+        ("--decrypt", args.decrypt),
+        ("--append", args.append),
+        ("--archive", args.archive.is_some()),
+        ("--extract", args.extract.is_some()),
+    ];
+    if let Some((flag, _)) = own_direction.iter().find(|(_, given)| *given) {
+        let subcommand = if encrypt { "encrypt" } else { "decrypt" };
+        Cli::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, format!("{} can't be used with `rcli {}`, which sets the direction", flag, subcommand))
+            .exit();
     }
+    args.encrypt = encrypt;
+    args.decrypt = !encrypt;
+    args
+}
+
+
+// `--jobs`: `jobs` workers take `paths` off one queue and run `process` on each, while `report` gets every outcome on
+// this thread as it comes in
+fn process_in_parallel<T, P, R>(paths: Vec<PathBuf>, jobs: u16, process: &P, report: &mut R) -> io::Result<()>
+where
+    T: Send,
+    P: Fn(&Path) -> T + Sync,
+    R: FnMut(PathBuf, T) -> io::Result<()>,
+{
+    let queue = Mutex::new(paths.into_iter());
+    let (sender, outcomes) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let (queue, sender) = (&queue, sender.clone());
+            scope.spawn(move || loop {
+                // Its own statement, so the lock is released before the file is processed: in a `while let`
+                // condition the guard would live through the whole body and the workers would take turns
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let Some(path) = next else { break };
+                let processed = process(&path);
+                // Gone when a failure ended the run, so stop taking files
+                if sender.send((path, processed)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        outcomes.into_iter().try_for_each(|(path, processed)| report(path, processed))
+    })
+}
+
 
+// `--file` itself, or with `--recursive` every file below it within the depth limits.
+// The files are listed before any is touched, so backups and temp files created along the way aren't picked up.
+fn files_to_process(args: &Args, file: &str) -> Vec<PathBuf> {
+    if !args.recursive {
+        return vec![PathBuf::from(file)];
+    }
+
+    // The walk keeps a handle open on each directory level it's in, up to the given count
+    let mut walk = WalkDir::new(file).max_open((args.max_open_files as usize).clamp(1, 10)).follow_links(args.follow_symlinks);
+    // Deeper levels are pruned from the walk entirely
+    if let Some(max_depth) = args.max_depth {
+        walk = walk.max_depth(max_depth);
+    }
+
+    // Excluded directories are pruned rather than walked, `.git` can be huge
+    let relative = |e: &walkdir::DirEntry| e.path().strip_prefix(file).unwrap_or(e.path()).to_path_buf();
+    let excluded = |e: &walkdir::DirEntry| e.depth() > 0 && args.exclude.iter().any(|pattern| glob::matches(pattern, &relative(e)));
+    let included = |e: &walkdir::DirEntry| args.include.is_empty() || args.include.iter().any(|pattern| glob::matches(pattern, &relative(e)));
+
+    // Unless followed, symlinks are left out rather than processed: a link could lead anywhere outside the directory.
+    // Followed, a link to a directory that contains it is a cycle, which the walk reports as an error and skips
+    let mut symlinks = 0;
+    let (entries, shallow): (Vec<_>, Vec<_>) = walk
+        .into_iter()
+        .filter_entry(|e| !excluded(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            symlinks += e.file_type().is_symlink() as usize;
+            e.file_type().is_file() && included(e)
+        })
+        .partition(|e| e.depth() >= args.min_depth.unwrap_or(0));
+
+    if !shallow.is_empty() && !args.quiet {
+        println!("Skipped {} file(s) above --min-depth", shallow.len());
+    }
+    if symlinks > 0 && !args.quiet {
+        println!("Skipped {} symlink(s), see --follow-symlinks", symlinks);
+    }
+
+    // Two links to one file would encrypt it twice over, so each file is processed once however it's reached
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .map(|e| e.into_path())
+        .filter(|path| !args.follow_symlinks || seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+
+// `--entropy-window`: report how much of each file looks encrypted, window by window
+fn report_entropy(paths: &[PathBuf], window: usize) -> io::Result<()> {
+    for path in paths {
+        let data = fs::read(path)?;
+        let report = entropy::windowed_entropy(&data, window);
+        println!(
+            "{}: {} of {} window(s) of {} bytes look encrypted ({:.1}%), whole file {:.2} bits/byte",
+            path.display(),
+            report.encrypted,
+            report.windows,
+            report.window,
+            100.0 * report.fraction(),
+            entropy::shannon_entropy(&data),
+        );
+    }
     Ok(())
 }
 
 
 fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse_from(profile::expand(std::env::args_os().collect())?);
     //println!("{:?}", args); 
 
-    let key_bytes = args
-    .key
-    .iter()
-    .map(|s| s.trim_start_matches("0x"))
-    .map(|s| u8::from_str_radix(s,16).expect("Invalid key hex byte!"))
-    .collect::<Vec<u8>>();
+    let args = match cli.command {
+        Some(Command::Encrypt(args)) => with_direction(args, true),
+        Some(Command::Decrypt(args)) => with_direction(args, false),
+        Some(Command::Selftest) => {
+            Rc4::self_test()?;
+            println!("RC4 self-test passed");
+            return Ok(());
+        }
+        Some(Command::ReportBias { key, samples, position, csv }) => {
+            return bias::report_bias(&parse_key(&key)?, samples, position, csv.as_deref());
+        }
+        Some(Command::KeystreamOnlyVerify { first, second, bytes }) => return keycheck::verify(&first, &second, bytes as usize),
+        Some(Command::GenKey { length, format }) => return genkey::gen_key(length as usize, format),
+        None => cli.args,
+    };
+
+    if let Some(path) = &args.self_check_vectors_from {
+        return vectors::self_check_from(path);
+    }
 
-    // If the recursive flag is set, process each file in the directory and its subdirectories.
-    if args.recursive {
-        for entry in WalkDir::new(&args.file)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-        {
-            process_file(entry.path().to_str().unwrap(), &key_bytes)?;
+    if let Some(window) = args.entropy_window {
+        let file = args.file.as_deref().expect("--file is required");
+        return report_entropy(&files_to_process(&args, file), window as usize);
+    }
+
+    if args.verify_vectors_on_start {
+        println!("Verified {} built-in test vectors", vectors::verify_builtin()?);
+    }
+
+    let key_bytes = match &args.key_file {
+        Some(path) => keyfile::read_key_file(path, args.key_format)?,
+        None if args.key_base64.is_some() => STANDARD
+            .decode(args.key_base64.as_deref().unwrap().trim())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("--key-base64 is not valid base64: {}", e)))?,
+        #[cfg(feature = "keyring")]
+        None if args.key_from_keyring.is_some() => {
+            keyring::retrieve_key(&keyring::OsKeyring, args.key_from_keyring.as_deref().unwrap())?
+        }
+        #[cfg(feature = "passphrase")]
+        None if args.passphrase.is_some() => {
+            let passphrase = match args.passphrase.clone().unwrap() {
+                Some(passphrase) => passphrase,
+                None if args.append || args.file.as_deref() == Some("-") => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "--passphrase needs a value when the data comes from stdin"));
+                }
+                None => kdf::read_passphrase(args.encrypt)?,
+            };
+            kdf::passphrase_key(&passphrase).to_vec()
+        }
+        None => parse_key(&args.key)?,
+    };
+
+    #[cfg(feature = "keyring")]
+    if let Some(entry) = &args.store_key {
+        keyring::store_key(&keyring::OsKeyring, entry, &key_bytes)?;
+        println!("Stored the key in the keyring as {}", entry);
+        return Ok(());
+    }
+
+    // The key is the first (innermost) layer
+    let mut keys = vec![key_bytes];
+    for layer in &args.key_layer {
+        keys.push(parse_key(&[layer])?);
+    }
+
+    // Keys aren't bounded by the argument parser (and layer keys are hashed before reaching the
+    // cipher), so check their length up front. Normalized keys only need to be there at all
+    if args.normalize_key && keys.iter().any(|key| key.is_empty()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "keys can't be empty"));
+    }
+    if !args.normalize_key && keys.iter().any(|key| !(5..=256).contains(&key.len())) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "keys must be 5 to 256 bytes long (or use --normalize-key)"));
+    }
+    if keys.len() > u8::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "at most 255 key layers are supported"));
+    }
+
+    #[cfg(feature = "clipboard")]
+    if args.clipboard {
+        return clipboard::process_clipboard(&keys[0], &NonceRegistry::new(RunNonceSource::new(args.seed)));
+    }
+
+    if args.append {
+        let file = args.file.expect("--file is required");
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        append::append(&file, &keys[0], &data)?;
+        println!("Appended {} bytes to {}", data.len(), file);
+        return Ok(());
+    }
+
+    if args.frame {
+        return frame::frame_stdio(&keys[0], args.decrypt);
+    }
+
+    let mut transforms: Vec<Box<dyn Transform>> = Vec::new();
+    if args.normalize_eol {
+        transforms.push(Box::new(transform::NormalizeEol));
+    }
+    if args.trim_trailing_newline {
+        transforms.push(Box::new(transform::TrimTrailingNewline));
+    }
+    // Last, so the padding covers the data as finally encrypted
+    if let Some(block) = args.pad_to {
+        transforms.push(Box::new(transform::PadTo(block)));
+    }
+    let mode = match (args.encrypt, args.decrypt) {
+        (true, _) => Some(Mode::Encrypt),
+        (_, true) => Some(Mode::Decrypt),
+        _ => None,
+    };
+
+    let opts = Options {
+        mode,
+        keys,
+        transforms,
+        skip_locked: args.skip_locked,
+        backup: args.backup.clone(),
+        rekey_every: args.rekey_every,
+        verify: args.verify_results.is_some(),
+        open_files: OpenFiles::new(args.max_open_files as usize),
+        seed: args.seed,
+        normalize_key: args.normalize_key,
+        guess: args.guess,
+        header: !args.no_header,
+    };
+    if opts.seed.is_some() {
+        eprintln!("Warning: --seed makes every nonce predictable, use it for tests only");
+    }
+
+    // Either `--file` or `--clipboard` is required, so past this point there is always a file
+    let file = args.file.clone().expect("--file is required");
+
+    // Opening a directory fails with an OS error that doesn't say what went wrong, flag the usual mistake like a usage error
+    if !args.recursive && Path::new(&file).is_dir() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::InvalidValue,
+                format!("'{}' is a directory; use --recursive to process its contents", file),
+            )
+            .exit();
+    }
+
+    // One path can't hold the results of a whole tree
+    if args.recursive && args.output.is_some() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--output writes a single file; use --output-dir with --recursive",
+            )
+            .exit();
+    }
+
+    // `--file -` streams stdin to stdout, with the report on stderr so it stays out of the data
+    if file == "-" {
+        let single_file_only = [
+            ("--recursive", args.recursive),
+            ("--output", args.output.is_some()),
+            ("--output-dir", args.output_dir.is_some()),
+            ("--backup", args.backup.is_some()),
+            ("--archive", args.archive.is_some()),
+            ("--extract", args.extract.is_some()),
+            ("--env", args.env),
+            ("--explain", args.explain),
+            ("--dry-run", args.dry_run),
+            ("--verify-results", args.verify_results.is_some()),
+        ];
+        if let Some((flag, _)) = single_file_only.iter().find(|(_, given)| *given) {
+            Cli::command()
+                .error(clap::error::ErrorKind::ArgumentConflict, format!("{} can't be used with --file - (stdin and stdout)", flag))
+                .exit();
+        }
+        let nonces = args.nonce.then(|| NonceRegistry::new(RunNonceSource::new(opts.seed)));
+        let (action, bytes) = stream::stream_stdio(&opts, nonces.as_ref())?;
+        match action {
+            _ if args.quiet => {}
+            Action::Encrypt => eprintln!("Encrypted stdin ({} bytes)", bytes),
+            _ => eprintln!("Decrypted stdin ({} bytes)", bytes),
+        }
+        let mut summary = Summary::default();
+        summary.add_action(action, Status::Written, bytes);
+        eprintln!("{}", summary);
+        return Ok(());
+    }
+
+    if let Some(out) = &args.archive {
+        let nonce = NonceRegistry::new(RunNonceSource::new(opts.seed)).draw()?;
+        let entries = archive::create(Path::new(&file), &files_to_process(&args, &file), &opts.keys[0], nonce, Path::new(out))?;
+        let bytes: u64 = entries.iter().map(|entry| entry.len).sum();
+        println!("Archived {} file(s) ({} bytes) into {}", entries.len(), bytes, out);
+        return Ok(());
+    }
+
+    if let Some(dir) = &args.extract {
+        let entries = archive::extract(Path::new(&file), &opts.keys[0], Path::new(dir))?;
+        println!("Extracted {} file(s) into {}", entries.len(), dir);
+        return Ok(());
+    }
+
+    if args.env {
+        let outcome = process_env_file(Path::new(&file), &opts)?;
+        if !args.quiet {
+            outcome.report();
+        }
+        let mut summary = Summary::default();
+        summary.add(&outcome);
+        println!("{}", summary);
+        return Ok(());
+    }
+
+    // With `--output-dir`, each file's result goes to the same place relative to it as the file is to `--file`.
+    // `--output` names the one file's result directly
+    let output_dir = args.output_dir.as_ref().map(PathBuf::from);
+    let output_path = |path: &Path| match &args.output {
+        Some(output) => Ok(Some(PathBuf::from(output))),
+        None => output_dir.as_deref().map(|dir| output::mirrored_path(Path::new(&file), path, dir)).transpose(),
+    };
+
+    if args.explain || args.dry_run {
+        let mut key_source = match &args.key_file {
+            Some(path) => format!("--key-file {} ({} bytes)", path, opts.keys[0].len()),
+            None if args.key_base64.is_some() => format!("--key-base64 ({} bytes)", opts.keys[0].len()),
+            #[cfg(feature = "passphrase")]
+            None if args.passphrase.is_some() => format!("--passphrase ({} bytes derived)", opts.keys[0].len()),
+            None => format!("--key ({} bytes)", opts.keys[0].len()),
+        };
+        if opts.keys.len() > 1 {
+            key_source.push_str(&format!(", under {} --key-layer(s)", opts.keys.len() - 1));
+        }
+        for path in files_to_process(&args, &file) {
+            let output_path = output_path(&path)?;
+            let explanation = explain::explain(&path, output_path.as_deref(), &opts, args.nonce, &key_source)?;
+            match args.dry_run {
+                true => println!("{}", explanation.dry_run()),
+                false => println!("{}", explanation),
+            }
+        }
+        return Ok(());
+    }
+
+    if args.recursive && !args.i_know_what_im_doing {
+        guard::check_recursive_target(Path::new(&file), guard::home_dir().as_deref())?;
+    }
+
+    // One registry for the whole run, so no two files are encrypted under the same nonce
+    let nonces = args.nonce.then(|| NonceRegistry::new(RunNonceSource::new(opts.seed)));
+
+    let paths = files_to_process(&args, &file);
+    // Results written elsewhere leave the originals alone, nothing to confirm then
+    if args.recursive && args.output_dir.is_none() && !args.yes && !paths.is_empty() && !guard::confirm(paths.len(), Path::new(&file), io::stdin().lock())? {
+        return Err(io::Error::other("aborted, no file was changed"));
+    }
+    #[cfg(feature = "progress")]
+    let mut progress = args.progress.then(|| progress::Progress::new(paths.len()));
+
+    let mut results = Vec::new();
+    let mut summary = Summary::default();
+    let (total, mut failures) = (paths.len(), Vec::new());
+    // Replacing a symlinked file in place replaces its target: the result renamed over the link would replace the link
+    let process = |path: &Path| {
+        let output_path = output_path(path)?;
+        let path = match output_path {
+            None if path.is_symlink() => fs::canonicalize(path)?,
+            _ => path.to_path_buf(),
+        };
+        process_file(&path, output_path.as_deref(), &opts, nonces.as_ref())
+    };
+    // Reports each file's outcome as it comes in, on this thread whichever one processed it, so lines never interleave
+    let mut report = |path: PathBuf, processed: io::Result<FileOutcome>| -> io::Result<()> {
+        let outcome = match processed {
+            Ok(outcome) => outcome,
+            // One bad file doesn't stop a walk over a tree, the rest is still processed and the failures listed at the end
+            Err(e) if args.recursive => {
+                if opts.verify {
+                    results.push((path, None));
+                }
+                failures.push(e);
+                #[cfg(feature = "progress")]
+                if let Some(progress) = progress.as_mut() {
+                    progress.inc();
+                }
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        if opts.verify {
+            results.push(match &outcome.verification {
+                Some((written, verification)) => (written.clone(), Some(*verification)),
+                None => (outcome.path.clone(), None),
+            });
+        }
+        summary.add(&outcome);
+        if args.quiet {
+            #[cfg(feature = "progress")]
+            if let Some(progress) = progress.as_mut() {
+                progress.inc();
+            }
+            return Ok(());
+        }
+
+        #[cfg(feature = "progress")]
+        if let Some(progress) = progress.as_mut() {
+            progress.suspend(|| outcome.report());
+            progress.inc();
+            return Ok(());
+        }
+        outcome.report();
+        Ok(())
+    };
+
+    if args.jobs == 1 {
+        for path in paths {
+            let processed = process(&path);
+            report(path, processed)?;
         }
     } else {
-        process_file(&args.file, &key_bytes)?;
+        // Files are independent, so `--jobs` workers take them off one queue, each with its own cipher; only the
+        // nonce registry and the open files limit are shared
+        process_in_parallel(paths, args.jobs, &process, &mut report)?;
     }
 
+    #[cfg(feature = "progress")]
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+
+    if args.recursive {
+        println!("Processed {} file(s), {} failed", total, failures.len());
+        for e in &failures {
+            eprintln!("  {}", e);
+        }
+    }
+    println!("{}", summary);
+
+    if let Some(results_path) = &args.verify_results {
+        let (passed, failed) = verify::write_results(Path::new(results_path), &results)?;
+        println!(
+            "Verified {} file(s): {} passed, {} failed, {} skipped (results in {})",
+            results.len(),
+            passed,
+            failed,
+            results.len() - passed - failed,
+            results_path
+        );
+        if failed > 0 {
+            return Err(io::Error::other(format!("{} file(s) failed verification, see {}", failed, results_path)));
+        }
+    }
+
+    if let Some(registry) = &nonces {
+        if registry.collisions() > 0 {
+            eprintln!("Warning: redrew {} colliding nonce(s)", registry.collisions());
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(io::Error::other(format!("{} of {} file(s) failed", failures.len(), total)));
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    fn options(mode: Option<Mode>) -> Options {
+        Options {
+            mode,
+            keys: vec![KEY.to_vec()],
+            transforms: Vec::new(),
+            skip_locked: false,
+            backup: None,
+            rekey_every: None,
+            verify: false,
+            open_files: OpenFiles::new(limit::DEFAULT_MAX_OPEN_FILES as usize),
+            seed: None,
+            normalize_key: false,
+            guess: Guess::Entropy,
+            header: false,
+        }
+    }
+
+    fn outcome_for(contents: &[u8], opts: &Options) -> FileOutcome {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, contents).unwrap();
+        process_file(&path, None, opts, None).unwrap()
+    }
+
+    #[test]
+    fn guessed_action_follows_contents() {
+        let outcome = outcome_for(b"This is a secret", &options(None));
+        assert_eq!((outcome.action, outcome.status, outcome.bytes), (Action::Encrypt, Status::Written, 16));
+
+        let mut ciphertext = b"This is a secret".to_vec();
+        apply_keystream(&KEY, &mut ciphertext).unwrap();
+        assert_eq!(outcome_for(&ciphertext, &options(None)).action, Action::Decrypt);
+    }
+
+    #[test]
+    fn entropy_guess_tells_binary_plaintext_from_ciphertext() {
+        let counts = |data: &[u8]| {
+            let mut counts = [0; 256];
+            entropy::count_bytes(&mut counts, data);
+            counts
+        };
+        let zeros = counts(&[0; 4096]);
+        let mut random = [0; 4096];
+        apply_keystream(&KEY, &mut random).unwrap();
+        let random = counts(&random);
+
+        // All-zero data is binary but plaintext: only entropy gets it right
+        assert!(looks_like_plaintext(&zeros, Guess::Entropy));
+        assert!(!looks_like_plaintext(&zeros, Guess::Printable));
+        assert!(!looks_like_plaintext(&random, Guess::Entropy));
+        assert!(!looks_like_plaintext(&random, Guess::Printable));
+
+        // Too short for entropy to say much, printable text decides
+        assert!(looks_like_plaintext(&counts(b"This is a secret"), Guess::Entropy));
+        assert!(!looks_like_plaintext(&counts(&[0; 16]), Guess::Entropy));
+        assert!(!looks_like_plaintext(&[0; 256], Guess::Entropy));
+    }
+
+    #[test]
+    fn explicit_mode_wins() {
+        assert_eq!(outcome_for(b"This is a secret", &options(Some(Mode::Decrypt))).action, Action::Decrypt);
+        assert_eq!(outcome_for(&[0xff; 32], &options(Some(Mode::Encrypt))).action, Action::Encrypt);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn chunk_buffer_is_wiped() {
+        let mut buffer = vec![0; 4];
+        let contents = read_chunked(&b"This is a secret"[..], &mut buffer, 0).unwrap();
+        assert_eq!(&contents[..], b"This is a secret");
+        assert_eq!(buffer, [0; 4]);
+    }
+
+    #[test]
+    fn header_means_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"This is a secret").unwrap();
+        let path = path.as_path();
+
+        let nonces = NonceRegistry::new(RunNonceSource::new(None));
+        assert_eq!(process_file(path, None, &options(None), Some(&nonces)).unwrap().action, Action::Encrypt);
+        assert_eq!(process_file(path, None, &options(None), None).unwrap().action, Action::Decrypt);
+        assert_eq!(fs::read(path).unwrap(), b"This is a secret");
+    }
+
+    #[test]
+    fn headerless_plan_refuses_to_decrypt_layers_without_a_header() {
+        let path = Path::new("file");
+        let layered = Options { keys: vec![KEY.to_vec(), KEY.to_vec()], ..options(Some(Mode::Decrypt)) };
+        let e = layered.headerless_plan(path, None, || Ok(false)).unwrap_err();
+        assert!(e.to_string().contains("key layers always carry one"), "{}", e);
+
+        // Encrypting them writes a header, describing both layers
+        let layered = Options { mode: None, ..layered };
+        let (action, header, layer_keys) = layered.headerless_plan(path, None, || unreachable!()).unwrap();
+        assert_eq!((action, header.unwrap().layers, layer_keys.len()), (Action::Encrypt, Some(2), 2));
+
+        let (action, header, layer_keys) = options(None).headerless_plan(path, None, || Ok(false)).unwrap();
+        assert_eq!((action, header, layer_keys), (Action::Decrypt, None, vec![KEY.to_vec()]));
+    }
+
+    #[test]
+    fn jobs_process_files_at_the_same_time() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let (in_flight, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let process = |path: &Path| {
+            most.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            path.to_path_buf()
+        };
+        let paths: Vec<PathBuf> = (0..8).map(|i| PathBuf::from(format!("file{}", i))).collect();
+        let mut reported = Vec::new();
+        process_in_parallel(paths.clone(), 4, &process, &mut |path, processed| {
+            assert_eq!(path, processed);
+            reported.push(path);
+            Ok(())
+        })
+        .unwrap();
+
+        reported.sort();
+        assert_eq!(reported, paths);
+        // Workers taking turns on the queue's lock would never have two files in flight
+        assert!(most.load(Ordering::SeqCst) > 1, "files were processed one at a time");
+    }
+}
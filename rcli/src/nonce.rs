@@ -0,0 +1,177 @@
+use crate::header::NONCE_LEN;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+use std::sync::Mutex;
+
+pub type Nonce = [u8; NONCE_LEN];
+
+/// Source of random nonces
+pub trait NonceSource {
+    fn fill(&mut self, nonce: &mut Nonce) -> io::Result<()>;
+}
+
+/// Nonces drawn from the OS CSPRNG
+pub struct OsNonceSource;
+
+impl NonceSource for OsNonceSource {
+    fn fill(&mut self, nonce: &mut Nonce) -> io::Result<()> {
+        Ok(getrandom::getrandom(nonce)?)
+    }
+}
+
+/// Deterministic nonces for the hidden `--seed` flag, so tests can assert exact ciphertext:
+/// the `n`th nonce is the first 16 bytes of SHA-256("rcli-seed" || seed || n), both big-endian
+/// u64s. Anyone who knows or guesses the seed knows every nonce, which is for testing only.
+pub struct SeededNonceSource {
+    seed: u64,
+    drawn: u64,
+}
+
+impl SeededNonceSource {
+    pub fn new(seed: u64) -> Self {
+        SeededNonceSource { seed, drawn: 0 }
+    }
+}
+
+impl NonceSource for SeededNonceSource {
+    fn fill(&mut self, nonce: &mut Nonce) -> io::Result<()> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"rcli-seed");
+        hasher.update(self.seed.to_be_bytes());
+        hasher.update(self.drawn.to_be_bytes());
+        nonce.copy_from_slice(&hasher.finalize()[..NONCE_LEN]);
+        self.drawn += 1;
+        Ok(())
+    }
+}
+
+/// Where a run's nonces come from: the OS, unless `--seed` is given
+pub enum RunNonceSource {
+    Os(OsNonceSource),
+    Seeded(SeededNonceSource),
+}
+
+impl RunNonceSource {
+    pub fn new(seed: Option<u64>) -> Self {
+        match seed {
+            Some(seed) => RunNonceSource::Seeded(SeededNonceSource::new(seed)),
+            None => RunNonceSource::Os(OsNonceSource),
+        }
+    }
+}
+
+impl NonceSource for RunNonceSource {
+    fn fill(&mut self, nonce: &mut Nonce) -> io::Result<()> {
+        match self {
+            RunNonceSource::Os(source) => source.fill(nonce),
+            RunNonceSource::Seeded(source) => source.fill(nonce),
+        }
+    }
+}
+
+/// Hands out nonces that are unique within a single run.
+///
+/// Two files encrypted under the same key and nonce share a keystream, and XORing the two
+/// ciphertexts cancels it out entirely. A 128-bit collision is astronomically unlikely, but it's
+/// cheap to rule out, so every nonce is remembered and a repeat is redrawn. The workers of a
+/// `--jobs` run share one registry, so drawing only needs a shared reference.
+pub struct NonceRegistry<S: NonceSource> {
+    drawn: Mutex<Drawn<S>>,
+}
+
+struct Drawn<S> {
+    source: S,
+    seen: HashSet<Nonce>,
+    collisions: usize,
+}
+
+impl<S: NonceSource> NonceRegistry<S> {
+    pub fn new(source: S) -> Self {
+        NonceRegistry {
+            drawn: Mutex::new(Drawn {
+                source,
+                seen: HashSet::new(),
+                collisions: 0,
+            }),
+        }
+    }
+
+    /// Draw a nonce not handed out before by this registry
+    pub fn draw(&self) -> io::Result<Nonce> {
+        let mut drawn = self.drawn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut nonce = [0; NONCE_LEN];
+        loop {
+            drawn.source.fill(&mut nonce)?;
+            if drawn.seen.insert(nonce) {
+                return Ok(nonce);
+            }
+            drawn.collisions += 1;
+        }
+    }
+
+    /// Number of repeated nonces that had to be redrawn
+    pub fn collisions(&self) -> usize {
+        self.drawn.lock().unwrap_or_else(|e| e.into_inner()).collisions
+    }
+}
+
+/// Per-file RC4 key: SHA-256(key || nonce)
+pub fn derive_key(key_bytes: &[u8], nonce: &Nonce) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(key_bytes);
+    hasher.update(nonce);
+    hasher.finalize().into()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays a fixed sequence of nonces
+    struct ScriptedSource(Vec<Nonce>);
+
+    impl NonceSource for ScriptedSource {
+        fn fill(&mut self, nonce: &mut Nonce) -> io::Result<()> {
+            *nonce = self.0.remove(0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn collision_is_redrawn() {
+        let (a, b) = ([0xaa; NONCE_LEN], [0xbb; NONCE_LEN]);
+        let registry = NonceRegistry::new(ScriptedSource(vec![a, a, b]));
+
+        assert_eq!(registry.draw().unwrap(), a);
+        assert_eq!(registry.collisions(), 0);
+
+        // The source repeats `a`: the registry must notice and hand out a fresh nonce instead
+        assert_eq!(registry.draw().unwrap(), b);
+        assert_eq!(registry.collisions(), 1);
+    }
+
+    #[test]
+    fn seeded_nonces_repeat_across_runs_only() {
+        let draw = |seed| {
+            let registry = NonceRegistry::new(SeededNonceSource::new(seed));
+            [registry.draw().unwrap(), registry.draw().unwrap()]
+        };
+
+        assert_eq!(draw(7), draw(7));
+        assert_ne!(draw(7)[0], draw(7)[1]);
+        assert_ne!(draw(7), draw(8));
+    }
+
+    #[test]
+    fn distinct_nonces_give_distinct_keys() {
+        let key = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+        let registry = NonceRegistry::new(OsNonceSource);
+        let (n1, n2) = (registry.draw().unwrap(), registry.draw().unwrap());
+
+        assert_ne!(n1, n2);
+        assert_ne!(derive_key(&key, &n1), derive_key(&key, &n2));
+        assert_eq!(derive_key(&key, &n1), derive_key(&key, &n1));
+    }
+}
@@ -0,0 +1,75 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where `--output-dir` puts the result for `file`, found under the source `root`: the same path
+/// relative to `root`, under `output_dir`. A lone file (`file == root`) goes directly in `output_dir`.
+///
+/// `root` and `file` are compared as given, so a relative root mirrors just as well as an absolute
+/// one whatever the output directory is. Anything that could land outside `output_dir` is refused.
+pub fn mirrored_path(root: &Path, file: &Path, output_dir: &Path) -> io::Result<PathBuf> {
+    let relative = match file.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        Ok(_) => file.file_name().map(Path::new).ok_or_else(|| escapes(file))?,
+        Err(_) => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not under {}", file.display(), root.display()),
+        )),
+    };
+
+    let mut out = output_dir.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            // `..`, a root or a drive prefix would point out of the output directory
+            _ => return Err(escapes(file)),
+        }
+    }
+
+    // Nothing was pushed, e.g. for a file named `..`
+    if out == output_dir {
+        return Err(escapes(file));
+    }
+    Ok(out)
+}
+
+fn escapes(file: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("output for {} would be outside --output-dir", file.display()),
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_relative_and_absolute_roots() {
+        for (root, file) in [
+            ("src", "src/a/b.txt"),
+            ("./src", "./src/a/b.txt"),
+            ("../data/src", "../data/src/a/b.txt"),
+            ("/data/src", "/data/src/a/b.txt"),
+        ] {
+            let out = mirrored_path(Path::new(root), Path::new(file), Path::new("/out")).unwrap();
+            assert_eq!(out, Path::new("/out/a/b.txt"), "{} in {}", file, root);
+        }
+    }
+
+    #[test]
+    fn lone_file_goes_in_output_dir() {
+        for file in ["b.txt", "a/b.txt", "../a/b.txt", "/a/b.txt"] {
+            let out = mirrored_path(Path::new(file), Path::new(file), Path::new("/out")).unwrap();
+            assert_eq!(out, Path::new("/out/b.txt"));
+        }
+    }
+
+    #[test]
+    fn nothing_escapes_output_dir() {
+        assert!(mirrored_path(Path::new("src"), Path::new("src/../../etc/passwd"), Path::new("/out")).is_err());
+        assert!(mirrored_path(Path::new("src"), Path::new("other/b.txt"), Path::new("/out")).is_err());
+        assert!(mirrored_path(Path::new(".."), Path::new(".."), Path::new("/out")).is_err());
+    }
+}
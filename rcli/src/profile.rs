@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Environment variable overriding the profiles file location
+pub const PROFILES_ENV: &str = "RCLI_PROFILES";
+
+/// Parse a profiles file: `[name]` sections, each followed by lines of flags.
+///
+/// ```text
+/// # Always keep a backup and use fresh nonces
+/// [safe]
+/// --backup
+/// --nonce --normalize-eol
+/// ```
+///
+/// Flags are split on whitespace, quoting isn't supported. `#` starts a comment line.
+pub fn parse_profiles(text: &str) -> io::Result<HashMap<String, Vec<String>>> {
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim().to_string();
+            profiles.entry(name.clone()).or_default();
+            current = Some(name);
+            continue;
+        }
+
+        let name = current.as_ref().ok_or_else(|| invalid(idx + 1, "flags before the first [profile] section"))?;
+        for flag in line.split_whitespace() {
+            if flag == "--profile" || flag.starts_with("--profile=") {
+                return Err(invalid(idx + 1, "profiles can't include other profiles"));
+            }
+            profiles.get_mut(name).unwrap().push(flag.to_string());
+        }
+    }
+
+    Ok(profiles)
+}
+
+/// Expand `--profile <NAME>` in the command line into the profile's flags.
+///
+/// The profile's flags are inserted right after the program name (and `encrypt` or `decrypt`),
+/// ahead of everything given on the command line, so an option given explicitly overrides the
+/// profile's value for it.
+pub fn expand(args: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    let Some(name) = profile_name(&args) else {
+        return Ok(args);
+    };
+
+    let path = profiles_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no profiles file: set RCLI_PROFILES or HOME"))?;
+    let text = fs::read_to_string(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let profiles = parse_profiles(&text)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    let flags = profiles.get(&name).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no profile '{}' in {}", name, path.display()))
+    })?;
+
+    let mut expanded = Vec::with_capacity(args.len() + flags.len());
+    let mut args = args.into_iter().peekable();
+    expanded.extend(args.next());
+    // `rcli encrypt` and `rcli decrypt` take the flags after the subcommand
+    expanded.extend(args.next_if(|arg| arg == "encrypt" || arg == "decrypt"));
+    expanded.extend(flags.iter().map(OsString::from));
+    expanded.extend(args);
+    Ok(expanded)
+}
+
+// Value of the (last) `--profile NAME` or `--profile=NAME` before any `--`
+fn profile_name(args: &[OsString]) -> Option<String> {
+    let mut name = None;
+    let mut args = args.iter().skip(1).map(|a| a.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--profile" {
+            name = args.next().map(|n| n.into_owned());
+        } else if let Some(n) = arg.strip_prefix("--profile=") {
+            name = Some(n.to_string());
+        }
+    }
+    name
+}
+
+// `$RCLI_PROFILES`, else `$XDG_CONFIG_HOME/rcli/profiles.conf`, else `~/.config/rcli/profiles.conf`
+fn profiles_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(PROFILES_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("rcli").join("profiles.conf"))
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os_args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn parse_sections() {
+        let profiles = parse_profiles("# comment\n[safe]\n--backup\n--nonce --normalize-eol\n\n[empty]\n").unwrap();
+        assert_eq!(profiles["safe"], ["--backup", "--nonce", "--normalize-eol"]);
+        assert!(profiles["empty"].is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_profiles("--backup\n").is_err());
+        assert!(parse_profiles("[a]\n--profile b\n").is_err());
+    }
+
+    #[test]
+    fn profile_name_forms() {
+        assert_eq!(profile_name(&os_args(&["rcli", "--profile", "safe"])), Some("safe".to_string()));
+        assert_eq!(profile_name(&os_args(&["rcli", "--profile=safe", "-r"])), Some("safe".to_string()));
+        assert_eq!(profile_name(&os_args(&["rcli", "-r"])), None);
+        assert_eq!(profile_name(&os_args(&["rcli", "--", "--profile", "safe"])), None);
+    }
+
+    #[test]
+    fn no_profile_leaves_args_alone() {
+        let args = os_args(&["rcli", "--file", "x"]);
+        assert_eq!(expand(args.clone()).unwrap(), args);
+    }
+}
@@ -0,0 +1,79 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{self, IsTerminal};
+
+/// `--progress` over the files of a run, drawn on stderr.
+///
+/// A bar redraws itself with terminal control codes, which end up as garbage in a log when stderr
+/// is piped or redirected. So the bar is only used on a terminal; otherwise progress is reported
+/// as plain text lines, one per 10% of the files.
+pub enum Progress {
+    Bar(ProgressBar),
+    Lines { total: usize, done: usize, reported: usize },
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self::with_terminal(total, io::stderr().is_terminal())
+    }
+
+    fn with_terminal(total: usize, terminal: bool) -> Self {
+        if !terminal {
+            return Progress::Lines { total, done: 0, reported: 0 };
+        }
+
+        let bar = ProgressBar::with_draw_target(Some(total as u64), ProgressDrawTarget::stderr());
+        bar.set_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} files ({eta})")
+                .expect("valid progress template"),
+        );
+        Progress::Bar(bar)
+    }
+
+    /// Run `print` (which writes to stdout) without tearing the bar
+    pub fn suspend<F: FnOnce()>(&self, print: F) {
+        match self {
+            Progress::Bar(bar) => bar.suspend(print),
+            Progress::Lines { .. } => print(),
+        }
+    }
+
+    /// Count one more file as done
+    pub fn inc(&mut self) {
+        match self {
+            Progress::Bar(bar) => bar.inc(1),
+            Progress::Lines { total, done, reported } => {
+                *done += 1;
+                // Tenths of the files done so far
+                let tenths = *done * 10 / (*total).max(1);
+                if tenths > *reported {
+                    *reported = tenths;
+                    eprintln!("Progress: {} of {} files", done, total);
+                }
+            }
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Progress::Bar(bar) = self {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_terminal_means_plain_lines() {
+        let mut progress = Progress::with_terminal(25, false);
+        for _ in 0..25 {
+            progress.inc();
+        }
+        match progress {
+            Progress::Lines { done, reported, .. } => assert_eq!((done, reported), (25, 10)),
+            Progress::Bar(_) => panic!("drew a bar without a terminal"),
+        }
+    }
+}
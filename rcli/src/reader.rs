@@ -0,0 +1,167 @@
+use crate::header::{self, Header, MAGIC};
+use crate::layers::{self, LayerStream};
+use std::io::{self, Read};
+
+/// Decrypts a stream written by `rcli` with a header as it's read: `open` reads and checks the
+/// header, then reads yield only the decrypted payload, so
+/// `io::copy(&mut HeaderReader::open(&keys, file)?, &mut out)` recovers what was encrypted.
+///
+/// The nonce, key normalization, key layers and rekeying recorded in the header are followed, and a payload that
+/// doesn't have the length the header records fails the read that reaches its end. Transforms
+/// (`--normalize-eol`, `--trim-trailing-newline`, `--pad-to`) rewrite the plaintext as a whole
+/// and aren't undone: `header()` tells what to undo.
+///
+/// Not built on `rc4::io::Rc4Reader`, which runs one key's keystream: the layers here are each
+/// a `LayerStream`, which switches keys mid-stream to follow `--rekey-every`.
+pub struct HeaderReader<R: Read> {
+    inner: R,
+    header: Header,
+    layers: Vec<LayerStream>,
+    // Payload bytes read so far
+    read: u64,
+}
+
+impl<R: Read> HeaderReader<R> {
+    /// Read the header from the start of `inner`, however many reads it arrives in, and set up
+    /// decryption with `keys` (`--key` followed by any `--key-layer`s)
+    pub fn open(keys: &[Vec<u8>], mut inner: R) -> io::Result<Self> {
+        let mut start = [0u8; MAGIC.len() + 2];
+        read_header_bytes(&mut inner, &mut start)?;
+        if !start.starts_with(MAGIC) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no rcli header"));
+        }
+
+        let fields_len = u16::from_be_bytes([start[MAGIC.len()], start[MAGIC.len() + 1]]) as usize;
+        let mut raw = start.to_vec();
+        raw.resize(start.len() + fields_len, 0);
+        read_header_bytes(&mut inner, &mut raw[start.len()..])?;
+        let (header, _) = Header::parse(&raw)?.expect("starts with the magic bytes");
+
+        let layer_count = header.layers.unwrap_or(1) as usize;
+        if layer_count != keys.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("encrypted under {} key layer(s), {} given", layer_count, keys.len()),
+            ));
+        }
+
+        let layers = layers::layer_keys(keys, &header)
+            .into_iter()
+            .map(|key| LayerStream::new(key, header.rekey_every))
+            .collect::<io::Result<_>>()?;
+        Ok(HeaderReader { inner, header, layers, read: 0 })
+    }
+
+    /// The header read by `open`
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+}
+
+impl<R: Read> Read for HeaderReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        if let Some(len) = self.header.payload_len {
+            let found = match self.read {
+                read if read > len => Some("more".to_string()),
+                read if n == 0 && !buf.is_empty() && read < len => Some(read.to_string()),
+                _ => None,
+            };
+            if let Some(found) = found {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} (expected {} payload bytes, found {})", header::TRUNCATED, len, found),
+                ));
+            }
+        }
+
+        // Each layer is a keystream XORed over the data, so they come off in any order
+        for layer in &mut self.layers {
+            layer.apply(&mut buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+// `read_exact`, with running out of data reported like any other truncated file
+fn read_header_bytes<R: Read>(inner: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    inner.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::InvalidData, format!("Invalid rcli header: {}", header::TRUNCATED)),
+        _ => e,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    // Hands out its data a few bytes per read, so the header arrives split across reads
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(buf.len()).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    // `plaintext` encrypted the way `process_file` writes it
+    fn encrypted(plaintext: &[u8], keys: &[Vec<u8>], mut header: Header) -> Vec<u8> {
+        header.layers = (keys.len() > 1).then_some(keys.len() as u8);
+        header.payload_len = Some(plaintext.len() as u64);
+        let mut payload = plaintext.to_vec();
+        layers::encrypt(&layers::layer_keys(keys, &header), header.rekey_every, &mut payload).unwrap();
+        [header.encode(), payload].concat()
+    }
+
+    #[test]
+    fn yields_only_the_decrypted_payload() {
+        let plaintext: Vec<u8> = (0..5000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+        let keys = [KEY.to_vec(), vec![0x01, 0x02, 0x03, 0x04, 0x05]];
+        let headers = [
+            Header { nonce: Some([0xab; header::NONCE_LEN]), ..Default::default() },
+            Header { rekey_every: Some(777), ..Default::default() },
+            Header { nonce: Some([0xcd; header::NONCE_LEN]), rekey_every: Some(1000), ..Default::default() },
+        ];
+
+        for header in headers {
+            for keys in [&keys[..1], &keys[..]] {
+                let data = encrypted(&plaintext, keys, header.clone());
+                for step in [1, 3, 7, 4096] {
+                    let mut out = Vec::new();
+                    io::copy(&mut HeaderReader::open(keys, Trickle(&data, step)).unwrap(), &mut out).unwrap();
+                    assert!(out == plaintext, "{:?}, {} keys, {} bytes a read", header, keys.len(), step);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_streams_fail() {
+        let data = encrypted(b"This is a secret", &[KEY.to_vec()], Header { nonce: Some([0xab; 16]), ..Default::default() });
+
+        // In the payload, in the header, and longer than the header says
+        let mut reader = HeaderReader::open(&[KEY.to_vec()], &data[..data.len() - 1]).unwrap();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), format!("{} (expected 16 payload bytes, found 15)", header::TRUNCATED));
+        assert!(HeaderReader::open(&[KEY.to_vec()], &data[..10]).err().unwrap().to_string().contains(header::TRUNCATED));
+        let longer = [&data[..], b"!"].concat();
+        assert!(HeaderReader::open(&[KEY.to_vec()], &longer[..]).unwrap().read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn wrong_layer_count_or_no_header_is_refused() {
+        let data = encrypted(b"This is a secret", &[KEY.to_vec(), KEY.to_vec()], Header::default());
+        let err = HeaderReader::open(&[KEY.to_vec()], &data[..]).err().unwrap();
+        assert_eq!(err.to_string(), "encrypted under 2 key layer(s), 1 given");
+
+        assert!(HeaderReader::open(&[KEY.to_vec()], &b"This is a secret"[..]).is_err());
+    }
+}
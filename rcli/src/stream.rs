@@ -0,0 +1,341 @@
+use crate::context::{failed, in_phase, Phase};
+use crate::header::MAGIC;
+use crate::layers::LayerWriter;
+use crate::nonce::{NonceRegistry, RunNonceSource};
+use crate::reader::HeaderReader;
+use crate::wipe::Wiped;
+use crate::{entropy, looks_like_plaintext, verify, wipe, write_result, Action, FileOutcome, Mode, Options, Status};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Files are streamed into the temp file their result is written to this much at a time, the read
+/// size measured fastest (see `benches/io_buffer.rs`)
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// En/decrypt `file` (`file_path`, locked by the caller) the way `process_file` does, holding no
+/// more than a chunk of it in memory at any time. Like the in-memory path, the result goes to a
+/// temp file that's renamed into place, so an interrupted run leaves the original intact.
+///
+/// Returns `None`, with `file` rewound, when it can only be processed whole: transforms rewrite
+/// the plaintext as a whole, so encrypting with them, or decrypting a header that records any,
+/// falls back to `process_file`'s in-memory path.
+pub fn stream_file(
+    file_path: &Path,
+    mut file: &File,
+    output_path: Option<&Path>,
+    opts: &Options,
+    nonces: Option<&NonceRegistry<RunNonceSource>>,
+) -> io::Result<Option<FileOutcome>> {
+    let keys = &opts.keys;
+    let len = file.metadata().map_err(in_phase(file_path, Phase::Read))?.len();
+
+    // Explicitly encrypting means even a file that's already encrypted gets another pass
+    let has_header = opts.mode != Some(Mode::Encrypt) && starts_with_magic(file).map_err(in_phase(file_path, Phase::Read))?;
+    if has_header {
+        let reader = HeaderReader::open(keys, file).map_err(in_phase(file_path, Phase::Decrypt))?;
+        let header = reader.header();
+        if header.crlf || header.trailing_eol.is_some() || header.pad_to.is_some() {
+            file.seek(SeekFrom::Start(0)).map_err(in_phase(file_path, Phase::Read))?;
+            return Ok(None);
+        }
+        // Reading and decrypting happen as the result is written, so their errors are named by operation
+        let checksum = write_streamed(file_path, file, output_path, opts, |out, buffer| {
+            let mut out = Hashing::new(out);
+            pump(reader, &mut out, buffer)?;
+            Ok(out.finish())
+        })?;
+        return outcome(file_path, output_path, opts, Action::Decrypt, len, checksum).map(Some);
+    }
+
+    if !opts.transforms.is_empty() {
+        return Ok(None);
+    }
+
+    // The same choices as `process_file`, header or not, except that the plaintext is never all there to look at
+    let (action, mut header, streams) = opts.headerless_plan(file_path, nonces, || {
+        Ok(looks_like_plaintext(&byte_counts(file).map_err(in_phase(file_path, Phase::Read))?, opts.guess))
+    })?;
+    if let Some(header) = &mut header {
+        header.payload_len = Some(len);
+    }
+
+    let rekey_every = opts.rekey_every.filter(|_| header.is_some());
+    let checksum = write_streamed(file_path, file, output_path, opts, |out, buffer| {
+        let mut out = Hashing::new(out);
+        if let Some(header) = &header {
+            out.write_all(&header.encode()).map_err(failed("write"))?;
+        }
+        if pump_through_layers(file, &streams, rekey_every, &mut out, buffer)? != len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the file changed while being read"));
+        }
+        Ok(out.finish())
+    })?;
+    outcome(file_path, output_path, opts, action, len, checksum).map(Some)
+}
+
+/// En/decrypt stdin to stdout (`--file -`) the way `stream_file` does a file, a chunk at a time.
+/// A pipe can't be rewound, so the direction is guessed from its first chunk, and a header written
+/// for it doesn't record the payload's length. Returns what was done and the bytes read.
+///
+/// Transforms need the whole plaintext, so encrypting with them, or decrypting a header that records
+/// any, is refused rather than holding all of stdin in memory.
+pub fn stream_stdio(opts: &Options, nonces: Option<&NonceRegistry<RunNonceSource>>) -> io::Result<(Action, u64)> {
+    let stdio = Path::new("-");
+    let no_transforms = || {
+        let e = io::Error::new(io::ErrorKind::InvalidInput, "transforms (--normalize-eol, --trim-trailing-newline, --pad-to) need a file, not stdin");
+        in_phase(stdio, Phase::Read)(e)
+    };
+    if !opts.transforms.is_empty() {
+        return Err(no_transforms());
+    }
+
+    let keys = &opts.keys;
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let first = fill_chunk(&mut stdin, &mut buffer).map_err(in_phase(stdio, Phase::Read))?;
+    let head = Wiped::from(buffer[..first].to_vec());
+    let input = (&head[..]).chain(stdin);
+
+    // Errors while streaming are named by operation, and by the direction chosen
+    let (action, result) = if opts.mode != Some(Mode::Encrypt) && head.starts_with(MAGIC) {
+        let reader = HeaderReader::open(keys, input).map_err(in_phase(stdio, Phase::Decrypt))?;
+        let header = reader.header();
+        if header.crlf || header.trailing_eol.is_some() || header.pad_to.is_some() {
+            return Err(no_transforms());
+        }
+        (Action::Decrypt, pump(reader, &mut stdout, &mut buffer))
+    } else {
+        let (action, header, streams) = opts.headerless_plan(stdio, nonces, || {
+            let mut counts = [0; 256];
+            entropy::count_bytes(&mut counts, &head);
+            Ok(looks_like_plaintext(&counts, opts.guess))
+        })?;
+
+        let rekey_every = opts.rekey_every.filter(|_| header.is_some());
+        let result = header
+            .map(|header| stdout.write_all(&header.encode()).map_err(failed("write")))
+            .transpose()
+            .and_then(|_| pump_through_layers(input, &streams, rekey_every, &mut stdout, &mut buffer));
+        (action, result)
+    };
+    wipe::wipe_slice(&mut buffer);
+
+    let phase = if action == Action::Encrypt { Phase::Encrypt } else { Phase::Decrypt };
+    let read = result.and_then(|read| stdout.flush().map_err(failed("write")).map(|()| read));
+    read.map(|read| (action, read)).map_err(in_phase(stdio, phase))
+}
+
+// Read into `buffer` until it's full or `input` ends, returning the bytes read
+fn fill_chunk<R: Read>(mut input: R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// Write the result through `write_result`, `fill` streaming it into the temp file through a
+// chunk buffer, wiped afterwards. Returns what `fill` does: the SHA-256 of what was written
+fn write_streamed<F>(file_path: &Path, file: &File, output_path: Option<&Path>, opts: &Options, fill: F) -> io::Result<[u8; 32]>
+where
+    F: FnOnce(&mut File, &mut [u8]) -> io::Result<[u8; 32]>,
+{
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut checksum = [0; 32];
+    let result = write_result(file_path, file, output_path, opts.backup.as_deref(), |out| {
+        checksum = fill(out, &mut buffer)?;
+        Ok(())
+    });
+    wipe::wipe_slice(&mut buffer);
+    result.map(|()| checksum)
+}
+
+// Copy `input` to `out` a chunk at a time. Returns the bytes copied
+fn pump<R: Read, W: Write>(mut input: R, out: &mut W, buffer: &mut [u8]) -> io::Result<u64> {
+    let mut copied = 0;
+    loop {
+        let n = match input.read(buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(failed("read")(e)),
+        };
+        out.write_all(&buffer[..n]).map_err(failed("write"))?;
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+// `pump` through a `LayerWriter` for each of `keys`, so `out` gets `input` en/decrypted under every layer.
+// Returns the bytes copied
+fn pump_through_layers<R: Read, W: Write>(
+    input: R,
+    keys: &[Vec<u8>],
+    rekey_every: Option<u64>,
+    out: &mut W,
+    buffer: &mut [u8],
+) -> io::Result<u64> {
+    // `Rc4Writer` hands its output on 4 KiB at a time, gathered back into chunk-sized writes here
+    let mut writer: Box<dyn Write + '_> = Box::new(BufWriter::with_capacity(CHUNK_SIZE, out));
+    for key in keys {
+        writer = Box::new(LayerWriter::new(key.clone(), rekey_every, writer)?);
+    }
+    let copied = pump(input, &mut writer, buffer)?;
+    writer.flush().map_err(failed("write"))?;
+    Ok(copied)
+}
+
+// Hashes everything written through it on its way into `inner`, for `--verify-results` to check the
+// written file against
+struct Hashing<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: Sha256,
+}
+
+impl<'a, W: Write> Hashing<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        Hashing { inner, hasher: Sha256::new() }
+    }
+
+    // The SHA-256 of everything written
+    fn finish(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+}
+
+impl<W: Write> Write for Hashing<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn outcome(file_path: &Path, output_path: Option<&Path>, opts: &Options, action: Action, len: u64, checksum: [u8; 32]) -> io::Result<FileOutcome> {
+    let written = output_path.unwrap_or(file_path);
+    let verification = match opts.verify {
+        true => Some((written.to_path_buf(), verify::check_written_digest(written, checksum).map_err(in_phase(file_path, Phase::Verify))?)),
+        false => None,
+    };
+    Ok(FileOutcome { path: file_path.to_path_buf(), action, bytes: len as usize, status: Status::Written, verification })
+}
+
+// Whether `file` starts with the header's magic bytes, leaving it rewound
+fn starts_with_magic(mut file: &File) -> io::Result<bool> {
+    let mut start = [0u8; MAGIC.len()];
+    let found = file.read_exact(&mut start).is_ok() && &start == MAGIC;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(found)
+}
+
+// How often each byte value occurs in the whole file, read through once and rewound, to guess its direction from
+fn byte_counts(mut file: &File) -> io::Result<[u64; 256]> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut counts = [0; 256];
+    let result = loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) => entropy::count_bytes(&mut counts, &buffer[..n]),
+            Err(e) => break Err(e),
+        }
+    };
+    wipe::wipe_slice(&mut buffer);
+    result?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(counts)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Header;
+    use crate::layers;
+    use crate::limit::OpenFiles;
+    use rc4::Rc4;
+    use std::fs;
+
+    const KEY: [u8; 5] = [0x4b, 0x8e, 0x29, 0x87, 0x80];
+
+    fn options(mode: Option<Mode>, keys: Vec<Vec<u8>>, rekey_every: Option<u64>) -> Options {
+        Options {
+            mode,
+            keys,
+            transforms: Vec::new(),
+            skip_locked: false,
+            backup: None,
+            rekey_every,
+            verify: true,
+            open_files: OpenFiles::new(1),
+            seed: None,
+            normalize_key: false,
+            guess: crate::Guess::Entropy,
+            header: false,
+        }
+    }
+
+    #[test]
+    fn streams_the_same_bytes_as_the_whole_file_cipher() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let original: Vec<u8> = (0..300_000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+
+        // Built the in-memory way, one keystream over the whole plaintext, rather than through `stream_file`
+        let mut single = original.clone();
+        Rc4::apply_keystream_static(&KEY, &mut single).unwrap();
+        let two_keys = vec![KEY.to_vec(), vec![0x01, 0x02, 0x03, 0x04, 0x05]];
+        let header = Header {
+            layers: Some(2),
+            rekey_every: Some(100_000),
+            payload_len: Some(original.len() as u64),
+            ..Default::default()
+        };
+        let mut layered = original.clone();
+        layers::encrypt(&layers::layer_keys(&two_keys, &header), Some(100_000), &mut layered).unwrap();
+        let layered = [header.encode(), layered].concat();
+
+        for (opts, expected) in [
+            (options(None, vec![KEY.to_vec()], None), single),
+            (options(Some(Mode::Encrypt), two_keys.clone(), Some(100_000)), layered),
+        ] {
+            fs::write(&path, &original).unwrap();
+
+            let outcome = stream_file(&path, &File::open(&path).unwrap(), None, &opts, None).unwrap().unwrap();
+            assert_eq!((outcome.action, outcome.bytes), (Action::Encrypt, original.len()));
+            assert!(outcome.verification.unwrap().1.passed);
+            assert!(fs::read(&path).unwrap() == expected);
+
+            let opts = Options { mode: None, ..opts };
+            let outcome = stream_file(&path, &File::open(&path).unwrap(), None, &opts, None).unwrap().unwrap();
+            assert_eq!(outcome.action, Action::Decrypt);
+            assert!(fs::read(&path).unwrap() == original);
+        }
+    }
+
+    #[test]
+    fn transforms_fall_back_to_the_whole_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"line\r\n").unwrap();
+
+        let mut opts = options(None, vec![KEY.to_vec()], None);
+        opts.transforms.push(Box::new(crate::transform::NormalizeEol));
+        crate::process_file(&path, None, &opts, None).unwrap();
+
+        let file = File::open(&path).unwrap();
+        assert!(stream_file(&path, &file, None, &options(None, vec![KEY.to_vec()], None), None).unwrap().is_none());
+        assert_eq!((&file).stream_position().unwrap(), 0);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{Action, FileOutcome, Status};
+use std::fmt;
+
+/// The concluding line of a run, e.g. `3 encrypted, 0 decrypted, 0 skipped, 12.4 MiB processed`,
+/// tallied from each file's outcome
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub encrypted: usize,
+    pub decrypted: usize,
+    /// Locked with `--skip-locked`, or empty
+    pub skipped: usize,
+    /// Bytes read from the files en/decrypted
+    pub bytes: u64,
+}
+
+impl Summary {
+    pub fn add(&mut self, outcome: &FileOutcome) {
+        self.add_action(outcome.action, outcome.status, outcome.bytes as u64);
+    }
+
+    pub fn add_action(&mut self, action: Action, status: Status, bytes: u64) {
+        match (action, status) {
+            (_, Status::Locked | Status::Empty) | (Action::Skip, _) => self.skipped += 1,
+            (Action::Encrypt, _) => self.encrypted += 1,
+            (Action::Decrypt, _) => self.decrypted += 1,
+        }
+        self.bytes += bytes;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} encrypted, {} decrypted, {} skipped, {} processed",
+            self.encrypted,
+            self.decrypted,
+            self.skipped,
+            human_size(self.bytes)
+        )
+    }
+}
+
+// `512 B`, `1.5 KiB`, `12.4 MiB`: bytes below a KiB exactly, anything larger to one decimal
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_read_in_binary_units() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(13_002_342), "12.4 MiB");
+        assert_eq!(human_size(5 << 30), "5.0 GiB");
+    }
+
+    #[test]
+    fn tallies_each_outcome() {
+        let mut summary = Summary::default();
+        summary.add_action(Action::Encrypt, Status::Written, 100);
+        summary.add_action(Action::Encrypt, Status::Written, 28);
+        summary.add_action(Action::Decrypt, Status::Written, 900);
+        summary.add_action(Action::Skip, Status::Empty, 0);
+        summary.add_action(Action::Skip, Status::Locked, 0);
+        assert_eq!(summary.to_string(), "2 encrypted, 1 decrypted, 2 skipped, 1.0 KiB processed");
+    }
+}
@@ -0,0 +1,240 @@
+use crate::header::Header;
+use crate::wipe;
+use std::io;
+
+/// A reversible rewrite of the plaintext around the cipher step.
+///
+/// `pre_transform` runs on plaintext before encryption and records in the header whatever
+/// `post_transform` needs to undo it. `post_transform` runs on plaintext after decryption and
+/// must leave the data alone unless the header says the transform was applied, since every known
+/// transform is consulted when decrypting a file. `--jobs` workers share them, hence `Sync`.
+pub trait Transform: Sync {
+    fn pre_transform(&self, data: Vec<u8>, header: &mut Header) -> Vec<u8>;
+    fn post_transform(&self, data: Vec<u8>, header: &Header) -> io::Result<Vec<u8>>;
+}
+
+/// Every transform a header can refer to, in the order they're applied before encryption
+pub fn registered() -> Vec<Box<dyn Transform>> {
+    // The block size `PadTo` undoes is the header's, not its own
+    vec![Box::new(NormalizeEol), Box::new(TrimTrailingNewline), Box::new(PadTo(1))]
+}
+
+/// Run `transforms` over plaintext before encryption
+pub fn pre(transforms: &[Box<dyn Transform>], mut data: Vec<u8>, header: &mut Header) -> Vec<u8> {
+    for transform in transforms {
+        data = transform.pre_transform(data, header);
+    }
+    data
+}
+
+/// Undo `transforms` on decrypted plaintext, in reverse order
+pub fn post(transforms: &[Box<dyn Transform>], mut data: Vec<u8>, header: &Header) -> io::Result<Vec<u8>> {
+    for transform in transforms.iter().rev() {
+        data = transform.post_transform(data, header)?;
+    }
+    Ok(data)
+}
+
+/// `--normalize-eol`: CRLF line endings are stored as LF and restored on decryption.
+///
+/// Only applied when every LF in the file is part of a CRLF, because a file mixing both can't be
+/// restored exactly from the normalized form. Other files are encrypted unchanged.
+pub struct NormalizeEol;
+
+impl Transform for NormalizeEol {
+    fn pre_transform(&self, mut data: Vec<u8>, header: &mut Header) -> Vec<u8> {
+        let all_crlf = data
+            .iter()
+            .enumerate()
+            .all(|(idx, &b)| b != b'\n' || (idx > 0 && data[idx - 1] == b'\r'));
+        if !all_crlf || !data.contains(&b'\n') {
+            return data;
+        }
+
+        header.crlf = true;
+        let mut out = Vec::with_capacity(data.len());
+        for (idx, &b) in data.iter().enumerate() {
+            // Drop the CR of every CRLF
+            if b == b'\r' && data.get(idx + 1) == Some(&b'\n') {
+                continue;
+            }
+            out.push(b);
+        }
+        wipe::wipe(&mut data);
+        out
+    }
+
+    fn post_transform(&self, mut data: Vec<u8>, header: &Header) -> io::Result<Vec<u8>> {
+        if !header.crlf {
+            return Ok(data);
+        }
+
+        // Sized for the worst case, so it never reallocates (see `Wiped`)
+        let mut out = Vec::with_capacity(2 * data.len());
+        for &b in &data {
+            if b == b'\n' {
+                out.push(b'\r');
+            }
+            out.push(b);
+        }
+        wipe::wipe(&mut data);
+        Ok(out)
+    }
+}
+
+/// `--trim-trailing-newline`: line endings at the end of the file are stripped before encryption,
+/// and restored exactly on decryption.
+///
+/// Whether the file ended in a newline then doesn't change the ciphertext of the rest, the header
+/// records what was stripped (none at all included). Files ending in over 255 line ending bytes
+/// are encrypted unchanged.
+pub struct TrimTrailingNewline;
+
+impl Transform for TrimTrailingNewline {
+    fn pre_transform(&self, mut data: Vec<u8>, header: &mut Header) -> Vec<u8> {
+        let kept = data.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |idx| idx + 1);
+        if data.len() - kept > u8::MAX as usize {
+            return data;
+        }
+
+        header.trailing_eol = Some(data.split_off(kept));
+        data
+    }
+
+    fn post_transform(&self, mut data: Vec<u8>, header: &Header) -> io::Result<Vec<u8>> {
+        if let Some(eol) = &header.trailing_eol {
+            data.extend_from_slice(eol);
+        }
+        Ok(data)
+    }
+}
+
+/// `--pad-to N`: the plaintext is padded to a multiple of N bytes (at most 255) before encryption,
+/// so the ciphertext fits fixed-size records, and the padding is stripped again on decryption.
+///
+/// PKCS#7-style: 1 to N bytes are always added, each holding the number added, so the padding can
+/// be told from the data even when the data already was a multiple of N.
+pub struct PadTo(pub u8);
+
+impl Transform for PadTo {
+    fn pre_transform(&self, mut data: Vec<u8>, header: &mut Header) -> Vec<u8> {
+        let block = self.0.max(1);
+        let pad = block - (data.len() % block as usize) as u8;
+        header.pad_to = Some(block);
+
+        if data.capacity() < data.len() + pad as usize {
+            // Growing in place would free a copy of the plaintext without wiping it (see `Wiped`)
+            let mut out = Vec::with_capacity(data.len() + pad as usize);
+            out.extend_from_slice(&data);
+            wipe::wipe(&mut data);
+            data = out;
+        }
+        data.resize(data.len() + pad as usize, pad);
+        data
+    }
+
+    fn post_transform(&self, mut data: Vec<u8>, header: &Header) -> io::Result<Vec<u8>> {
+        let Some(block) = header.pad_to else {
+            return Ok(data);
+        };
+
+        let pad = data.last().copied().unwrap_or(0);
+        let valid = data.len().is_multiple_of(block as usize)
+            && (1..=block).contains(&pad)
+            && data[data.len() - pad as usize..].iter().all(|&b| b == pad);
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid padding, the file is corrupt or the key is wrong"));
+        }
+
+        data.truncate(data.len() - pad as usize);
+        Ok(data)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> (Vec<u8>, Header) {
+        let mut header = Header::default();
+        let normalized = NormalizeEol.pre_transform(data.to_vec(), &mut header);
+        let restored = NormalizeEol.post_transform(normalized.clone(), &header).unwrap();
+        assert_eq!(restored, data);
+        (normalized, header)
+    }
+
+    #[test]
+    fn crlf_is_normalized_and_restored() {
+        let (normalized, header) = round_trip(b"line 1\r\nline 2\r\n\r\nstray \r here\r\n");
+        assert!(header.crlf);
+        assert_eq!(normalized, b"line 1\nline 2\n\nstray \r here\n");
+    }
+
+    #[test]
+    fn mixed_and_lf_files_are_untouched() {
+        for data in [&b"line 1\r\nline 2\n"[..], b"line 1\nline 2\n", b"no newline", b"\n"] {
+            let (normalized, header) = round_trip(data);
+            assert!(!header.crlf);
+            assert_eq!(normalized, data);
+        }
+    }
+
+    #[test]
+    fn trailing_newlines_are_trimmed_and_restored() {
+        for (data, trimmed) in [
+            (&b"text\n"[..], &b"text"[..]),
+            (b"text", b"text"),
+            (b"text\r\n\n", b"text"),
+            (b"\n\n", b""),
+            (b"", b""),
+        ] {
+            let mut header = Header::default();
+            let normalized = TrimTrailingNewline.pre_transform(data.to_vec(), &mut header);
+            assert_eq!(normalized, trimmed);
+            assert_eq!(header.trailing_eol.as_deref(), Some(&data[trimmed.len()..]));
+            assert_eq!(TrimTrailingNewline.post_transform(normalized, &header).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn long_newline_runs_are_untouched() {
+        let data = [&b"text"[..], &[b'\n'; 256]].concat();
+        let mut header = Header::default();
+        assert_eq!(TrimTrailingNewline.pre_transform(data.clone(), &mut header), data);
+        assert_eq!(header.trailing_eol, None);
+    }
+
+    #[test]
+    fn trims_after_eol_normalization() {
+        let data = b"line 1\r\nline 2\r\n";
+        let transforms = registered();
+        let mut header = Header::default();
+        let normalized = pre(&transforms[..2], data.to_vec(), &mut header);
+        assert_eq!(normalized, b"line 1\nline 2");
+        // Decryption consults every transform, the ones not applied leave the data alone
+        assert_eq!(post(&transforms, normalized, &header).unwrap(), data);
+    }
+
+    #[test]
+    fn padding_is_added_and_stripped() {
+        for (len, padded_len) in [(0, 16), (1, 16), (15, 16), (16, 32), (17, 32), (32, 48)] {
+            let data: Vec<u8> = (0..len as u8).collect();
+            let mut header = Header::default();
+            let padded = PadTo(16).pre_transform(data.clone(), &mut header);
+            assert_eq!(padded.len(), padded_len, "{} bytes", len);
+            assert_eq!(header.pad_to, Some(16));
+            assert!(padded[len..].iter().all(|&b| b as usize == padded_len - len));
+            assert_eq!(PadTo(1).post_transform(padded, &header).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn corrupt_padding_is_an_error() {
+        let header = Header { pad_to: Some(4), ..Default::default() };
+        for padded in [&b"abc\x02"[..], b"ab\x03\x03", b"abc\x00", b"abc\x05", b"abcd\x01", b""] {
+            assert!(PadTo(4).post_transform(padded.to_vec(), &header).is_err(), "{:?}", padded);
+        }
+        assert_eq!(PadTo(4).post_transform(b"ab\x02\x02".to_vec(), &header).unwrap(), b"ab");
+    }
+}
@@ -0,0 +1,173 @@
+use rc4::Rc4;
+use std::fs;
+use std::io;
+
+/// RFC 6229 keystream vectors, built into the binary for `--verify-vectors-on-start`
+pub const RFC6229: &str = include_str!("../tests/data/rfc6229.csv");
+
+/// One `key,offset,expected-hex` line of a test vector file
+#[derive(Debug, PartialEq, Eq)]
+pub struct Vector {
+    pub line: usize,
+    pub key: Vec<u8>,
+    pub offset: usize,
+    pub expected: Vec<u8>,
+}
+
+/// Parse a test vector file.
+///
+/// Each non-empty line that doesn't start with `#` holds `key,offset,expected`, where `key` and
+/// `expected` are hex strings (an optional `0x` prefix and inner whitespace are ignored) and
+/// `offset` is the decimal keystream offset `expected` starts at.
+pub fn parse_vectors(text: &str) -> io::Result<Vec<Vector>> {
+    let mut vectors = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = idx + 1;
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = trimmed.split(',').map(str::trim).collect();
+        if fields.len() != 3 {
+            let msg = format!("expected 3 comma-separated fields (key,offset,expected-hex), found {}", fields.len());
+            return Err(invalid(line, &msg));
+        }
+
+        let key = decode_hex(fields[0]).map_err(|e| invalid(line, &format!("key: {}", e)))?;
+        let offset = fields[1]
+            .parse::<usize>()
+            .map_err(|_| invalid(line, &format!("offset: '{}' is not a non-negative integer", fields[1])))?;
+        let expected = decode_hex(fields[2]).map_err(|e| invalid(line, &format!("expected: {}", e)))?;
+        if expected.is_empty() {
+            return Err(invalid(line, "expected: no bytes given"));
+        }
+        // The keystream position past the last expected byte has to be one there is
+        if offset.checked_add(expected.len()).is_none() {
+            return Err(invalid(line, &format!("offset: {} is past the end of the keystream", offset)));
+        }
+
+        vectors.push(Vector { line, key, offset, expected });
+    }
+
+    Ok(vectors)
+}
+
+/// Check a vector against this crate's keystream, `Err` if the key is rejected
+pub fn check(vector: &Vector) -> io::Result<bool> {
+    let mut rc4 = Rc4::new(&vector.key).map_err(|e| invalid(vector.line, &e.to_string()))?;
+    // Only the expected bytes are kept, however far into the keystream they start
+    rc4.skip(vector.offset);
+    let mut keystream = vec![0; vector.expected.len()];
+    rc4.fill_keystream(&mut keystream);
+
+    Ok(keystream == vector.expected)
+}
+
+/// Validate every vector in `path`, printing a PASS/FAIL line per vector.
+/// Fails if the file is malformed or any vector doesn't match.
+pub fn self_check_from(path: &str) -> io::Result<()> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    let vectors = parse_vectors(&text)?;
+
+    let mut failed = 0;
+    for vector in &vectors {
+        let status = if check(vector)? {
+            "PASS"
+        } else {
+            failed += 1;
+            "FAIL"
+        };
+        println!(
+            "{} line {}: key={} offset={}",
+            status,
+            vector.line,
+            to_hex(&vector.key),
+            vector.offset
+        );
+    }
+
+    println!("{} of {} vectors passed", vectors.len() - failed, vectors.len());
+    if failed > 0 {
+        return Err(io::Error::other(format!("{} test vector(s) failed", failed)));
+    }
+
+    Ok(())
+}
+
+/// Check the built-in RFC 6229 vectors, without printing each one.
+/// Returns how many passed, or an error naming the first that didn't.
+pub fn verify_builtin() -> io::Result<usize> {
+    let vectors = parse_vectors(RFC6229)?;
+    for vector in &vectors {
+        if !check(vector)? {
+            return Err(io::Error::other(format!(
+                "cipher self-test failed on RFC 6229 vector key={} offset={}: this build can't be trusted to encrypt (or later decrypt) anything",
+                to_hex(&vector.key),
+                vector.offset
+            )));
+        }
+    }
+    Ok(vectors.len())
+}
+
+// Hex as `rc4::decode_hex` reads it, pairs of digits only: unlike `--key` tokens, a lone digit is an error
+fn decode_hex(s: &str) -> Result<Vec<u8>, rc4::Rc4Error> {
+    let mut bytes = vec![0; s.len() / 2];
+    let len = rc4::decode_hex(s, &mut bytes)?;
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let text = "# RFC 6229\n\n0x0102030405, 0, b2396305f03dc027ccc3524a0a1118a8\n";
+        let vectors = parse_vectors(text).unwrap();
+
+        assert_eq!(vectors.len(), 1);
+        assert_eq!(vectors[0].line, 3);
+        assert_eq!(vectors[0].key, [0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(vectors[0].offset, 0);
+        assert!(check(&vectors[0]).unwrap());
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        for (text, msg) in [
+            ("0102030405,0", "line 1: expected 3"),
+            ("0102030405,-1,b2", "line 1: offset"),
+            ("\n01020304zz,0,b2", "line 2: key"),
+            ("0102030405,0,b23", "line 1: expected: odd number of hex digits"),
+            ("0102030405,18446744073709551615,b2", "line 1: offset: 18446744073709551615 is past the end"),
+        ] {
+            let err = parse_vectors(text).unwrap_err();
+            assert!(err.to_string().starts_with(msg), "{}", err);
+        }
+    }
+
+    #[test]
+    fn mismatch_fails() {
+        let vectors = parse_vectors("0102030405,0,b3").unwrap();
+        assert!(!check(&vectors[0]).unwrap());
+    }
+
+    #[test]
+    fn builtin_vectors_pass() {
+        assert_eq!(verify_builtin().unwrap(), 54);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::wipe;
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// `--verify-results`: how a file's result checked out after writing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    /// SHA-256 of the file as read back from disk
+    pub checksum: [u8; 32],
+    /// It matches what was meant to be written
+    pub passed: bool,
+}
+
+/// Read `path` back and compare it against `expected`, the contents just written to it, catching
+/// a write the filesystem or disk silently got wrong
+pub fn check_written(path: &Path, expected: &[u8]) -> io::Result<Verification> {
+    check_written_digest(path, Sha256::digest(expected).into())
+}
+
+/// `check_written` for contents known only by their SHA-256 (streamed out, never all in memory).
+/// The file is hashed as it's read back, so it isn't held in memory either
+pub fn check_written_digest(path: &Path, expected: [u8; 32]) -> io::Result<Verification> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let result = loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) => hasher.update(&buffer[..n]),
+            Err(e) => break Err(e),
+        }
+    };
+
+    // It may be decrypted plaintext
+    wipe::wipe_slice(&mut buffer);
+    result?;
+    let checksum: [u8; 32] = hasher.finalize().into();
+    Ok(Verification { checksum, passed: checksum == expected })
+}
+
+/// Write the results of a run to `path`, one line per file:
+///
+/// ```text
+/// PASS  <sha256 of the result>  <path of the result>
+/// ```
+///
+/// with `FAIL` for a result that didn't read back as written, and `SKIP` (and no checksum) for a
+/// file that wasn't processed (locked or empty). Returns the number of passed and failed files.
+pub fn write_results(path: &Path, results: &[(PathBuf, Option<Verification>)]) -> io::Result<(usize, usize)> {
+    let (mut passed, mut failed) = (0, 0);
+    let mut out = String::new();
+    for (file, verification) in results {
+        match verification {
+            Some(verification) => {
+                let status = if verification.passed { "PASS" } else { "FAIL" };
+                let checksum = verification.checksum.iter().fold(String::new(), |mut hex, b| {
+                    let _ = write!(hex, "{:02x}", b);
+                    hex
+                });
+                let _ = writeln!(out, "{}  {}  {}", status, checksum, file.display());
+                if verification.passed {
+                    passed += 1;
+                } else {
+                    failed += 1;
+                }
+            }
+            None => {
+                let _ = writeln!(out, "SKIP  {:64}  {}", "-", file.display());
+            }
+        }
+    }
+
+    let mut results_file = fs::File::create(path)?;
+    results_file.write_all(out.as_bytes())?;
+    results_file.sync_all()?;
+    Ok((passed, failed))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_back_must_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"ciphertext").unwrap();
+
+        let good = check_written(&path, b"ciphertext").unwrap();
+        assert!(good.passed);
+        assert_eq!(good.checksum, <[u8; 32]>::from(Sha256::digest(b"ciphertext")));
+        assert!(!check_written(&path, b"ciphertexu").unwrap().passed);
+    }
+
+    #[test]
+    fn results_list_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let results_path = dir.path().join("results.txt");
+        let pass = Verification { checksum: [0xab; 32], passed: true };
+        let fail = Verification { passed: false, ..pass };
+        let results = [
+            (PathBuf::from("a.txt"), Some(pass)),
+            (PathBuf::from("b.txt"), Some(fail)),
+            (PathBuf::from("c.txt"), None),
+        ];
+
+        assert_eq!(write_results(&results_path, &results).unwrap(), (1, 1));
+        let lines: Vec<String> = fs::read_to_string(&results_path).unwrap().lines().map(String::from).collect();
+        assert_eq!(lines[0], format!("PASS  {}  a.txt", "ab".repeat(32)));
+        assert_eq!(lines[1], format!("FAIL  {}  b.txt", "ab".repeat(32)));
+        assert!(lines[2].starts_with("SKIP  -") && lines[2].ends_with("  c.txt"));
+    }
+}
@@ -0,0 +1,86 @@
+use std::ops::{Deref, DerefMut};
+
+/// A file's contents on their way through `rcli`, overwritten with zeroes when dropped (with the
+/// `zeroize` feature), so freed memory doesn't keep a copy of the plaintext for a core dump or a
+/// memory scrape to find.
+///
+/// The guarantee only covers memory this process frees: it can't reach copies the OS made, e.g.
+/// pages swapped out to disk, or data still in the page cache of the files read and written.
+#[derive(Default)]
+pub struct Wiped(Vec<u8>);
+
+impl Wiped {
+    /// Empty, but with room for `capacity` bytes, so filling it up to there never reallocates
+    /// (which would free a copy of the data so far without wiping it)
+    pub fn with_capacity(capacity: usize) -> Self {
+        Wiped(Vec::with_capacity(capacity))
+    }
+
+    /// Take the contents out, e.g. for a function that takes a `Vec`. The caller is responsible
+    /// for them from then on, wrap the result back with `Wiped::from`.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+impl From<Vec<u8>> for Wiped {
+    fn from(buf: Vec<u8>) -> Self {
+        Wiped(buf)
+    }
+}
+
+impl Deref for Wiped {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for Wiped {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl Drop for Wiped {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// Zero `buf` in full, capacity included, with the `zeroize` feature; a no-op without it
+pub fn wipe(buf: &mut Vec<u8>) {
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(buf);
+    #[cfg(not(feature = "zeroize"))]
+    let _ = buf;
+}
+
+/// Zero `buf` with the `zeroize` feature; a no-op without it
+pub fn wipe_slice(buf: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(buf);
+    #[cfg(not(feature = "zeroize"))]
+    let _ = buf;
+}
+
+
+#[cfg(all(test, feature = "zeroize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wipe_zeroes_slices() {
+        let mut buf = *b"This is a secret";
+        wipe_slice(&mut buf);
+        assert_eq!(buf, [0; 16]);
+    }
+
+    #[test]
+    fn wipe_clears_vecs() {
+        let mut buf = b"This is a secret".to_vec();
+        wipe(&mut buf);
+        assert!(buf.is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use predicates::prelude::*;
 use std::fs;
 
 
@@ -15,9 +16,9 @@ fn test_encrypt_and_decrypt() {
     // Run the encryption command
     Command::cargo_bin("rcli")
         .unwrap()
-        .args(&["--file", file_path.to_str().unwrap()])
+        .args(["--file", file_path.to_str().unwrap()])
         .arg("--key")
-        .args(&key)
+        .args(key)
         .assert()
         .success()
         .stdout(predicates::str::contains("Encrypted"));
@@ -29,9 +30,9 @@ fn test_encrypt_and_decrypt() {
     // Run the decryption command
     Command::cargo_bin("rcli")
         .unwrap()
-        .args(&["--file", file_path.to_str().unwrap()])
+        .args(["--file", file_path.to_str().unwrap()])
         .arg("--key")
-        .args(&key)
+        .args(key)
         .assert()
         .success()
         .stdout(predicates::str::contains("Decrypted"));
@@ -55,7 +56,7 @@ fn test_invalid_key() {
     // Run the command with an invalid key
     Command::cargo_bin("rcli")
         .unwrap()
-        .args(&["--file", file_path.to_str().unwrap(), "--key", invalid_key])
+        .args(["--file", file_path.to_str().unwrap(), "--key", invalid_key])
         .assert()
         .failure();
 }
@@ -78,10 +79,10 @@ fn test_recursive_encryption() {
     // Run the recursive encryption command
     Command::cargo_bin("rcli")
         .unwrap()
-        .args(&["--file", dir.path().to_str().unwrap()])
+        .args(["--file", dir.path().to_str().unwrap()])
         .arg("--key")
-        .args(&key)
-        .arg("--recursive")
+        .args(key)
+        .args(["--recursive", "--yes"])
         .assert()
         .success()
         .stdout(predicates::str::contains("Encrypted"));
@@ -95,10 +96,10 @@ fn test_recursive_encryption() {
     // Run the recursive decryption command
     Command::cargo_bin("rcli")
         .unwrap()
-        .args(&["--file", dir.path().to_str().unwrap()])
+        .args(["--file", dir.path().to_str().unwrap()])
         .arg("--key")
-        .args(&key)
-        .arg("--recursive")
+        .args(key)
+        .args(["--recursive", "--yes"])
         .assert()
         .success()
         .stdout(predicates::str::contains("Decrypted"));
@@ -109,3 +110,2220 @@ fn test_recursive_encryption() {
     assert_eq!(contents1, b"This is file 1");
     assert_eq!(contents2, b"This is file 2");
 }
+
+
+#[test]
+fn test_recursive_nonce_encryption() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path1 = dir.path().join("file1.txt");
+    let file_path2 = dir.path().join("file2.txt");
+
+    // Identical plaintexts would produce identical ciphertexts without a per-file nonce
+    fs::write(&file_path1, "Same contents").unwrap();
+    fs::write(&file_path2, "Same contents").unwrap();
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    // Run the recursive encryption command with nonces
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .args(["--recursive", "--yes", "--nonce"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Encrypted"));
+
+    // Each file carries a header, and no two files share a keystream
+    let encrypted_contents1 = fs::read(&file_path1).unwrap();
+    let encrypted_contents2 = fs::read(&file_path2).unwrap();
+    assert!(encrypted_contents1.starts_with(b"RC4\x01"));
+    assert!(encrypted_contents2.starts_with(b"RC4\x01"));
+    assert_ne!(encrypted_contents1, encrypted_contents2);
+
+    // Decryption picks up the nonce from each header, no flag needed
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .args(["--recursive", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Decrypted"));
+
+    assert_eq!(fs::read(&file_path1).unwrap(), b"Same contents");
+    assert_eq!(fs::read(&file_path2).unwrap(), b"Same contents");
+}
+
+
+#[test]
+fn test_self_check_rfc6229_vectors() {
+    let vectors = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/rfc6229.csv");
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--self-check-vectors-from", vectors])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("FAIL").not())
+        .stdout(predicates::str::contains("54 of 54 vectors passed"));
+}
+
+
+#[test]
+fn test_self_check_vectors_mismatch_and_malformed() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // A wrong expected value is reported and fails the run
+    let mismatch = dir.path().join("mismatch.csv");
+    fs::write(&mismatch, "0102030405,0,b2396305f03dc027ccc3524a0a1118a9\n").unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--self-check-vectors-from", mismatch.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("FAIL line 1"));
+
+    // A malformed file names the offending line
+    let malformed = dir.path().join("malformed.csv");
+    fs::write(&malformed, "# comment\n0102030405,zero,b2\n").unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--self-check-vectors-from", malformed.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("line 2: offset"));
+}
+
+
+#[test]
+fn test_layered_keys_decrypt_in_reverse_order_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    let run = |key: &str, layer: &str| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", file_path.to_str().unwrap()])
+            .arg("--key")
+            .args(key.split(' '))
+            .args(["--key-layer", layer])
+            .assert()
+            .success();
+    };
+    let key_a = "0x4b 0x8e 0x29 0x87 0x80";
+    let key_b = "0x01 0x02 0x03 0x04 0x05";
+
+    // Encrypt under A, then B
+    run(key_a, key_b);
+    let encrypted_contents = fs::read(&file_path).unwrap();
+    assert!(encrypted_contents.starts_with(b"RC4\x01"));
+
+    // Swapping the keys doesn't recover the plaintext
+    run(key_b, key_a);
+    assert_ne!(fs::read(&file_path).unwrap(), b"This is a secret");
+
+    // The original key order peels B off first, then A
+    fs::write(&file_path, &encrypted_contents).unwrap();
+    run(key_a, key_b);
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_layered_keys_layer_count_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .args(["--key-layer", "0x01 0x02 0x03 0x04 0x05"])
+        .assert()
+        .success();
+
+    // Decrypting with only one of the two layers is refused rather than producing garbage
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("2 key layer(s), 1 given"));
+}
+
+
+// Encrypt `plaintext` in a fresh file with the given key arguments and return the ciphertext
+fn encrypt_with(dir: &std::path::Path, name: &str, plaintext: &[u8], key_args: &[&str]) -> Vec<u8> {
+    let file_path = dir.join(name);
+    fs::write(&file_path, plaintext).unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .args(key_args)
+        .assert()
+        .success();
+
+    fs::read(&file_path).unwrap()
+}
+
+
+#[test]
+fn test_key_file_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    let plaintext = b"This is a secret";
+    let expected = encrypt_with(dir.path(), "expected.txt", plaintext, &["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"]);
+
+    // Hex text key file
+    let hex_key = dir.path().join("hex.key");
+    fs::write(&hex_key, "0x4b 0x8e 0x29 0x87 0x80\n").unwrap();
+    let ciphertext = encrypt_with(dir.path(), "hex.txt", plaintext, &["--key-file", hex_key.to_str().unwrap()]);
+    assert_eq!(ciphertext, expected);
+
+    // Raw binary key file
+    let raw_key = dir.path().join("raw.key");
+    fs::write(&raw_key, [0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap();
+    let ciphertext = encrypt_with(dir.path(), "raw.txt", plaintext, &["--key-file", raw_key.to_str().unwrap()]);
+    assert_eq!(ciphertext, expected);
+}
+
+
+#[test]
+fn test_single_digit_key_tokens_are_whole_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+    let run = |direction: &str, key: &[&str]| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", file_path.to_str().unwrap(), direction, "--key"])
+            .args(key)
+            .assert()
+            .success();
+    };
+
+    // `1` and `0x1` are the byte 0x01, the same key as `01`
+    run("--encrypt", &["1", "2", "3", "4", "5"]);
+    let encrypted = fs::read(&file_path).unwrap();
+    run("--decrypt", &["0x1", "0x2", "0x3", "0x4", "0x5"]);
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+
+    run("--encrypt", &["01", "02", "03", "04", "05"]);
+    assert_eq!(fs::read(&file_path).unwrap(), encrypted);
+    run("--decrypt", &["0x01", "2", "0x3", "04", "5"]);
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_key_file_round_trips_and_replaces_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let plaintext = b"This is a secret";
+
+    // Spread over lines and indented, as a hand-edited key file might be
+    let key_file = dir.path().join("secret.key");
+    fs::write(&key_file, "  4b 8e\n\t29\r\n87 80\n\n").unwrap();
+    let key_args = ["--key-file", key_file.to_str().unwrap()];
+
+    let ciphertext = encrypt_with(dir.path(), "secret.txt", plaintext, &key_args);
+    assert_ne!(ciphertext, plaintext);
+    let file_path = dir.path().join("secret.txt");
+    Command::cargo_bin("rcli").unwrap().args(["--file", file_path.to_str().unwrap()]).args(key_args).assert().success();
+    assert_eq!(fs::read(&file_path).unwrap(), plaintext);
+
+    // Exactly one of `--key` and `--key-file`
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--key", "0x4b"])
+        .args(key_args)
+        .assert()
+        .failure()
+        .code(2);
+    Command::cargo_bin("rcli").unwrap().args(["--file", file_path.to_str().unwrap()]).assert().failure().code(2);
+    assert_eq!(fs::read(&file_path).unwrap(), plaintext);
+}
+
+
+#[test]
+fn test_key_base64() {
+    let dir = tempfile::tempdir().unwrap();
+    let plaintext = b"This is a secret";
+    let expected = encrypt_with(dir.path(), "expected.txt", plaintext, &["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"]);
+
+    // The same five bytes
+    let ciphertext = encrypt_with(dir.path(), "base64.txt", plaintext, &["--key-base64", "S44ph4A="]);
+    assert_eq!(ciphertext, expected);
+    let decrypted = encrypt_with(dir.path(), "base64.txt", &ciphertext, &["--key-base64", "S44ph4A="]);
+    assert_eq!(decrypted, plaintext);
+
+    let file_path = dir.path().join("base64.txt");
+    for (key, message) in [("S44ph4A", "is not valid base64"), ("S44p!4A=", "is not valid base64"), ("S44p", "keys must be 5 to 256 bytes long")] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", file_path.to_str().unwrap(), "--key-base64", key])
+            .assert()
+            .failure()
+            .code(1)
+            .stderr(predicate::str::contains(message));
+    }
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--key-base64", "S44ph4A=", "--key", "0x4b"])
+        .assert()
+        .failure()
+        .code(2);
+    assert_eq!(fs::read(&file_path).unwrap(), plaintext);
+}
+
+
+#[test]
+fn test_key_file_ambiguous_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let plaintext = b"This is a secret";
+
+    // Ten bytes that are all hex digits: a 5-byte hex key, or a 10-byte raw key
+    let key_file = dir.path().join("ambiguous.key");
+    fs::write(&key_file, "4b8e298780").unwrap();
+
+    let file_path = dir.path().join("auto.txt");
+    fs::write(&file_path, plaintext).unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .args(["--key-file", key_file.to_str().unwrap()])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("could be hex text or raw bytes"));
+
+    // Auto-detection reads it as hex...
+    let as_hex = encrypt_with(dir.path(), "hex.txt", plaintext, &["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"]);
+    assert_eq!(fs::read(&file_path).unwrap(), as_hex);
+
+    // ...and the override reads its bytes as-is
+    let as_raw = encrypt_with(dir.path(), "raw.txt", plaintext, &["--key", "34", "62", "38", "65", "32", "39", "38", "37", "38", "30"]);
+    let forced = encrypt_with(dir.path(), "forced.txt", plaintext, &["--key-file", key_file.to_str().unwrap(), "--key-format", "raw"]);
+    assert_eq!(forced, as_raw);
+    assert_ne!(forced, as_hex);
+}
+
+
+#[test]
+fn test_normalize_eol_restores_crlf() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("crlf.txt");
+    let original = b"line 1\r\nline 2\r\n\r\nline 4\r\n";
+    fs::write(&file_path, original).unwrap();
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .arg("--normalize-eol")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Encrypted"));
+
+    // An 18-byte header (magic, length, CRLF flag, payload length) followed by the LF-only text, one byte shorter per line
+    let encrypted_contents = fs::read(&file_path).unwrap();
+    assert!(encrypted_contents.starts_with(b"RC4\x01"));
+    assert_eq!(encrypted_contents.len(), 18 + original.len() - 4);
+
+    // The header restores the original line endings on decryption
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Decrypted"));
+
+    assert_eq!(fs::read(&file_path).unwrap(), original);
+}
+
+
+#[test]
+fn test_rewrite_through_temp_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let run = || {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", file_path.to_str().unwrap()])
+            .arg("--key")
+            .args(key)
+            .assert()
+            .success();
+
+        // The temp file was renamed over the original, nothing is left behind
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(entries, ["secret.txt"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&file_path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+    };
+
+    run();
+    assert_ne!(fs::read(&file_path).unwrap(), b"This is a secret");
+    run();
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+#[test]
+fn test_mode_and_mtime_are_preserved() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    let output_path = dir.path().join("out/secret.txt.rc4");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    fs::File::options().write(true).open(&file_path).unwrap().set_modified(mtime).unwrap();
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    for args in [vec!["--encrypt"], vec!["--decrypt", "--output", output_path.to_str().unwrap()]] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", file_path.to_str().unwrap()])
+            .args(args)
+            .arg("--key")
+            .args(key)
+            .assert()
+            .success();
+    }
+    assert_ne!(fs::read(&file_path).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(&output_path).unwrap(), b"This is a secret");
+
+    for path in [&file_path, &output_path] {
+        let metadata = fs::metadata(path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), mtime, "{}", path.display());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640, "{}", path.display());
+        }
+    }
+}
+
+
+#[test]
+fn test_read_only_file_is_not_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    let mut permissions = fs::metadata(&file_path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&file_path, permissions).unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(["0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("read-only"));
+
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_read_only_source_decrypts_to_output_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let encrypted = encrypt_with(dir.path(), "secret.txt", b"This is a secret", &[&["--nonce"][..], &key].concat());
+    let file_path = dir.path().join("secret.txt");
+
+    let mut permissions = fs::metadata(&file_path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&file_path, permissions).unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--output-dir", out_dir.path().to_str().unwrap()])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Decrypted"));
+
+    // The source is untouched, the result is writable
+    assert_eq!(fs::read(&file_path).unwrap(), encrypted);
+    assert!(fs::metadata(&file_path).unwrap().permissions().readonly());
+    let output_path = out_dir.path().join("secret.txt");
+    assert_eq!(fs::read(&output_path).unwrap(), b"This is a secret");
+    assert!(!fs::metadata(&output_path).unwrap().permissions().readonly());
+}
+
+
+#[test]
+fn test_report_bias() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv_path = dir.path().join("bias.csv");
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .arg("report-bias")
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87"])
+        .args(["--samples", "5000", "--position", "1"])
+        .args(["--csv", csv_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("offset 1 over 5000 samples"))
+        .stdout(predicates::str::contains("Chi-square"));
+
+    // One row per byte value, and the counts add up to the number of samples
+    let csv = fs::read_to_string(&csv_path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("byte,count"));
+
+    let counts: Vec<u64> = lines
+        .map(|line| line.split(',').nth(1).unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(counts.len(), 256);
+    assert_eq!(counts.iter().sum::<u64>(), 5000);
+}
+
+
+#[test]
+fn test_locked_file_is_skipped() {
+    use fs2::FileExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    // Hold the lock as if another rcli run were working on the file
+    let lock = fs::File::open(&file_path).unwrap();
+    lock.lock_exclusive().unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(["0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .arg("--skip-locked")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Skipped"));
+
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_locked_file_is_waited_for() {
+    use fs2::FileExt;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    let lock = fs::File::open(&file_path).unwrap();
+    lock.lock_exclusive().unwrap();
+
+    // Without --skip-locked the run blocks until the lock is released
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rcli"))
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(["0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(child.try_wait().unwrap().is_none());
+    assert_eq!(fs::read(&file_path).unwrap(), b"This is a secret");
+
+    lock.unlock().unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_ne!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_profile_expands_to_flags() {
+    let dir = tempfile::tempdir().unwrap();
+    let profiles = dir.path().join("profiles.conf");
+    fs::write(&profiles, "# Safety first\n[safe]\n--backup\n--key 0x01 0x02 0x03 0x04 0x05\n").unwrap();
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let direct = dir.path().join("direct.txt");
+    let profiled = dir.path().join("profiled.txt");
+    fs::write(&direct, "This is a secret").unwrap();
+    fs::write(&profiled, "This is a secret").unwrap();
+
+    // Passing the flags directly...
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", direct.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .arg("--backup")
+        .assert()
+        .success();
+
+    // ...is equivalent to the profile, whose key is overridden by the explicit one
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("RCLI_PROFILES", &profiles)
+        .args(["--profile", "safe"])
+        .args(["--file", profiled.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.path().join("profiled.txt.bak")).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(dir.path().join("direct.txt.bak")).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(&profiled).unwrap(), fs::read(&direct).unwrap());
+}
+
+
+#[test]
+fn test_unknown_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let profiles = dir.path().join("profiles.conf");
+    fs::write(&profiles, "[safe]\n--backup\n").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("RCLI_PROFILES", &profiles)
+        .args(["--profile", "fast", "--file", "x"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("no profile 'fast'"));
+}
+
+
+#[test]
+fn test_encrypt_high_entropy_plaintext() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("random.bin");
+
+    // Already-compressed or encrypted data looks just like ciphertext
+    let mut plaintext = vec![0u8; 64 * 1024];
+    getrandom::getrandom(&mut plaintext).unwrap();
+    fs::write(&file_path, &plaintext).unwrap();
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    // Explicit mode doesn't depend on what the contents look like
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .arg("--encrypt")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Encrypted"));
+
+    // Marked with a header, so the next run can't take it for plaintext
+    let encrypted_contents = fs::read(&file_path).unwrap();
+    let mut expected = plaintext.clone();
+    rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut expected).unwrap();
+    assert!(encrypted_contents.starts_with(b"RC4\x01") && encrypted_contents.ends_with(&expected));
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap()])
+        .arg("--key")
+        .args(key)
+        .arg("--decrypt")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Decrypted"));
+
+    assert_eq!(fs::read(&file_path).unwrap(), plaintext);
+}
+
+
+#[test]
+fn test_encrypted_files_carry_a_header_unless_no_header() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "This is a secret").unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let mut ciphertext = b"This is a secret".to_vec();
+    rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut ciphertext).unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::cargo_bin("rcli").unwrap().args(["--file", path.to_str().unwrap()]).args(extra).args(key).assert().success()
+    };
+
+    // Marked as encrypted, so the next run decrypts it and strips the header, even judged by printable text alone
+    run(&[]).stdout(predicate::str::contains("Encrypted"));
+    let encrypted = fs::read(&path).unwrap();
+    assert!(encrypted.starts_with(b"RC4\x01") && encrypted.ends_with(&ciphertext));
+    run(&["--guess", "printable"]).stdout(predicate::str::contains("Decrypted"));
+    assert_eq!(fs::read(&path).unwrap(), b"This is a secret");
+
+    // Plain RC4 for other tools, decrypted again by guessing
+    run(&["--no-header"]);
+    assert_eq!(fs::read(&path).unwrap(), ciphertext);
+    run(&[]).stdout(predicate::str::contains("Decrypted"));
+    assert_eq!(fs::read(&path).unwrap(), b"This is a secret");
+
+    // Options recorded in the header can't go without one
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap(), "--no-header", "--nonce"])
+        .args(key)
+        .assert()
+        .code(2);
+}
+
+
+#[test]
+fn test_encrypt_and_decrypt_flags_conflict() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("image.png");
+    fs::write(&file_path, b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--encrypt", "--decrypt"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("cannot be used with"));
+    assert_eq!(fs::read(&file_path).unwrap(), b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR");
+}
+
+
+#[test]
+fn test_encrypt_and_decrypt_subcommands() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let run = |subcommand: &str, extra: &[&str]| {
+        Command::cargo_bin("rcli").unwrap().arg(subcommand).args(["--file", file_path.to_str().unwrap()]).args(key).args(extra).assert()
+    };
+
+    // Encrypting twice over is what was asked for, not a guess to second-guess
+    run("encrypt", &[]).success().stdout(predicate::str::contains("Encrypted"));
+    let once = fs::read(&file_path).unwrap();
+    run("encrypt", &[]).success().stdout(predicate::str::contains("Encrypted"));
+    assert_ne!(fs::read(&file_path).unwrap(), once);
+    run("decrypt", &[]).success().stdout(predicate::str::contains("Decrypted"));
+    assert_eq!(fs::read(&file_path).unwrap(), once);
+    run("decrypt", &[]).success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "This is a secret");
+
+    // The subcommand sets the direction, so the flags that set one too are refused
+    for flag in ["--decrypt", "--encrypt"] {
+        run("encrypt", &[flag]).failure().code(2).stderr(predicate::str::contains(format!("{} can't be used with `rcli encrypt`", flag)));
+    }
+    run("decrypt", &["--archive", "out.rc4a"]).failure().code(2);
+
+    // A profile's flags go after the subcommand
+    let profiles = dir.path().join("profiles.conf");
+    fs::write(&profiles, "[safe]\n--backup\n").unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("RCLI_PROFILES", &profiles)
+        .args(["encrypt", "--profile", "safe", "--file", file_path.to_str().unwrap()])
+        .args(key)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(dir.path().join("secret.txt.bak")).unwrap(), "This is a secret");
+}
+
+
+#[test]
+fn test_selftest_subcommand() {
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .arg("selftest")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("RC4 self-test passed"));
+}
+
+
+#[test]
+fn test_jobs_round_trip_many_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<_> = (0..200)
+        .map(|i| {
+            let path = dir.path().join(format!("dir{}", i % 7)).join(format!("file{}.txt", i));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, format!("Secret number {}\n", i).repeat(i % 5 + 1)).unwrap();
+            path
+        })
+        .collect();
+    let run = |subcommand: &str| {
+        let output = Command::cargo_bin("rcli")
+            .unwrap()
+            .args([subcommand, "--file", dir.path().to_str().unwrap(), "--recursive", "--yes", "--jobs", "4", "--nonce"])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .assert()
+            .success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap()
+    };
+
+    // One whole line per file, however the workers' output came in
+    let stdout = run("encrypt");
+    let reported: Vec<_> = stdout.lines().filter(|line| line.starts_with("Encrypted ")).collect();
+    assert_eq!(reported.len(), files.len());
+    for path in &files {
+        assert!(reported.iter().any(|line| line.starts_with(&format!("Encrypted {} (", path.display()))), "{}", path.display());
+        assert!(fs::read(path).unwrap().starts_with(b"RC4\x01"));
+    }
+    assert!(stdout.contains("Processed 200 file(s), 0 failed"));
+
+    run("decrypt");
+    for (i, path) in files.iter().enumerate() {
+        assert_eq!(fs::read_to_string(path).unwrap(), format!("Secret number {}\n", i).repeat(i % 5 + 1));
+    }
+}
+
+
+#[test]
+fn test_summary_counts_every_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<_> = (0..5)
+        .map(|i| {
+            let path = dir.path().join(format!("dir{}", i % 2)).join(format!("file{}.txt", i));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "Secret ".repeat(i * 100)).unwrap();
+            path
+        })
+        .collect();
+    let run = |args: &[&str]| {
+        let output = Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--yes"])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .args(args)
+            .assert()
+            .success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap()
+    };
+
+    // file0.txt is empty, so skipped. With --quiet, nothing but the summary
+    assert_eq!(
+        run(&["--encrypt", "--quiet"]),
+        "Processed 5 file(s), 0 failed\n4 encrypted, 0 decrypted, 1 skipped, 6.8 KiB processed\n"
+    );
+    assert!(fs::read(&files[1]).unwrap().starts_with(b"RC4\x01"));
+
+    let stdout = run(&["--decrypt"]);
+    assert_eq!(stdout.lines().filter(|line| line.starts_with("Decrypted ")).count(), 4);
+    assert!(stdout.lines().last().unwrap().starts_with("0 encrypted, 4 decrypted, 1 skipped, "), "{}", stdout);
+}
+
+
+#[test]
+fn test_dry_run_changes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let (plain, encrypted, empty) = (dir.path().join("plain.txt"), dir.path().join("sub/encrypted.txt"), dir.path().join("empty.txt"));
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(&plain, "This is a secret").unwrap();
+    fs::write(&encrypted, "This is a secret").unwrap();
+    fs::write(&empty, "").unwrap();
+    Command::cargo_bin("rcli").unwrap().args(["--file", encrypted.to_str().unwrap()]).args(key).assert().success();
+
+    let snapshot = || -> Vec<_> {
+        let mut entries: Vec<_> = walkdir::WalkDir::new(dir.path())
+            .into_iter()
+            .map(|entry| entry.unwrap().into_path())
+            .map(|path| (fs::read(&path).ok(), fs::metadata(&path).unwrap().modified().unwrap(), path))
+            .collect();
+        entries.sort_by(|a, b| a.2.cmp(&b.2));
+        entries
+    };
+    let before = snapshot();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--dry-run", "--backup"])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Would encrypt {}\n", plain.display())))
+        .stdout(predicate::str::contains(format!("Would decrypt {}\n", encrypted.display())))
+        .stdout(predicate::str::contains(format!("Would skip {}: empty\n", empty.display())));
+
+    // Byte for byte, and no temp files or backups left behind either
+    assert_eq!(snapshot(), before);
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", plain.to_str().unwrap(), "--dry-run", "--decrypt", "--output-dir", "out"])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Would decrypt {} to out", plain.display())));
+    assert_eq!(snapshot(), before);
+}
+
+
+#[test]
+fn test_include_and_exclude_globs() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = ["keep.txt", "skip.log", "notes/keep.txt", "notes/skip.log", ".git/config", "old.txt.rc4"];
+    for path in paths {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "This is a secret").unwrap();
+    }
+    let run = |filters: &[&str]| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--yes", "--encrypt"])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .args(filters)
+            .assert()
+            .success();
+    };
+    let modified = || -> Vec<_> {
+        paths.iter().copied().filter(|path| fs::read(dir.path().join(path)).unwrap() != b"This is a secret").collect()
+    };
+
+    run(&["--exclude", "*.log", "--exclude", ".git", "--exclude", "*.rc4"]);
+    assert_eq!(modified(), ["keep.txt", "notes/keep.txt"]);
+
+    // Exclude wins over include, and a pattern with a `/` matches the whole relative path
+    run(&["--include", "*.log", "--include", "*/config", "--exclude", "notes/*"]);
+    assert_eq!(modified(), ["keep.txt", "skip.log", "notes/keep.txt", ".git/config"]);
+}
+
+
+#[test]
+fn test_symlinks_are_skipped_unless_followed() {
+    let dir = tempfile::tempdir().unwrap();
+    let (tree, outside) = (dir.path().join("tree"), dir.path().join("outside"));
+    fs::create_dir_all(&tree).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    let (plain, secret) = (tree.join("plain.txt"), outside.join("secret.txt"));
+    fs::write(&plain, "This is a secret").unwrap();
+    fs::write(&secret, "This is a secret").unwrap();
+    std::os::unix::fs::symlink(&secret, tree.join("link.txt")).unwrap();
+    std::os::unix::fs::symlink(&outside, tree.join("linked_dir")).unwrap();
+    let run = |extra: &[&str]| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", tree.to_str().unwrap(), "--recursive", "--yes"])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .args(extra)
+            .assert()
+            .success()
+    };
+
+    // Both links lead outside the tree, so neither is touched
+    run(&[]).stdout(predicate::str::contains("Skipped 2 symlink(s), see --follow-symlinks"));
+    assert!(fs::read(&plain).unwrap().starts_with(b"RC4\x01"));
+    assert_eq!(fs::read(&secret).unwrap(), b"This is a secret");
+
+    // Followed, the file they both lead to is encrypted once, and the link stays a link
+    let followed = run(&["--follow-symlinks"]);
+    let stdout = String::from_utf8(followed.get_output().stdout.clone()).unwrap();
+    assert_eq!(stdout.matches("Encrypted ").count(), 1, "{}", stdout);
+    assert!(stdout.contains("secret.txt (16 bytes)"), "{}", stdout);
+    assert_eq!(fs::read(&plain).unwrap(), b"This is a secret");
+    let encrypted = fs::read(&secret).unwrap();
+    assert!(encrypted.starts_with(b"RC4\x01") && !encrypted[4..].windows(4).any(|w| w == b"RC4\x01"));
+    assert!(fs::symlink_metadata(tree.join("link.txt")).unwrap().file_type().is_symlink());
+
+    // A link named directly is processed through to its target too
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", tree.join("link.txt").to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(&secret).unwrap(), b"This is a secret");
+    assert!(fs::symlink_metadata(tree.join("link.txt")).unwrap().file_type().is_symlink());
+}
+
+
+#[test]
+fn test_backup_keeps_the_original_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+    let run = |key: &[&str], backup: &[&str]| {
+        Command::cargo_bin("rcli").unwrap().args(["--file", file_path.to_str().unwrap(), "--key"]).args(key).args(backup).assert()
+    };
+
+    run(&["0x4b", "0x8e", "0x29", "0x87", "0x80"], &["--backup"]).success();
+    assert_eq!(fs::read(dir.path().join("secret.txt.bak")).unwrap(), b"This is a secret");
+    let ciphertext = fs::read(&file_path).unwrap();
+
+    // Decrypting with the wrong key garbles the file, but the backup under the given suffix has the ciphertext
+    run(&["0x01", "0x02", "0x03", "0x04", "0x05"], &["--backup", ".orig"]).success();
+    assert_eq!(fs::read(dir.path().join("secret.txt.orig")).unwrap(), ciphertext);
+    assert_ne!(fs::read(&file_path).unwrap(), b"This is a secret");
+
+    // An existing backup is never overwritten, and the file is left alone
+    let garbled = fs::read(&file_path).unwrap();
+    run(&["0x4b", "0x8e", "0x29", "0x87", "0x80"], &["--backup=.orig"]).failure().stderr(predicate::str::contains("can't create backup"));
+    assert_eq!(fs::read(&file_path).unwrap(), garbled);
+    assert_eq!(fs::read(dir.path().join("secret.txt.orig")).unwrap(), ciphertext);
+
+    run(&["0x4b"], &["--backup="]).failure().code(2);
+}
+
+
+#[test]
+fn test_recursive_run_asks_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let (first, second) = (dir.path().join("first.txt"), dir.path().join("sub/second.txt"));
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(&first, "This is a secret").unwrap();
+    fs::write(&second, "This is a secret").unwrap();
+    let run = |extra: &[&str], stdin: &str| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", dir.path().to_str().unwrap(), "--recursive"])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .args(extra)
+            .write_stdin(stdin)
+            .assert()
+    };
+    let prompt = format!("About to modify 2 file(s) under {} in place. Continue? [y/N]", dir.path().display());
+
+    // Anything but yes, no answer included, aborts before any file is touched
+    for answer in ["n\n", "", "sure\n"] {
+        run(&[], answer).failure().stderr(predicate::str::contains(prompt.as_str())).stderr(predicate::str::contains("aborted, no file was changed"));
+        assert_eq!(fs::read(&first).unwrap(), b"This is a secret");
+        assert_eq!(fs::read(&second).unwrap(), b"This is a secret");
+    }
+
+    run(&[], "y\n").success().stdout(predicate::str::contains("Processed 2 file(s), 0 failed"));
+    assert!(fs::read(&first).unwrap().starts_with(b"RC4\x01"));
+
+    // `--yes` doesn't ask at all, for scripts
+    run(&["--yes"], "").success().stderr(predicate::str::contains("Continue?").not());
+    assert_eq!(fs::read(&first).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(&second).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_recursive_depth_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let sub_dir = dir.path().join("subdir");
+    let sub_sub_dir = sub_dir.join("subsubdir");
+    fs::create_dir_all(&sub_sub_dir).unwrap();
+
+    let top = dir.path().join("top.txt");
+    let middle = sub_dir.join("middle.txt");
+    let bottom = sub_sub_dir.join("bottom.txt");
+    for path in [&top, &middle, &bottom] {
+        fs::write(path, "This is a secret").unwrap();
+    }
+
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let run = |depth_args: &[&str]| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", dir.path().to_str().unwrap()])
+            .arg("--key")
+            .args(key)
+            .args(["--recursive", "--yes"])
+            .args(depth_args)
+            .assert()
+            .success()
+    };
+
+    // Only the directory's own files
+    run(&["--max-depth", "1"]);
+    assert_ne!(fs::read(&top).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(&middle).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(&bottom).unwrap(), b"This is a secret");
+
+    // Only the deepest level, the skipped shallower files are reported
+    run(&["--min-depth", "3"]).stdout(predicates::str::contains("Skipped 2 file(s) above --min-depth"));
+    assert_ne!(fs::read(&top).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(&middle).unwrap(), b"This is a secret");
+    assert_ne!(fs::read(&bottom).unwrap(), b"This is a secret");
+
+    // Depth 0 is the directory itself, which holds no files to process: a mistake, not a silent no-op
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--max-depth", "0", "--key"])
+        .args(key)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+
+#[test]
+fn test_append_builds_encrypted_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let log_path = dir.path().join("app.log");
+    let key = ["0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    for entry in ["first entry\n", "second entry\n"] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", log_path.to_str().unwrap(), "--append", "--key"])
+            .args(key)
+            .write_stdin(entry)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Appended"));
+    }
+
+    assert_ne!(fs::read(&log_path).unwrap(), b"first entry\nsecond entry\n");
+    assert_eq!(fs::read_to_string(dir.path().join("app.log.rc4pos")).unwrap().trim(), "25");
+
+    // The log decrypts as a whole
+    let copy_path = dir.path().join("copy.log");
+    fs::copy(&log_path, &copy_path).unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", copy_path.to_str().unwrap(), "--decrypt", "--key"])
+        .args(key)
+        .assert()
+        .success();
+    assert_eq!(fs::read(&copy_path).unwrap(), b"first entry\nsecond entry\n");
+
+    // Truncating the log is detected before any keystream is reused
+    let mut truncated = fs::read(&log_path).unwrap();
+    truncated.pop();
+    fs::write(&log_path, &truncated).unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", log_path.to_str().unwrap(), "--append", "--key"])
+        .args(key)
+        .write_stdin("third entry\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("refusing to append"));
+}
+
+
+#[test]
+fn test_trim_trailing_newline_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let args = ["--trim-trailing-newline", "--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    let with_newline = encrypt_with(dir.path(), "with.txt", b"line 1\nline 2\n", &args);
+    let without_newline = encrypt_with(dir.path(), "without.txt", b"line 1\nline 2", &args);
+
+    // Only the headers differ, the payloads are the same
+    let payload_len = b"line 1\nline 2".len();
+    assert_eq!(with_newline[with_newline.len() - payload_len..], without_newline[without_newline.len() - payload_len..]);
+
+    for (name, original) in [("with.txt", &b"line 1\nline 2\n"[..]), ("without.txt", b"line 1\nline 2")] {
+        let path = dir.path().join(name);
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Decrypted"));
+        assert_eq!(fs::read(&path).unwrap(), original);
+    }
+}
+
+
+#[test]
+fn test_pad_to_restores_exact_contents() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    // A multiple of 16 still gets a full block of padding, so it can be told from the data
+    for (name, original, payload_len) in [("exact.txt", &b"sixteen bytes!!\n"[..], 32), ("short.txt", b"ten bytes\n", 16)] {
+        let args = [&["--pad-to", "16"][..], &key].concat();
+        let encrypted = encrypt_with(dir.path(), name, original, &args);
+        let (header_len, payload) = (encrypted.len() - payload_len, &encrypted[encrypted.len() - payload_len..]);
+        assert!(encrypted.starts_with(b"RC4\x01"));
+        assert_eq!(u16::from_be_bytes([encrypted[4], encrypted[5]]) as usize, header_len - 6);
+        assert_eq!(payload.len() % 16, 0);
+
+        let path = dir.path().join(name);
+        Command::cargo_bin("rcli").unwrap().args(["--file", path.to_str().unwrap()]).args(key).assert().success();
+        assert_eq!(fs::read(&path).unwrap(), original);
+    }
+
+    // Corrupting the last byte of the padding is detected on decryption
+    let encrypted = encrypt_with(dir.path(), "corrupt.txt", b"ten bytes\n", &[&["--pad-to", "16"][..], &key].concat());
+    let mut corrupt = encrypted.clone();
+    *corrupt.last_mut().unwrap() ^= 0x01;
+    let path = dir.path().join("corrupt.txt");
+    fs::write(&path, &corrupt).unwrap();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap()])
+        .args(key)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid padding"));
+    assert_eq!(fs::read(&path).unwrap(), corrupt);
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap(), "--pad-to", "0"])
+        .args(key)
+        .assert()
+        .code(2);
+}
+
+
+#[test]
+fn test_truncated_file_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let plaintext = b"This is a secret, and a rather long one at that";
+    let encrypted = encrypt_with(dir.path(), "file.txt", plaintext, &[&["--nonce"][..], &key].concat());
+    let path = dir.path().join("file.txt");
+
+    // Cut short in the payload, and in the header itself
+    for len in [encrypted.len() - 1, encrypted.len() - plaintext.len(), 10] {
+        fs::write(&path, &encrypted[..len]).unwrap();
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap()])
+            .args(key)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("file appears truncated or corrupt"));
+        assert_eq!(fs::read(&path).unwrap(), &encrypted[..len]);
+    }
+
+    fs::write(&path, &encrypted).unwrap();
+    Command::cargo_bin("rcli").unwrap().args(["--file", path.to_str().unwrap()]).args(key).assert().success();
+    assert_eq!(fs::read(&path).unwrap(), plaintext);
+}
+
+
+#[test]
+fn test_explain_changes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    fs::write(dir.path().join("plain.txt"), "This is a secret").unwrap();
+    let encrypted = encrypt_with(dir.path(), "headed.txt", b"This is a secret", &[&["--nonce"][..], &key].concat());
+
+    let output = Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--explain"])
+        .args(key)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let plain = &stdout[stdout.find("plain.txt:").unwrap()..];
+    let plain = &plain[..plain.find("\n\n").unwrap_or(plain.len())];
+    assert!(plain.contains("action:  encrypt (no rcli header"), "{}", stdout);
+    assert!(plain.contains("header:  none"), "{}", stdout);
+    assert!(plain.contains("key:     --key (5 bytes)"), "{}", stdout);
+
+    let headed = &stdout[stdout.find("headed.txt:").unwrap()..];
+    let headed = &headed[..headed.find("\n\n").unwrap_or(headed.len())];
+    assert!(headed.contains("action:  decrypt (the file starts with an rcli header)"), "{}", stdout);
+    assert!(headed.contains("header:  found (nonce"), "{}", stdout);
+
+    assert_eq!(fs::read(dir.path().join("plain.txt")).unwrap(), b"This is a secret");
+    assert_eq!(fs::read(dir.path().join("headed.txt")).unwrap(), encrypted);
+}
+
+
+// Under a descriptor limit far below the number of files, with backups (the most descriptors per file)
+#[cfg(unix)]
+#[test]
+fn test_many_files_under_low_descriptor_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    for n in 0..300 {
+        let sub = dir.path().join(format!("d{}", n % 7));
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join(format!("f{}.txt", n)), format!("This is secret number {}", n)).unwrap();
+    }
+
+    let rcli = assert_cmd::cargo::cargo_bin("rcli");
+    let script = format!(
+        "ulimit -n 24 && exec {} --file {} --recursive --yes --encrypt --backup --max-open-files 4 --key 0x4b 0x8e 0x29 0x87 0x80",
+        rcli.display(),
+        dir.path().display()
+    );
+    let output = std::process::Command::new("sh").args(["-c", &script]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().matches("Encrypted").count(), 300);
+
+    for n in 0..300 {
+        let path = dir.path().join(format!("d{}/f{}.txt", n % 7, n));
+        assert_ne!(fs::read(&path).unwrap(), format!("This is secret number {}", n).as_bytes());
+        assert!(fs::metadata(format!("{}.bak", path.display())).is_ok());
+    }
+}
+
+
+#[test]
+fn test_nul_and_control_bytes_round_trip_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    // NULs at the start, middle and end, and every other C0 control byte plus DEL, around some text
+    let mut original: Vec<u8> = vec![0x00, 0x00];
+    original.extend_from_slice(b"before NUL");
+    original.push(0x00);
+    original.extend(0x01..0x20u8);
+    original.extend_from_slice(b"form\x0cfeed\x1b[0m\x7f");
+    original.extend_from_slice(&[0x00; 16]);
+    original.extend_from_slice(b"\r\n\x00");
+
+    for (name, extra) in [("plain.bin", &[][..]), ("headed.bin", &["--nonce"][..])] {
+        let path = dir.path().join(name);
+        fs::write(&path, &original).unwrap();
+
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--encrypt"])
+            .args(extra)
+            .args(key)
+            .assert()
+            .success();
+        let encrypted: Vec<u8> = fs::read(&path).unwrap();
+        assert_ne!(encrypted, original);
+        assert!(encrypted.len() >= original.len());
+
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--decrypt"])
+            .args(key)
+            .assert()
+            .success();
+        let decrypted: Vec<u8> = fs::read(&path).unwrap();
+        assert_eq!(decrypted.len(), original.len(), "{}", name);
+        assert_eq!(decrypted, original, "{}", name);
+    }
+}
+
+
+#[test]
+fn test_rekey_every_round_trips_across_boundaries() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let original: Vec<u8> = (0..10_000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+
+    let rekeyed = encrypt_with(dir.path(), "rekeyed.txt", &original, &[&["--rekey-every", "1000"][..], &key].concat());
+    let plain = encrypt_with(dir.path(), "plain.txt", &original, &[&["--encrypt"][..], &key].concat());
+    assert!(rekeyed.starts_with(b"RC4\x01"));
+
+    // Each 1000-byte segment has its own keystream, unlike a single long one
+    let payload = &rekeyed[rekeyed.len() - original.len()..];
+    assert_ne!(payload, plain);
+    assert_ne!(payload[..1000], payload[1000..2000]);
+
+    let path = dir.path().join("rekeyed.txt");
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap()])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Decrypted"));
+    assert_eq!(fs::read(&path).unwrap(), original);
+}
+
+
+#[test]
+fn test_directory_without_recursive_is_a_usage_error() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("file.txt"), "This is a secret").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(format!(
+            "error: '{}' is a directory; use --recursive to process its contents",
+            dir.path().display()
+        )));
+    assert_eq!(fs::read(dir.path().join("file.txt")).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_verify_results_lists_every_file() {
+    use sha2::{Digest, Sha256};
+
+    let dir = tempfile::tempdir().unwrap();
+    let results_dir = tempfile::tempdir().unwrap();
+    let results_path = results_dir.path().join("results.txt");
+    let names = ["a.txt", "sub/b.txt", "sub/deeper/c.txt"];
+    for name in names {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("This is the secret of {}", name)).unwrap();
+    }
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--yes", "--nonce"])
+        .args(["--verify-results", results_path.to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Verified 3 file(s): 3 passed, 0 failed, 0 skipped"));
+
+    let results = fs::read_to_string(&results_path).unwrap();
+    assert_eq!(results.lines().count(), names.len());
+    for name in names {
+        let path = dir.path().join(name);
+        let checksum: String = Sha256::digest(fs::read(&path).unwrap()).iter().map(|b| format!("{:02x}", b)).collect();
+        let line = format!("PASS  {}  {}", checksum, path.display());
+        assert!(results.lines().any(|l| l == line), "no {:?} in {}", line, results);
+    }
+}
+
+
+#[test]
+fn test_empty_file_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("empty.txt");
+    fs::write(&path, b"").unwrap();
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+    for direction in ["--encrypt", "--nonce"] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), direction])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .assert()
+            .success()
+            .stdout(predicate::eq(format!("Skipped {}: empty\n0 encrypted, 0 decrypted, 1 skipped, 0 B processed\n", path.display())));
+    }
+
+    // Not rewritten either
+    assert_eq!(fs::read(&path).unwrap(), b"");
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+}
+
+
+#[test]
+fn test_multi_megabyte_file_round_trips_in_chunks() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.txt");
+    let original: Vec<u8> = (0..5_000_000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+    fs::write(&path, &original).unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    // One continuous keystream across all the chunks
+    Command::cargo_bin("rcli").unwrap().args(["--file", path.to_str().unwrap()]).args(key).assert().success();
+    let mut expected = original.clone();
+    rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut expected).unwrap();
+    let encrypted = fs::read(&path).unwrap();
+    assert!(encrypted.starts_with(b"RC4\x01") && encrypted.ends_with(&expected));
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap()])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Decrypted"));
+    assert!(fs::read(&path).unwrap() == original);
+}
+
+
+#[test]
+fn test_output_writes_elsewhere_and_leaves_the_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("in.txt"), dir.path().join("out/out.bin"));
+    fs::write(&input, "This is a secret").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", input.to_str().unwrap(), "-o", output.to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Encrypted"));
+
+    assert_eq!(fs::read(&input).unwrap(), b"This is a secret");
+    let mut expected = b"This is a secret".to_vec();
+    rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut expected).unwrap();
+    let written = fs::read(&output).unwrap();
+    assert!(written.starts_with(b"RC4\x01") && written.ends_with(&expected));
+
+    // One path can't take a whole tree
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--output", output.to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("use --output-dir with --recursive"));
+    assert_eq!(fs::read(&input).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_dash_file_streams_stdin_to_stdout() {
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let mut ciphertext = b"This is a secret".to_vec();
+    rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut ciphertext).unwrap();
+
+    // Text is encrypted and ciphertext decrypted, with only the data on stdout
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", "-", "--no-header"])
+        .args(key)
+        .write_stdin("This is a secret")
+        .assert()
+        .success()
+        .stdout(predicate::eq(ciphertext.clone()))
+        .stderr(predicates::str::contains("Encrypted stdin (16 bytes)"));
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", "-"])
+        .args(key)
+        .write_stdin(ciphertext)
+        .assert()
+        .success()
+        .stdout(predicate::eq(b"This is a secret".to_vec()))
+        .stderr(predicates::str::contains("Decrypted stdin (16 bytes)"));
+
+    // A header is written and read back just like on a file, across a pipe's worth of chunks
+    let plaintext: Vec<u8> = (0..200_000u32).map(|n| b"This is a secret. "[n as usize % 18]).collect();
+    let output = Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", "-", "--nonce", "--rekey-every", "50000"])
+        .args(key)
+        .write_stdin(plaintext.clone())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"RC4\x01"));
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", "-"])
+        .args(key)
+        .write_stdin(output.stdout)
+        .assert()
+        .success()
+        .stdout(predicate::eq(plaintext));
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", "-", "--recursive"])
+        .args(key)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--recursive can't be used with --file -"));
+}
+
+
+#[test]
+fn test_recursive_run_continues_past_a_failing_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let (good, bad) = (dir.path().join("good.txt"), dir.path().join("bad.txt"));
+    fs::write(&bad, "This is a secret").unwrap();
+    Command::cargo_bin("rcli").unwrap().args(["--file", bad.to_str().unwrap()]).args(key).assert().success();
+
+    // Cut short, so its header's payload length no longer matches and decrypting it fails
+    let truncated = fs::read(&bad).unwrap();
+    fs::write(&bad, &truncated[..truncated.len() - 4]).unwrap();
+    fs::write(&good, "This is a secret").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--yes"])
+        .args(key)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!("Encrypted {}", good.display())))
+        .stdout(predicate::str::contains("Processed 2 file(s), 1 failed"))
+        .stderr(predicate::str::contains(format!("error processing '{}'", bad.display())))
+        .stderr(predicate::str::contains("1 of 2 file(s) failed"));
+    assert!(fs::read(&good).unwrap().starts_with(b"RC4\x01"));
+    assert_eq!(fs::read(&bad).unwrap(), &truncated[..truncated.len() - 4]);
+}
+
+
+#[test]
+fn test_output_dir_mirrors_relative_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src/a")).unwrap();
+    fs::write(dir.path().join("src/top.txt"), "top level").unwrap();
+    fs::write(dir.path().join("src/a/b.txt"), "nested file").unwrap();
+
+    // Relative source root, absolute output directory
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["--file", "./src", "--recursive", "--encrypt", "--output-dir", out_dir.path().to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success();
+
+    // Originals are untouched, results land in the mirrored locations and nowhere else
+    assert_eq!(fs::read(dir.path().join("src/a/b.txt")).unwrap(), b"nested file");
+    let mut expected = b"nested file".to_vec();
+    rc4::Rc4::apply_keystream_static(&[0x4b, 0x8e, 0x29, 0x87, 0x80], &mut expected).unwrap();
+    let written = fs::read(out_dir.path().join("a/b.txt")).unwrap();
+    assert!(written.starts_with(b"RC4\x01") && written.ends_with(&expected));
+    assert!(out_dir.path().join("top.txt").is_file());
+    assert_eq!(fs::read_dir(out_dir.path()).unwrap().count(), 2);
+
+    // A lone file, given with `..`, goes directly in the output directory
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .current_dir(dir.path().join("src/a"))
+        .args(["--file", "../top.txt", "--encrypt", "--output-dir", out_dir.path().join("single").to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success();
+    assert!(out_dir.path().join("single/top.txt").is_file());
+}
+
+
+#[test]
+fn test_verify_vectors_on_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--verify-vectors-on-start"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Verified 54 built-in test vectors").and(predicate::str::contains("Encrypted")));
+    assert_ne!(fs::read(&file_path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_frame_round_trip() {
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    let framed = Command::cargo_bin("rcli")
+        .unwrap()
+        .arg("--frame")
+        .args(key)
+        .write_stdin("first message\nsecond\n")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert_eq!(framed[..4], [0, 0, 0, 13]);
+    assert_eq!(framed.len(), 4 + 13 + 4 + 6);
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--frame", "--decrypt"])
+        .args(key)
+        .write_stdin(framed)
+        .assert()
+        .success()
+        .stdout("first message\nsecond\n");
+}
+
+
+#[test]
+fn test_env_file_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let env_path = dir.path().join(".env");
+    let original = "# Database settings\nDB_USER=admin\nDB_PASS=s3cr3t\n\nAPI_URL=https://example.com/?a=b\n";
+    fs::write(&env_path, original).unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", env_path.to_str().unwrap(), "--env"])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Encrypted"));
+
+    // Names, comments and blank lines are untouched, every value is encrypted
+    let encrypted = fs::read_to_string(&env_path).unwrap();
+    let lines: Vec<&str> = encrypted.lines().collect();
+    assert_eq!(lines[0], "# Database settings");
+    assert_eq!(lines[3], "");
+    for (line, name) in [(1, "DB_USER="), (2, "DB_PASS="), (4, "API_URL=")] {
+        assert!(lines[line].starts_with(&format!("{}rc4:", name)), "{}", lines[line]);
+    }
+    assert!(!encrypted.contains("admin") && !encrypted.contains("s3cr3t") && !encrypted.contains("example.com"));
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", env_path.to_str().unwrap(), "--env"])
+        .args(key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Decrypted"));
+    assert_eq!(fs::read_to_string(&env_path).unwrap(), original);
+}
+
+
+#[test]
+fn test_entropy_window_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("mixed.bin");
+
+    // Half text, half random-looking bytes
+    let mut contents: Vec<u8> = b"Some plain text, repeated to fill the first half. ".iter().copied().cycle().take(4096).collect();
+    let mut random = vec![0u8; 4096];
+    rc4::Rc4::apply_keystream_static(&[0x01, 0x02, 0x03, 0x04, 0x05], &mut random).unwrap();
+    contents.extend(random);
+    fs::write(&file_path, &contents).unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--entropy-window", "512"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("8 of 16 window(s) of 512 bytes look encrypted (50.0%)"));
+
+    // Report only, the file is left alone
+    assert_eq!(fs::read(&file_path).unwrap(), contents);
+}
+
+
+#[test]
+fn test_recursive_guard_on_home() {
+    let home = tempfile::tempdir().unwrap();
+    let projects = home.path().join("projects");
+    fs::create_dir(&projects).unwrap();
+    fs::write(home.path().join("notes.txt"), "This is a secret").unwrap();
+    fs::write(projects.join("plan.txt"), "This is a plan").unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+
+    // The home directory is refused, even spelled through `..`, and nothing is touched
+    for target in [home.path().to_path_buf(), projects.join("..")] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .env("HOME", home.path())
+            .args(["--file", target.to_str().unwrap(), "--recursive"])
+            .args(key)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--i-know-what-im-doing"));
+    }
+    assert_eq!(fs::read(home.path().join("notes.txt")).unwrap(), b"This is a secret");
+
+    // A directory below it is fine
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("HOME", home.path())
+        .args(["--file", projects.to_str().unwrap(), "--recursive", "--yes"])
+        .args(key)
+        .assert()
+        .success();
+    assert_ne!(fs::read(projects.join("plan.txt")).unwrap(), b"This is a plan");
+
+    // And so is home itself, when asked for explicitly
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("HOME", home.path())
+        .args(["--file", home.path().to_str().unwrap(), "--recursive", "--yes", "--max-depth", "1", "--i-know-what-im-doing"])
+        .args(key)
+        .assert()
+        .success();
+    assert_ne!(fs::read(home.path().join("notes.txt")).unwrap(), b"This is a secret");
+}
+
+
+#[cfg(feature = "progress")]
+#[test]
+fn test_progress_without_terminal_is_plain_text() {
+    let dir = tempfile::tempdir().unwrap();
+    for n in 0..20 {
+        fs::write(dir.path().join(format!("file{}.txt", n)), "This is a secret").unwrap();
+    }
+
+    // assert_cmd captures stdout and stderr through pipes, so neither is a terminal
+    let output = Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--yes", "--progress"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Progress: 20 of 20 files"), "{}", stderr);
+    for captured in [&output.stdout[..], stderr.as_bytes()] {
+        assert!(!captured.contains(&0x1b), "escape sequence in {:?}", String::from_utf8_lossy(captured));
+        assert!(!captured.contains(&b'\r'));
+    }
+}
+
+
+#[test]
+fn test_binary_content_never_panics_display() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = ["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"];
+    let binary: Vec<u8> = (0..=255).chain([0xff, 0xfe, 0xc3, 0x28]).collect();
+
+    // A file name that isn't UTF-8 is shown lossily
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path = dir.path().join(std::ffi::OsStr::from_bytes(b"bin\xff\xfe.dat"));
+        fs::write(&path, &binary).unwrap();
+        for direction in ["--encrypt", "--decrypt"] {
+            Command::cargo_bin("rcli")
+                .unwrap()
+                .args(["--file", dir.path().to_str().unwrap(), "--recursive", "--yes", direction])
+                .args(key)
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("bin\u{fffd}\u{fffd}.dat"));
+        }
+        assert_eq!(fs::read(&path).unwrap(), binary);
+    }
+
+    // Binary messages come out of `--frame --decrypt` as raw bytes
+    let mut line = binary.clone();
+    line.retain(|&b| b != b'\n' && b != b'\r');
+    let framed = Command::cargo_bin("rcli").unwrap().arg("--frame").args(key).write_stdin(line.clone()).output().unwrap();
+    assert!(framed.status.success());
+    let decrypted = Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--frame", "--decrypt"])
+        .args(key)
+        .write_stdin(framed.stdout)
+        .output()
+        .unwrap();
+    assert!(decrypted.status.success());
+    assert_eq!(decrypted.stdout, [&line[..], b"\n"].concat());
+
+    // `.env` values that don't decrypt to text (wrong key) are an error, not a crash
+    let env_path = dir.path().join(".env");
+    // Long enough that wrongly decrypted bytes are practically never valid UTF-8 by chance
+    fs::write(&env_path, format!("SECRET={}\n", "This is a secret. ".repeat(4))).unwrap();
+    Command::cargo_bin("rcli").unwrap().args(["--file", env_path.to_str().unwrap(), "--env"]).args(key).assert().success();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", env_path.to_str().unwrap(), "--env", "--key", "0x01", "0x02", "0x03", "0x04", "0x05"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("not valid UTF-8"));
+}
+
+
+#[test]
+fn test_archive_and_extract_nested_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: [(&str, Vec<u8>); 4] = [
+        ("top.txt", b"top level".to_vec()),
+        ("a/b.txt", b"nested file".to_vec()),
+        ("a/deeper/c.bin", (0..200_000u32).map(|n| (n % 253) as u8).collect()),
+        ("a/empty", Vec::new()),
+    ];
+    for (name, contents) in &files {
+        let path = dir.path().join("src").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["--file", "src", "--recursive", "--archive", "src.rc4a"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Archived 4 file(s) (200020 bytes) into src.rc4a"));
+
+    // Archiving leaves the originals alone, and doesn't overwrite an archive
+    assert_eq!(fs::read(dir.path().join("src/a/b.txt")).unwrap(), b"nested file");
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["--file", "src", "--recursive", "--archive", "src.rc4a"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .failure();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["--file", "src.rc4a", "--extract", "out"])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Extracted 4 file(s) into out"));
+
+    // The same tree, and nothing else
+    for (name, contents) in &files {
+        assert_eq!(&fs::read(dir.path().join("out").join(name)).unwrap(), contents, "{}", name);
+    }
+    let extracted = walkdir::WalkDir::new(dir.path().join("out")).into_iter().filter(|e| e.as_ref().unwrap().file_type().is_file()).count();
+    assert_eq!(extracted, files.len());
+}
+
+
+#[test]
+fn test_seed_makes_nonced_output_reproducible() {
+    let dir = tempfile::tempdir().unwrap();
+    let encrypt = |name: &str, seed: &str| {
+        let path = dir.path().join(name);
+        fs::write(&path, "This is a secret").unwrap();
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--nonce", "--seed", seed])
+            .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("for tests only"));
+        fs::read(&path).unwrap()
+    };
+
+    let first = encrypt("a.txt", "42");
+    assert_eq!(encrypt("b.txt", "42"), first);
+    assert_ne!(encrypt("c.txt", "43"), first);
+
+    // Still an ordinary nonced file
+    let path = dir.path().join("a.txt");
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap()])
+        .args(["--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(&path).unwrap(), b"This is a secret");
+}
+
+
+#[test]
+fn test_large_file_streams_in_bounded_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.txt");
+
+    // 24 MiB, past the streaming threshold, under an address space limit of less than that: the
+    // run only succeeds if the file is never held in memory whole
+    let line = b"This is a secret line of a large file that doesn't fit in memory.\n";
+    let original: Vec<u8> = line.iter().copied().cycle().take(24 * 1024 * 1024).collect();
+    fs::write(&path, &original).unwrap();
+
+    let rcli = assert_cmd::cargo::cargo_bin("rcli");
+    let run = |flags: &str| {
+        let script = format!(
+            "ulimit -v 20000 && exec {} --file {} {} --verify-results {} --key 0x4b 0x8e 0x29 0x87 0x80",
+            rcli.display(),
+            path.display(),
+            flags,
+            dir.path().join("results.txt").display()
+        );
+        let output = std::process::Command::new("sh").args(["-c", &script]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert!(run("--nonce").contains("1 passed"));
+    let encrypted = fs::read(&path).unwrap();
+    assert!(encrypted.starts_with(b"RC4\x01") && encrypted[encrypted.len() - original.len()..] != original[..]);
+    drop(encrypted);
+
+    assert!(run("").contains("Decrypted"));
+    assert!(fs::read(&path).unwrap() == original);
+}
+
+
+#[test]
+fn test_keystream_only_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("key.bin");
+    fs::write(&key_file, [0x4b, 0x8e, 0x29, 0x87, 0x80]).unwrap();
+
+    for second in ["base64:S44ph4A=".to_string(), format!("file:{}", key_file.display())] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["keystream-only-verify", "--first", "hex:4b8e298780", "--second", &second])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Keystreams match over the first 1024 bytes"));
+    }
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["keystream-only-verify", "--first", "hex:4b8e298780", "--second", "base64:S44ph4E=", "--bytes", "64"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("keystreams differ"));
+}
+
+
+#[test]
+fn test_gen_key() {
+    let gen_key = |args: &[&str]| {
+        let output = Command::cargo_bin("rcli").unwrap().arg("gen-key").args(args).assert().success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap().trim_end().to_string()
+    };
+
+    // 16 bytes by default, as hex
+    let (first, second) = (gen_key(&[]), gen_key(&[]));
+    assert_eq!(first.len(), 32);
+    assert!(first.bytes().all(|b| b.is_ascii_hexdigit()));
+    assert_ne!(first, second);
+
+    // 41 bytes of base64 are 56 characters, ending in one `=`
+    let base64 = gen_key(&["--length", "41", "--format", "base64"]);
+    assert_eq!(base64.len(), 56);
+    assert!(base64.ends_with('=') && !base64.ends_with("=="));
+
+    // What it prints works as a key
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+    for key_args in [["--key", &first], ["--key-base64", &base64]] {
+        for _ in 0..2 {
+            Command::cargo_bin("rcli").unwrap().args(["--file", file_path.to_str().unwrap()]).args(key_args).assert().success();
+        }
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "This is a secret");
+    }
+
+    for length in ["4", "257"] {
+        Command::cargo_bin("rcli").unwrap().args(["gen-key", "--length", length]).assert().failure().code(2);
+    }
+}
+
+
+#[test]
+fn test_write_error_names_the_file_operation_and_phase() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret.txt");
+    let original = b"This is a secret.\n".repeat(1000);
+    fs::write(&path, &original).unwrap();
+
+    // A file size limit of one block makes writing the temp file fail with EFBIG; SIGXFSZ is
+    // ignored so the write returns an error instead of killing the process
+    let rcli = assert_cmd::cargo::cargo_bin("rcli");
+    let script = format!(
+        "trap '' XFSZ; ulimit -f 1 && exec {} --file {} --key 0x4b 0x8e 0x29 0x87 0x80",
+        rcli.display(),
+        path.display()
+    );
+    let output = std::process::Command::new("sh").args(["-c", &script]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("error processing '{}': write failed:", path.display())), "{}", stderr);
+    assert!(stderr.contains("(during write-back)"), "{}", stderr);
+    assert_eq!(fs::read(&path).unwrap(), original);
+
+    // The half-written temp file is gone too
+    let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names, ["secret.txt"]);
+}
+
+
+#[cfg(feature = "passphrase")]
+#[test]
+fn test_passphrase_round_trips_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("secret.txt");
+    fs::write(&file_path, "This is a secret").unwrap();
+    let run = |passphrase: &str| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", file_path.to_str().unwrap(), "--passphrase", passphrase])
+            .assert()
+            .success();
+        fs::read(&file_path).unwrap()
+    };
+
+    let ciphertext = run("correct horse battery staple");
+    assert_ne!(ciphertext, b"This is a secret");
+
+    // A different passphrase derives a different key, so decrypting with it gives garbage...
+    assert_ne!(run("correct horse battery stapler"), b"This is a secret");
+    // ...while the same one derives the same key again
+    fs::write(&file_path, &ciphertext).unwrap();
+    assert_eq!(run("correct horse battery staple"), b"This is a secret");
+
+    // It replaces the key, it can't be given alongside one
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", file_path.to_str().unwrap(), "--passphrase", "x", "--key", "0x4b"])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+
+#[cfg(feature = "passphrase")]
+#[test]
+fn test_passphrase_without_a_value_is_read_from_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let (typed, given) = (dir.path().join("typed.txt"), dir.path().join("given.txt"));
+    fs::write(&typed, "This is a secret").unwrap();
+    fs::write(&given, "This is a secret").unwrap();
+    let run = |path: &std::path::Path, stdin: &str| {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--passphrase"])
+            .write_stdin(stdin)
+            .assert()
+    };
+
+    // Not a terminal, so the first line is the passphrase, without its line ending
+    run(&typed, "correct horse battery staple\r\nignored\n").success();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", given.to_str().unwrap(), "--passphrase", "correct horse battery staple"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(&typed).unwrap(), fs::read(&given).unwrap());
+
+    run(&typed, "correct horse battery staple\n").success();
+    assert_eq!(fs::read_to_string(&typed).unwrap(), "This is a secret");
+
+    run(&typed, "\n").failure().stderr(predicate::str::contains("the passphrase can't be empty"));
+    assert_eq!(fs::read_to_string(&typed).unwrap(), "This is a secret");
+}
+
+
+#[cfg(feature = "keyring")]
+#[test]
+fn test_key_stored_in_keyring_encrypts_like_the_key() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // A stand-in `secret-tool` first on PATH, keeping secrets in files next to it. Like the real
+    // one, a missing secret fails silently, and the `locked` service fails with a reason
+    let dir = tempfile::tempdir().unwrap();
+    let fake = dir.path().join("secret-tool");
+    fs::write(
+        &fake,
+        "#!/bin/sh\nstore=\"$(dirname \"$0\")/secret\"\ncase \"$1 $3\" in\n  \"lookup locked\") echo 'Cannot unlock the collection' >&2; exit 1 ;;\n  store*) cat > \"$store.$5.$7\" ;;\n  lookup*) cat \"$store.$3.$5\" 2>/dev/null ;;\nesac\n",
+    )
+    .unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.path().display(), std::env::var("PATH").unwrap());
+
+    let (stored, direct) = (dir.path().join("stored.txt"), dir.path().join("direct.txt"));
+    fs::write(&stored, "This is a secret").unwrap();
+    fs::write(&direct, "This is a secret").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("PATH", &path)
+        .args(["--store-key", "rcli/test", "--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(dir.path().join("secret.rcli.test")).unwrap(), "4b8e298780");
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("PATH", &path)
+        .args(["--file", stored.to_str().unwrap(), "--key-from-keyring", "rcli/test"])
+        .assert()
+        .success();
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", direct.to_str().unwrap(), "--key", "0x4b", "0x8e", "0x29", "0x87", "0x80"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(&stored).unwrap(), fs::read(&direct).unwrap());
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("PATH", &path)
+        .args(["--file", stored.to_str().unwrap(), "--key-from-keyring", "rcli/missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no key in the keyring for 'rcli/missing'"));
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .env("PATH", &path)
+        .args(["--file", stored.to_str().unwrap(), "--key-from-keyring", "locked/test"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("secret-tool failed to look up the key: Cannot unlock the collection"));
+}
+
+
+#[test]
+fn test_normalize_key_round_trips_keys_of_any_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret.txt");
+    fs::write(&path, "This is a secret").unwrap();
+
+    let short = ["0x4b", "0x8e", "0x29"];
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap(), "--key"])
+        .args(short)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("keys must be 5 to 256 bytes long (or use --normalize-key)"));
+
+    let long: Vec<String> = (0..300).map(|n| format!("0x{:02x}", n % 256)).collect();
+    for key in [short.iter().map(|s| s.to_string()).collect::<Vec<_>>(), long] {
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--normalize-key", "--key"])
+            .args(&key)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Encrypted"));
+        let encrypted = fs::read(&path).unwrap();
+        assert!(encrypted.starts_with(b"RC4\x01") && !encrypted.ends_with(b"This is a secret"));
+
+        Command::cargo_bin("rcli")
+            .unwrap()
+            .args(["--file", path.to_str().unwrap(), "--normalize-key", "--key"])
+            .args(&key)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Decrypted"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "This is a secret");
+    }
+}
+
+
+#[test]
+fn test_invalid_hex_key_is_an_error_not_a_panic() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret.txt");
+    fs::write(&path, "This is a secret").unwrap();
+
+    Command::cargo_bin("rcli")
+        .unwrap()
+        .args(["--file", path.to_str().unwrap(), "--key", "0x4b", "0x8z", "0x29", "0x87", "0x80"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'0x8z': invalid hex digit at byte 3 of the key"))
+        .stderr(predicate::str::contains("panicked").not());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "This is a secret");
+}